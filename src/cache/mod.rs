@@ -0,0 +1,367 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    log,
+    model::{Id, WikiPage, WikiTree},
+    parse::INPUT_EXT,
+    render::{self, OUTPUT_EXT, SALT_LEN},
+};
+
+#[cfg(test)]
+mod test;
+
+/// File in the output directory where what was last built to it is recorded.
+/// It's hidden, so should be left out when the site is published.
+pub const CACHE_FILE: &str = ".mdwiki-cache";
+
+/// First line of the cache file, changed whenever its format does.
+const HEADER: &str = "mdwiki-cache 2";
+
+/// Incremental hash of the parts of something, separated so that moving
+/// text between parts changes it. Each begins with a secret, so that hashes
+/// of protected pages can't be used to check guesses of their contents.
+struct Hash(Sha256);
+
+impl Hash {
+    fn new(secret: &[u8]) -> Self {
+        let mut hash = Self(Sha256::new());
+        hash.add(secret);
+        hash
+    }
+
+    fn add(&mut self, part: impl AsRef<[u8]>) -> &mut Self {
+        self.0.update(part.as_ref());
+        self.0.update([0]);
+        self
+    }
+
+    fn finish(self) -> String {
+        self.0.finalize()[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Keys derived from the current and previous tag keys with a salt, sorted by
+/// tag, from which hashes of the site are made.
+fn secret(config: &Config, salt: &[u8]) -> Vec<u8> {
+    let mut keys: Vec<(String, &str)> = config
+        .tag_keys
+        .iter()
+        .map(|(tag, key)| (format!("{tag:?}"), key.as_str()))
+        .chain(config.previous_tag_keys.iter().flat_map(|(tag, keys)| {
+            let tag = format!("{tag:?} previous");
+            keys.iter().map(move |key| (tag.clone(), key.as_str()))
+        }))
+        .collect();
+    keys.sort();
+    let passwords: Vec<&str> = keys.iter().map(|(_, key)| *key).collect();
+    render::derive_keys(&passwords, salt, config.kdf_iterations)
+}
+
+/// Hash of the settings which affect every page of the site, including the
+/// tag keys, through the secret derived from them, and the version of mdwiki
+/// rendering it.
+fn config_hash(config: &Config, secret: &[u8]) -> String {
+    let mut hash = Hash::new(secret);
+    hash.add(env!("CARGO_PKG_VERSION")).add(format!(
+        "{} {} {} {} {} {} {} {} {:?} {:?} {:?} {:?} {:?} {:?} {}",
+        config.empty_links,
+        config.generate_indexes,
+        config.nav_tree,
+        config.search,
+        config.page_heading,
+        config.add_breadcrumbs,
+        config.drafts,
+        config.toc_min_headings,
+        config.site_title,
+        config.base_url,
+        config.theme,
+        config.encrypted_tags,
+        config.tag_match,
+        config.tag_unlocks,
+        config.kdf_iterations,
+    ));
+    let tags: Vec<String> = config
+        .tag_keys
+        .keys()
+        .map(|tag| format!("{tag:?}"))
+        .chain(
+            config
+                .previous_tag_keys
+                .iter()
+                .map(|(tag, keys)| format!("{tag:?} previous {}", keys.len())),
+        )
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    hash.add(tags.join(" "));
+    hash.finish()
+}
+
+/// Markdown file each doc and index page was parsed from, before protected
+/// pages are renamed.
+pub fn source_files(src: &Path, tree: &WikiTree) -> HashMap<PathBuf, Id> {
+    let mut files = HashMap::new();
+    for page in tree.pages().iter().filter(|p| p.is_doc() || p.is_index()) {
+        let mut path = src.to_path_buf();
+        path.extend(page.url().split('/').filter(|s| !s.is_empty()));
+        let name = page.filename().strip_suffix(&format!(".{OUTPUT_EXT}"));
+        if let Some(name) = name {
+            path.set_file_name(format!("{name}.{INPUT_EXT}"));
+            files.insert(path, page.id());
+        }
+    }
+    files
+}
+
+/// How a page appears in the nav tree, breadcrumbs and indexes of others.
+fn entry(page: &WikiPage) -> String {
+    let kind = if page.is_generated() {
+        "generated"
+    } else if page.is_index() {
+        "index"
+    } else if page.is_dir() {
+        "dir"
+    } else {
+        "doc"
+    };
+    format!(
+        "{kind} {} {} {} {:?} {:?}",
+        page.url(),
+        page.title(),
+        page.order(),
+        page.tags(),
+        page.tag_groups()
+    )
+}
+
+/// Keys of the output of the pages of a tree, see `Keys::key`. What every
+/// page's key includes is hashed once, rather than for each page.
+pub struct Keys<'a> {
+    config: &'a Config,
+    secret: &'a [u8],
+    tree: &'a WikiTree,
+    /// Hash of the entry of every page, if every page lists every other in
+    /// its nav tree.
+    nav_tree: Option<String>,
+}
+
+impl<'a> Keys<'a> {
+    fn new(config: &'a Config, secret: &'a [u8], tree: &'a WikiTree) -> Self {
+        let nav_tree = config.nav_tree.then(|| {
+            let mut hash = Hash::new(secret);
+            for page in tree.pages() {
+                hash.add(entry(page));
+            }
+            hash.finish()
+        });
+        Self {
+            config,
+            secret,
+            tree,
+            nav_tree,
+        }
+    }
+
+    /// Key of the output of a doc, index or media page, which changes
+    /// whenever anything it's rendered from does: its source, the pages it
+    /// lists and links to, and those linking to it. `source` is the file a doc
+    /// or index page was parsed from. None if the source can't be read.
+    pub fn key(&self, page: &WikiPage, source: Option<&Path>) -> Option<String> {
+        let (config, tree) = (self.config, self.tree);
+        let mut hash = Hash::new(self.secret);
+        hash.add(entry(page));
+
+        if page.is_media() {
+            hash.add(std::fs::read(page.file()?).ok()?);
+            return Some(hash.finish());
+        }
+        match source {
+            Some(source) => hash.add(std::fs::read(source).ok()?),
+            None => hash.add(format!("{:?}", page.document()?)),
+        };
+
+        // Pages around it appear in its breadcrumbs, and its own nav tree, or
+        // every page when the nav tree is shown.
+        if let Some(nav_tree) = &self.nav_tree {
+            hash.add(nav_tree);
+        } else {
+            let mut parent = tree.get_parent(page);
+            while let Some(dir) = parent {
+                hash.add(entry(dir));
+                for page in tree.children(dir.id()) {
+                    hash.add(entry(page));
+                }
+                parent = tree.get_parent(dir);
+            }
+        }
+
+        for link in render::link_resolutions(config, tree, page) {
+            hash.add(link);
+        }
+        for link in tree.backlinks(page.id()) {
+            let from = tree.get(link.from)?;
+            hash.add(format!(
+                "{} {} {} {:?}",
+                from.url(),
+                from.title(),
+                link.context,
+                link.access
+            ));
+        }
+        Some(hash.finish())
+    }
+}
+
+/// Path in outdir a URL of the site is written to.
+pub fn output_path(outdir: &Path, url: &str) -> PathBuf {
+    let mut path = outdir.to_path_buf();
+    path.extend(url.split('/').filter(|s| !s.is_empty()));
+    path
+}
+
+/// What was last built to an output directory: the settings it was built
+/// with, the salt its keys were derived with and the key of each file written,
+/// by URL.
+#[derive(Debug, PartialEq)]
+pub struct Cache {
+    outdir: PathBuf,
+    config: String,
+    salt: [u8; SALT_LEN],
+    /// Derived from the tag keys, so never written to the cache.
+    secret: Vec<u8>,
+    files: BTreeMap<String, String>,
+}
+
+impl Cache {
+    fn new(outdir: &Path, config: &Config) -> Self {
+        let secret = secret(config, &config.kdf_salt);
+        Self {
+            outdir: outdir.to_path_buf(),
+            config: config_hash(config, &secret),
+            salt: config.kdf_salt,
+            secret,
+            files: BTreeMap::new(),
+        }
+    }
+
+    fn parse(outdir: &Path, text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let config = lines.next()?.strip_prefix("config ")?;
+        let salt = lines.next()?.strip_prefix("salt ")?;
+
+        let mut files = BTreeMap::new();
+        for line in lines {
+            let (key, url) = line.split_once(' ')?;
+            files.insert(url.to_string(), key.to_string());
+        }
+        Some(Self {
+            outdir: outdir.to_path_buf(),
+            config: config.to_string(),
+            salt: render::b64_decode(salt)?.try_into().ok()?,
+            secret: Vec::new(),
+            files,
+        })
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "{HEADER}\nconfig {}\nsalt {}\n",
+            self.config,
+            render::b64_encode(&self.salt)
+        );
+        for (url, key) in &self.files {
+            let _ = writeln!(text, "{key} {url}");
+        }
+        text
+    }
+
+    /// The cache of the last build to outdir. Its files are only reused if
+    /// they were built with the same settings and keys, in which case keys are
+    /// derived with the same salt so that pages which are reused can be
+    /// decrypted with those which are rendered again.
+    pub fn load(outdir: &Path, config: &mut Config) -> Self {
+        let path = outdir.join(CACHE_FILE);
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            log::debug(format!("No build cache at {}", path.display()));
+            return Self::new(outdir, config);
+        };
+        let Some(mut cache) = Self::parse(outdir, &text) else {
+            log::warning(format!("Ignoring invalid cache {}", path.display()));
+            return Self::new(outdir, config);
+        };
+
+        cache.secret = secret(config, &cache.salt);
+        if cache.config == config_hash(config, &cache.secret) {
+            config.kdf_salt = cache.salt;
+            return cache;
+        }
+
+        log::info("Settings or keys changed, building everything.");
+        // Files are still removed if they aren't written again.
+        let mut files = cache.files;
+        files.values_mut().for_each(String::clear);
+        Self {
+            files,
+            ..Self::new(outdir, config)
+        }
+    }
+
+    pub fn save(&self) {
+        let path = self.outdir.join(CACHE_FILE);
+        if let Err(e) = std::fs::write(&path, self.to_text()) {
+            log::warning(format!("Failed to write cache ({}): {e}", path.display()));
+        }
+    }
+
+    /// Keys of the output of the pages of a tree, made with the secret of
+    /// this cache.
+    pub fn keys<'a>(&'a self, config: &'a Config, tree: &'a WikiTree) -> Keys<'a> {
+        Keys::new(config, &self.secret, tree)
+    }
+
+    /// Whether the file at a URL was last written from the same key, and is
+    /// still there.
+    pub fn is_fresh(&self, url: &str, key: &str) -> bool {
+        self.files.get(url).is_some_and(|k| k == key) && output_path(&self.outdir, url).is_file()
+    }
+
+    /// Record the key a file was written from.
+    pub fn insert(&mut self, url: &str, key: String) {
+        self.files.insert(url.to_string(), key);
+    }
+
+    /// Replace the files of the last build with those just written, removing
+    /// any left over from it along with directories left empty.
+    pub fn replace(&mut self, files: BTreeMap<String, String>) {
+        for url in self.files.keys().filter(|url| !files.contains_key(*url)) {
+            let path = output_path(&self.outdir, url);
+            match std::fs::remove_file(&path) {
+                Ok(()) => log::info(format!("Removed {}", path.display())),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => log::warning(format!("Failed to remove {}: {e}", path.display())),
+            }
+
+            let mut dir = path.parent();
+            while let Some(d) = dir.filter(|d| *d != self.outdir) {
+                if std::fs::remove_dir(d).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+        self.files = files;
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    model::{Id, WikiTree},
+    parse::parse_document,
+    render::link_pages,
+};
+
+use super::{Cache, Keys, config_hash, secret};
+
+#[test]
+fn test_cache_text() {
+    let cache = Cache {
+        outdir: PathBuf::from("/tmp/wiki html"),
+        config: String::from("0123abcd"),
+        salt: *b"saltsaltsaltsalt",
+        secret: Vec::new(),
+        files: BTreeMap::from([
+            (String::from("/a b.html"), String::from("ff00")),
+            (String::from("/search-index.js"), String::new()),
+        ]),
+    };
+    let text = cache.to_text();
+    assert_eq!(
+        text,
+        "mdwiki-cache 2\nconfig 0123abcd\nsalt c2FsdHNhbHRzYWx0c2FsdA==\n\
+        ff00 /a b.html\n /search-index.js\n"
+    );
+    let outdir = Path::new("/tmp/wiki html");
+    assert_eq!(Cache::parse(outdir, &text), Some(cache));
+
+    assert_eq!(Cache::parse(outdir, "mdwiki-cache 1\nconfig 00\n"), None);
+    assert_eq!(
+        Cache::parse(outdir, "mdwiki-cache 2\nconfig 00\nsalt c2FsdA==\n"),
+        None
+    );
+}
+
+#[test]
+fn test_cache_secret() {
+    let config = Config {
+        tag_keys: HashMap::from([("dm".into(), String::from("password"))]),
+        ..Config::none()
+    };
+    let salt = *b"saltsaltsaltsalt";
+    let secret = secret(&config, &salt);
+    assert_eq!(secret.len(), 32);
+    assert_ne!(secret, super::secret(&config, b"pepperpepperpepp"));
+
+    // The key only appears in the cache through the secret derived from it.
+    let changed = Config {
+        tag_keys: HashMap::from([("dm".into(), String::from("passwore"))]),
+        ..Config::none()
+    };
+    let changed_secret = super::secret(&changed, &salt);
+    assert_ne!(
+        config_hash(&config, &secret),
+        config_hash(&changed, &changed_secret)
+    );
+    assert_eq!(config_hash(&config, &[]), config_hash(&changed, &[]));
+}
+
+fn key(config: &Config, tree: &WikiTree, id: Id) -> String {
+    Keys::new(config, &[], tree)
+        .key(tree.get(id).unwrap(), None)
+        .unwrap()
+}
+
+fn tree(a: &str, b: &str, c: &str) -> WikiTree {
+    let config = Config::none();
+    let mut tree = WikiTree::new();
+    tree.add_doc(WikiTree::ROOT, "a.html", "A", parse_document(a));
+    tree.add_doc(WikiTree::ROOT, "b.html", "B", parse_document(b));
+    let dir = tree.add_dir(WikiTree::ROOT, "places");
+    tree.add_doc(dir, "c.html", "C", parse_document(c));
+    link_pages(&config, &mut tree);
+    tree
+}
+
+#[test]
+fn test_output_key() {
+    let config = Config::none();
+    let base = tree("# A\n\n[[B]] is near.", "# B", "# C");
+    let (a, b, c) = (1, 2, 4);
+    assert_eq!(key(&config, &base, a), key(&config, &base, a));
+
+    // A page changes with its content, and those it links to with its links.
+    let changed = tree("# A\n\n[[B]] is far.", "# B", "# C");
+    assert_ne!(key(&config, &base, a), key(&config, &changed, a));
+    assert_ne!(key(&config, &base, b), key(&config, &changed, b));
+    assert_eq!(key(&config, &base, c), key(&config, &changed, c));
+
+    // Links change with the sections they lead to.
+    let base = tree("# A\n\n[[B#Docks]]", "# B", "# C");
+    let changed = tree("# A\n\n[[B#Docks]]", "# B\n\n## Docks", "# C");
+    assert_ne!(key(&config, &base, a), key(&config, &changed, a));
+
+    // Without a nav tree, pages only list those around them.
+    let base = tree("# A", "# B", "# C");
+    let changed = tree("# A", "# B", "# C\n\nText");
+    assert_eq!(key(&config, &base, b), key(&config, &changed, b));
+
+    // With one, every page lists every other.
+    let config = Config {
+        nav_tree: true,
+        ..Config::none()
+    };
+    let mut renamed = tree("# A", "# B", "# C");
+    renamed.set_document(c, String::from("Sea"), parse_document("# Sea"));
+    assert_ne!(key(&config, &base, b), key(&config, &renamed, b));
+}
@@ -0,0 +1,277 @@
+use std::path::PathBuf;
+
+use crate::{config::Config, log::Level};
+
+#[cfg(test)]
+mod test;
+
+pub const USAGE: &str = "\
+Usage: mdwiki <command> [options] [path]
+
+Commands:
+  build <src>       Render the wiki in <src> to HTML (the default command)
+  check <src>       Check the links and images of the wiki, and report orphan
+                    pages and duplicate titles, failing if any problems,
+                    warnings or errors are reported
+  serve <src>       Serve the rendered wiki over HTTP, rebuilding it and
+                    reloading pages when files change
+  new <dir>         Create a new wiki in <dir>
+  decrypt <site>    Copy the built wiki in <site> with all content the tag
+                    keys can read decrypted
+  verify-keys <site>
+                    Check that all encrypted content in the built wiki in
+                    <site> decrypts with the tag keys
+  help              Print this message
+
+Options:
+  -o, --output <dir>        Output directory for build (default: <src>-html)
+                            or decrypt (default: <site>-decrypted)
+  -w, --watch               Keep building as files change, re-rendering only
+                            the pages they affect
+  -p, --port <port>         Port for serve (default: 8000)
+  -c, --config <file>       Config file (default: <src>/mdwiki.toml)
+  -s, --secrets <file>      File of tag keys, outside the wiki directory
+      --site-title <title>  Name of the wiki, added to page titles
+      --base-url <url>      Prefix for links, if not served from the root
+      --no-empty-links      Don't resolve links with empty URLs by title
+      --no-indexes          Don't generate indexes for directories without one
+      --no-nav-tree         Don't add a tree of all pages to each page
+      --no-search           Don't add a search index and box to each page
+      --page-heading        Add a heading with the page title to each page
+      --no-breadcrumbs      Don't add links to parent directories to each page
+      --drafts              Render pages marked as drafts
+      --kdf-iterations <n>  Iterations used to derive encryption keys
+                            (default: 600000)
+      --toc-min-headings <n>
+                            Headings needed for an automatic table of contents,
+                            0 to only add one where marked (default: 4)
+  -v, --verbose             Print debug messages
+  -q, --quiet               Only print warnings and errors
+  -h, --help                Print this message
+  -V, --version             Print the version";
+
+const DEFAULT_PORT: u16 = 8000;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Build {
+        src: PathBuf,
+        out: Option<PathBuf>,
+        watch: bool,
+    },
+    Check {
+        src: PathBuf,
+    },
+    Serve {
+        src: PathBuf,
+        port: u16,
+    },
+    New {
+        dir: PathBuf,
+    },
+    Decrypt {
+        site: PathBuf,
+        out: Option<PathBuf>,
+    },
+    VerifyKeys {
+        site: PathBuf,
+    },
+    Help,
+    Version,
+}
+
+/// Settings from the command line which take precedence over the defaults
+/// and config file. `None` leaves the corresponding `Config` field unchanged.
+#[derive(Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub empty_links: Option<bool>,
+    pub generate_indexes: Option<bool>,
+    pub nav_tree: Option<bool>,
+    pub search: Option<bool>,
+    pub page_heading: Option<bool>,
+    pub add_breadcrumbs: Option<bool>,
+    pub drafts: Option<bool>,
+    pub toc_min_headings: Option<usize>,
+    pub kdf_iterations: Option<u32>,
+    pub site_title: Option<String>,
+    pub base_url: Option<String>,
+}
+
+impl Overrides {
+    pub fn apply(&self, config: &mut Config) {
+        fn set<T: Copy>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        set(&mut config.empty_links, self.empty_links);
+        set(&mut config.generate_indexes, self.generate_indexes);
+        set(&mut config.nav_tree, self.nav_tree);
+        set(&mut config.search, self.search);
+        set(&mut config.page_heading, self.page_heading);
+        set(&mut config.add_breadcrumbs, self.add_breadcrumbs);
+        set(&mut config.drafts, self.drafts);
+        set(&mut config.toc_min_headings, self.toc_min_headings);
+        set(&mut config.kdf_iterations, self.kdf_iterations);
+
+        if let Some(site_title) = &self.site_title {
+            config.site_title = Some(site_title.clone());
+        }
+        if let Some(base_url) = &self.base_url {
+            config.base_url = base_url.trim_end_matches('/').to_string();
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub config: Option<PathBuf>,
+    pub secrets: Option<PathBuf>,
+    pub overrides: Overrides,
+    pub log_level: Level,
+}
+
+impl Args {
+    /// A command with no options.
+    fn only(command: Command) -> Self {
+        Self {
+            command,
+            config: None,
+            secrets: None,
+            overrides: Overrides::default(),
+            log_level: Level::Info,
+        }
+    }
+}
+
+/// Split `--option=value` into the option and its value.
+fn split_option(arg: &str) -> (&str, Option<&str>) {
+    match arg.split_once('=') {
+        Some((option, value)) if option.starts_with("--") => (option, Some(value)),
+        _ => (arg, None),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {option}: {value}"))
+}
+
+/// Parse command line arguments, excluding the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut overrides = Overrides::default();
+    let mut log_level = Level::Info;
+    let mut output = None;
+    let mut watch = false;
+    let mut port = None;
+    let mut config = None;
+    let mut secrets = None;
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (option, inline_value) = split_option(&arg);
+        let mut value = || {
+            inline_value
+                .map(String::from)
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("Missing value for {option}"))
+        };
+
+        match option {
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "-w" | "--watch" => watch = true,
+            "-p" | "--port" => port = Some(parse_number(option, &value()?)?),
+            "-c" | "--config" => config = Some(PathBuf::from(value()?)),
+            "-s" | "--secrets" => secrets = Some(PathBuf::from(value()?)),
+            "--site-title" => overrides.site_title = Some(value()?),
+            "--base-url" => overrides.base_url = Some(value()?),
+            "--no-empty-links" => overrides.empty_links = Some(false),
+            "--no-indexes" => overrides.generate_indexes = Some(false),
+            "--no-nav-tree" => overrides.nav_tree = Some(false),
+            "--no-search" => overrides.search = Some(false),
+            "--page-heading" => overrides.page_heading = Some(true),
+            "--no-breadcrumbs" => overrides.add_breadcrumbs = Some(false),
+            "--drafts" => overrides.drafts = Some(true),
+            "--toc-min-headings" => {
+                overrides.toc_min_headings = Some(parse_number(option, &value()?)?)
+            }
+            "--kdf-iterations" => match parse_number(option, &value()?)? {
+                0 => return Err(String::from("--kdf-iterations must be positive")),
+                n => overrides.kdf_iterations = Some(n),
+            },
+            "-v" | "--verbose" => log_level = Level::Debug,
+            "-q" | "--quiet" => log_level = Level::Warning,
+            // Anything else given with these is ignored, even if invalid.
+            "-h" | "--help" => return Ok(Args::only(Command::Help)),
+            "-V" | "--version" => return Ok(Args::only(Command::Version)),
+            _ if option.starts_with('-') && option != "-" => {
+                return Err(format!("Unknown option: {option}"));
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let name = positional.next();
+    let path = positional.next();
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument: {extra}"));
+    }
+
+    let src = |path: Option<String>| PathBuf::from(path.unwrap_or_else(|| String::from(".")));
+    let command = match name.as_deref() {
+        Some("help") => Command::Help,
+        Some("version") => Command::Version,
+        Some("build") => Command::Build {
+            src: src(path),
+            out: output.take(),
+            watch: std::mem::take(&mut watch),
+        },
+        Some("check") => Command::Check { src: src(path) },
+        Some("serve") => Command::Serve {
+            src: src(path),
+            port: port.take().unwrap_or(DEFAULT_PORT),
+        },
+        Some("decrypt") => Command::Decrypt {
+            site: src(path),
+            out: output.take(),
+        },
+        Some("verify-keys") => Command::VerifyKeys { site: src(path) },
+        Some("new") => match path {
+            Some(dir) => Command::New {
+                dir: PathBuf::from(dir),
+            },
+            None => return Err(String::from("Missing directory for new wiki")),
+        },
+        // A lone path builds it, as before subcommands existed.
+        Some(_) if path.is_none() => Command::Build {
+            src: src(name),
+            out: output.take(),
+            watch: std::mem::take(&mut watch),
+        },
+        Some(name) => return Err(format!("Unknown command: {name}")),
+        None => return Err(String::from("Missing command")),
+    };
+
+    if output.is_some() {
+        return Err(String::from("--output is only valid for build and decrypt"));
+    }
+    if watch {
+        return Err(String::from("--watch is only valid for build"));
+    }
+    if port.is_some() {
+        return Err(String::from("--port is only valid for serve"));
+    }
+
+    Ok(Args {
+        command,
+        config,
+        secrets,
+        overrides,
+        log_level,
+    })
+}
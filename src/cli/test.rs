@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use crate::{config::Config, log::Level};
+
+use super::{Args, Command, Overrides, parse_args};
+
+fn parse(args: &[&str]) -> Result<Args, String> {
+    parse_args(args.iter().map(|s| s.to_string()))
+}
+
+#[test]
+fn test_parse_build() {
+    assert_eq!(
+        parse(&["build", "wiki", "-o", "site"]).unwrap().command,
+        Command::Build {
+            src: PathBuf::from("wiki"),
+            out: Some(PathBuf::from("site")),
+            watch: false
+        }
+    );
+    assert_eq!(
+        parse(&["--output=site", "build", "--watch"])
+            .unwrap()
+            .command,
+        Command::Build {
+            src: PathBuf::from("."),
+            out: Some(PathBuf::from("site")),
+            watch: true
+        }
+    );
+}
+
+#[test]
+fn test_parse_bare_path() {
+    assert_eq!(
+        parse(&["wiki"]).unwrap().command,
+        Command::Build {
+            src: PathBuf::from("wiki"),
+            out: None,
+            watch: false
+        }
+    );
+}
+
+#[test]
+fn test_parse_commands() {
+    assert_eq!(
+        parse(&["check", "wiki"]).unwrap().command,
+        Command::Check {
+            src: PathBuf::from("wiki")
+        }
+    );
+    assert_eq!(
+        parse(&["serve", "wiki", "--port", "8080"]).unwrap().command,
+        Command::Serve {
+            src: PathBuf::from("wiki"),
+            port: 8080
+        }
+    );
+    assert_eq!(
+        parse(&["new", "wiki"]).unwrap().command,
+        Command::New {
+            dir: PathBuf::from("wiki")
+        }
+    );
+    assert_eq!(
+        parse(&["decrypt", "site", "-o", "print"]).unwrap().command,
+        Command::Decrypt {
+            site: PathBuf::from("site"),
+            out: Some(PathBuf::from("print"))
+        }
+    );
+    assert_eq!(
+        parse(&["verify-keys", "site"]).unwrap().command,
+        Command::VerifyKeys {
+            site: PathBuf::from("site")
+        }
+    );
+    assert_eq!(parse(&["build", "--help"]).unwrap().command, Command::Help);
+    assert_eq!(
+        parse(&["build", "/tmp/w", "--help"]).unwrap().command,
+        Command::Help
+    );
+    assert_eq!(
+        parse(&["check", "wiki", "extra", "-V", "--bad"])
+            .unwrap()
+            .command,
+        Command::Version
+    );
+}
+
+#[test]
+fn test_parse_options() {
+    let args = parse(&[
+        "build",
+        "--no-nav-tree",
+        "--no-search",
+        "--page-heading",
+        "--toc-min-headings",
+        "2",
+        "-q",
+    ])
+    .unwrap();
+    assert_eq!(args.log_level, Level::Warning);
+    assert_eq!(
+        args.overrides,
+        Overrides {
+            nav_tree: Some(false),
+            search: Some(false),
+            page_heading: Some(true),
+            toc_min_headings: Some(2),
+            ..Overrides::default()
+        }
+    );
+
+    let mut config = Config::none();
+    config.nav_tree = true;
+    config.search = true;
+    args.overrides.apply(&mut config);
+    assert!(!config.nav_tree);
+    assert!(!config.search);
+    assert!(config.page_heading);
+    assert!(!config.add_breadcrumbs);
+    assert_eq!(config.toc_min_headings, 2);
+}
+
+#[test]
+fn test_parse_config_options() {
+    let args = parse(&["serve", "--config", "site.toml", "--base-url=/wiki/"]).unwrap();
+    assert_eq!(args.config, Some(PathBuf::from("site.toml")));
+
+    let mut config = Config::none();
+    args.overrides.apply(&mut config);
+    assert_eq!(config.base_url, "/wiki");
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(parse(&[]).unwrap_err(), "Missing command");
+    assert_eq!(
+        parse(&["bulid", "wiki"]).unwrap_err(),
+        "Unknown command: bulid"
+    );
+    assert_eq!(
+        parse(&["build", "--nav"]).unwrap_err(),
+        "Unknown option: --nav"
+    );
+    assert_eq!(parse(&["build", "-o"]).unwrap_err(), "Missing value for -o");
+    assert_eq!(
+        parse(&["serve", "-p", "http"]).unwrap_err(),
+        "Invalid value for -p: http"
+    );
+    assert_eq!(
+        parse(&["check", "-o", "site"]).unwrap_err(),
+        "--output is only valid for build and decrypt"
+    );
+    assert_eq!(
+        parse(&["serve", "-w"]).unwrap_err(),
+        "--watch is only valid for build"
+    );
+    assert_eq!(
+        parse(&["new"]).unwrap_err(),
+        "Missing directory for new wiki"
+    );
+}
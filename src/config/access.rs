@@ -0,0 +1,94 @@
+use crate::model::Tag;
+
+use super::Config;
+
+/// How the tags of encrypted content combine to decide who can read it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TagMatch {
+    /// Readers need a key for every tag.
+    #[default]
+    All,
+    /// A key for any one of the tags is enough.
+    Any,
+}
+
+impl TagMatch {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "all" => Some(Self::All),
+            "any" => Some(Self::Any),
+            _ => None,
+        }
+    }
+}
+
+/// Tags able to read encrypted content, as groups of which a reader needs a
+/// key from each. Each group reads one share of the content key.
+pub type Access = Vec<Vec<Tag>>;
+
+impl Config {
+    /// Tags with keys which can read content with the given tag: the tag
+    /// itself and any tags which unlock it, directly or through other tags.
+    pub fn readers_of(&self, tag: &Tag) -> Vec<Tag> {
+        let mut readers = vec![tag.clone()];
+        let mut i = 0;
+        while let Some(current) = readers.get(i).cloned() {
+            for (reader, unlocks) in &self.tag_unlocks {
+                if unlocks.contains(&current) && !readers.contains(reader) {
+                    readers.push(reader.clone());
+                }
+            }
+            i += 1;
+        }
+
+        readers.retain(|reader| self.tag_keys.contains_key(reader));
+        readers
+    }
+
+    /// Whether content with the tag is encrypted, because it or a tag which
+    /// unlocks it has a key.
+    pub fn is_protected(&self, tag: &Tag) -> bool {
+        !self.readers_of(tag).is_empty()
+    }
+
+    /// Who can read content with the given tags, or `None` if none of them
+    /// are protected and the content is left as it is.
+    pub fn access(&self, tags: &[Tag]) -> Option<Access> {
+        let groups: Vec<Vec<Tag>> = tags.iter().map(|tag| vec![tag.clone()]).collect();
+        self.group_access(&groups)
+    }
+
+    /// Who can read content with a tag from each of the groups given, as for
+    /// media referenced from content with different tags, or `None` if none of
+    /// them are protected.
+    pub fn group_access(&self, groups: &[Vec<Tag>]) -> Option<Access> {
+        let mut access: Access = Vec::new();
+        for group in groups {
+            let mut readers: Vec<Tag> = Vec::new();
+            for reader in group.iter().flat_map(|tag| self.readers_of(tag)) {
+                if !readers.contains(&reader) {
+                    readers.push(reader);
+                }
+            }
+            if !readers.is_empty() && !access.contains(&readers) {
+                access.push(readers);
+            }
+        }
+        if access.is_empty() {
+            return None;
+        }
+
+        Some(match self.tag_match {
+            TagMatch::All => access,
+            TagMatch::Any => {
+                let mut readers: Vec<Tag> = Vec::new();
+                for tag in access.into_iter().flatten() {
+                    if !readers.contains(&tag) {
+                        readers.push(tag);
+                    }
+                }
+                vec![readers]
+            }
+        })
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufRead, IsTerminal, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    log,
+    model::{Meta, Tag, Value},
+};
+
+use super::Config;
+
+/// Prefix of environment variables holding tag keys, e.g. `MDWIKI_TAG_KEY_DM`
+/// for the tag `dm`.
+pub(super) const KEY_ENV_PREFIX: &str = "MDWIKI_TAG_KEY_";
+
+/// Prefix of environment variables holding the key a tag had before it was
+/// changed, e.g. `MDWIKI_PREVIOUS_TAG_KEY_DM`.
+pub(super) const PREVIOUS_KEY_ENV_PREFIX: &str = "MDWIKI_PREVIOUS_TAG_KEY_";
+
+pub(super) fn key_env_var(tag: &Tag) -> String {
+    format!("{KEY_ENV_PREFIX}{}", tag.as_ref().to_uppercase())
+}
+
+/// Tag keys from the environment variables with the given prefix, with tags in
+/// lower case.
+pub(super) fn keys_from_env<I: IntoIterator<Item = (String, String)>>(
+    prefix: &str,
+    vars: I,
+) -> HashMap<Tag, String> {
+    vars.into_iter()
+        .filter_map(|(name, key)| {
+            let tag = name.strip_prefix(prefix)?.to_lowercase();
+            (!tag.is_empty() && !key.is_empty()).then(|| (tag.as_str().into(), key))
+        })
+        .collect()
+}
+
+/// Keys read from a secrets file.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct Secrets {
+    pub keys: HashMap<Tag, String>,
+    /// Keys tags had before they were changed, from the `[previous]` table.
+    pub previous: HashMap<Tag, Vec<String>>,
+}
+
+/// Keys from the parsed contents of a secrets file: `tag = "key"` entries,
+/// and `tag = "key"` or `tag = ["key", ...]` entries in a `[previous]` table.
+pub(super) fn parse_secrets(values: &Meta) -> Result<Secrets, String> {
+    let mut secrets = Secrets::default();
+    for (name, value) in values.entries() {
+        if let Some(tag) = name.strip_prefix("previous.") {
+            let keys = match value {
+                Value::String(key) => vec![key.clone()],
+                Value::List(keys) => keys
+                    .iter()
+                    .map(|key| key.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| format!("previous keys for `{tag}` must be strings"))?,
+                _ => return Err(format!("previous key for `{tag}` must be a string or list")),
+            };
+            secrets.previous.insert(tag.into(), keys);
+        } else {
+            let key = value
+                .as_str()
+                .ok_or_else(|| format!("key for `{name}` must be a string"))?;
+            secrets.keys.insert(name.into(), key.to_string());
+        }
+    }
+    Ok(secrets)
+}
+
+/// Read tag keys from a TOML secrets file, see `parse_secrets`. The file must
+/// be outside the wiki, so that keys aren't committed alongside the content.
+fn read_secrets(path: &Path, wiki: &Path) -> Result<Secrets, String> {
+    let canonical = |path: &Path| {
+        std::fs::canonicalize(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))
+    };
+    if canonical(path)?.starts_with(canonical(wiki)?) {
+        return Err(format!(
+            "Secrets file {} must be outside the wiki directory.",
+            path.display()
+        ));
+    }
+
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    crate::parse::parse_toml(&text)
+        .and_then(|values| parse_secrets(&values))
+        .map_err(|e| format!("Invalid secrets file {}: {e}", path.display()))
+}
+
+/// Turn terminal echo on or off, so that keys aren't shown as they're typed.
+fn set_echo(on: bool) {
+    let _ = Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Key for a tag typed at the terminal, if running interactively.
+fn prompt_key(tag: &Tag) -> Option<String> {
+    if !std::io::stdin().is_terminal() {
+        return None;
+    }
+    eprint!("Key for tag {}: ", tag.as_ref());
+    let _ = std::io::stderr().flush();
+
+    set_echo(false);
+    let mut line = String::new();
+    let read = std::io::stdin().lock().read_line(&mut line);
+    set_echo(true);
+    eprintln!();
+
+    let key = line.trim_end_matches(['\r', '\n']);
+    (read.is_ok() && !key.is_empty()).then(|| key.to_string())
+}
+
+fn no_key_error(tag: &Tag) -> String {
+    format!(
+        "No key for encrypted tag `{}`. Set {}, or add it to a secrets file given with --secrets.",
+        tag.as_ref(),
+        key_env_var(tag)
+    )
+}
+
+impl Config {
+    /// Collect tag keys from the secrets file, then environment variables,
+    /// with later sources taking precedence. Keys still missing are asked for
+    /// by `require_keys`, once it's known which tags are used.
+    pub fn load_keys(&mut self, secrets: Option<&Path>, wiki: &Path) -> Result<(), String> {
+        if let Some(path) = secrets {
+            log::debug(format!("Loading tag keys from {}", path.display()));
+            let secrets = read_secrets(path, wiki)?;
+            self.tag_keys.extend(secrets.keys);
+            self.previous_tag_keys.extend(secrets.previous);
+        }
+        self.tag_keys
+            .extend(keys_from_env(KEY_ENV_PREFIX, std::env::vars()));
+        let previous = keys_from_env(PREVIOUS_KEY_ENV_PREFIX, std::env::vars());
+        self.previous_tag_keys
+            .extend(previous.into_iter().map(|(tag, key)| (tag, vec![key])));
+        Ok(())
+    }
+
+    /// Encrypted tags amongst those used which have no key, in the order
+    /// they're listed.
+    fn missing_keys(&self, used: &HashSet<Tag>) -> Vec<Tag> {
+        self.encrypted_tags
+            .iter()
+            .filter(|tag| used.contains(*tag) && !self.tag_keys.contains_key(*tag))
+            .cloned()
+            .collect()
+    }
+
+    /// An error if any encrypted tag amongst those used has no key.
+    pub fn check_keys(&self, used: &HashSet<Tag>) -> Result<(), String> {
+        match self.missing_keys(used).first() {
+            Some(tag) => Err(no_key_error(tag)),
+            None => Ok(()),
+        }
+    }
+
+    /// Prompt for the key of each encrypted tag amongst those used which has
+    /// none if running interactively, otherwise an error.
+    pub fn require_keys(&mut self, used: &HashSet<Tag>) -> Result<(), String> {
+        for tag in self.missing_keys(used) {
+            let key = prompt_key(&tag).ok_or_else(|| no_key_error(&tag))?;
+            self.tag_keys.insert(tag, key);
+        }
+        self.drop_previous_keys();
+        Ok(())
+    }
+
+    /// Prompt for the key of each encrypted tag which has none if running
+    /// interactively, for built sites, where which tags are used isn't known.
+    /// Content needing keys still missing is reported when it's decrypted.
+    pub fn prompt_keys(&mut self) {
+        let tags = self.encrypted_tags.iter().cloned().collect();
+        for tag in self.missing_keys(&tags) {
+            if let Some(key) = prompt_key(&tag) {
+                self.tag_keys.insert(tag, key);
+            }
+        }
+        self.drop_previous_keys();
+    }
+
+    /// Only keep previous keys for tags with a current key, which differ
+    /// from it.
+    fn drop_previous_keys(&mut self) {
+        for (tag, previous) in &mut self.previous_tag_keys {
+            match self.tag_keys.get(tag) {
+                Some(key) => previous.retain(|old| old != key),
+                None => log::warning(format!(
+                    "Ignoring previous keys for `{}`, which has no current key.",
+                    tag.as_ref()
+                )),
+            }
+        }
+        let tag_keys = &self.tag_keys;
+        self.previous_tag_keys
+            .retain(|tag, previous| tag_keys.contains_key(tag) && !previous.is_empty());
+    }
+}
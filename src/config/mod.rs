@@ -0,0 +1,248 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    log,
+    model::{Tag, Value},
+    render::{SALT_LEN, random_salt},
+};
+
+mod access;
+mod keys;
+#[cfg(test)]
+mod test;
+
+pub use access::{Access, TagMatch};
+
+/// Name of the configuration file looked for in the root of a wiki.
+pub const CONFIG_FILE: &str = "mdwiki.toml";
+
+pub struct Config {
+    /// Whether to support empty links by attempting to rewrite them. For
+    /// example:
+    /// `[City]()` would be rendered as `<a href="/path/city.html">City</a>`
+    /// if that file exists in the tree. The nearest relative of the page the
+    /// link is in will be used. The title is converted to kebab case to find
+    /// an appropriate file.
+    pub empty_links: bool,
+
+    /// Whether to generate index.html for directories in which it doesn't
+    /// exist. If true a simple index of the directory will be generated.
+    pub generate_indexes: bool,
+
+    /// Whether to include a nav tree of all wiki content in generated pages.
+    /// If true, all pages will have a tree reflecting the directory structure
+    /// on the left hand side, with directories not part of the current files
+    /// ancestry collapsed.
+    pub nav_tree: bool,
+
+    /// Whether to write a search index of the wiki and add a search box to
+    /// each page.
+    pub search: bool,
+
+    /// Whether to add a heading to pages. If true a heading will be added using
+    /// the capitalised filename with extension omitted.
+    pub page_heading: bool,
+
+    /// Whether to add a series of breadcrumbs with links for all ancestors of
+    /// the current directory above the first node.
+    pub add_breadcrumbs: bool,
+
+    /// Whether to render pages marked as drafts in their front matter. If
+    /// false, drafts are skipped entirely.
+    pub drafts: bool,
+
+    /// Minimum number of headings a page must have for a table of contents
+    /// to be added to it automatically. If 0, a table of contents is only
+    /// included where a `[TOC]` or `#toc` marker appears in the page.
+    pub toc_min_headings: usize,
+
+    /// Name of the wiki, appended to the title of each page.
+    pub site_title: Option<String>,
+
+    /// Prefix for absolute URLs, for wikis which aren't served from the root
+    /// of their domain, e.g. `/wiki`. Empty if served from the root.
+    pub base_url: String,
+
+    /// Stylesheet included after the default styles, for example to override
+    /// the colour variables it defines.
+    pub theme: Option<String>,
+
+    /// Tags whose content must be encrypted, `dm` by default. Building fails
+    /// if any of these is used in the wiki but has no key.
+    pub encrypted_tags: Vec<Tag>,
+
+    /// Passwords associated with tags. Including a password here will cause
+    /// all nodes with the given tag to be AES encoded with the given password.
+    /// Keys are never read from the wiki itself, see `load_keys`.
+    pub tag_keys: HashMap<Tag, String>,
+
+    /// Keys tags had before they were last changed. Browsers with one of
+    /// these stored are asked for the new key, rather than failing to decrypt.
+    pub previous_tag_keys: HashMap<Tag, Vec<String>>,
+
+    /// Whether readers of content with several tags need keys for all of
+    /// them, or any one.
+    pub tag_match: TagMatch,
+
+    /// Tags whose keys also read content with other tags, e.g. `dm` unlocking
+    /// `paladin`. A tag with no key of its own is readable by those which
+    /// unlock it.
+    pub tag_unlocks: Vec<(Tag, Vec<Tag>)>,
+
+    /// Number of PBKDF2 iterations used to derive encryption keys from tag
+    /// keys. Higher values make guessing keys slower, but also decrypting.
+    pub kdf_iterations: u32,
+
+    /// Salt used when deriving encryption keys, random for each build.
+    pub kdf_salt: [u8; SALT_LEN],
+}
+
+/// Iterations recommended by OWASP for PBKDF2-HMAC-SHA256.
+const DEFAULT_KDF_ITERATIONS: u32 = 600_000;
+
+impl Config {
+    #[cfg(test)]
+    pub fn none() -> Self {
+        Self {
+            empty_links: false,
+            generate_indexes: false,
+            nav_tree: false,
+            search: false,
+            page_heading: false,
+            add_breadcrumbs: false,
+            drafts: false,
+            toc_min_headings: 0,
+            site_title: None,
+            base_url: String::new(),
+            theme: None,
+            encrypted_tags: Vec::new(),
+            tag_keys: HashMap::new(),
+            previous_tag_keys: HashMap::new(),
+            tag_match: TagMatch::All,
+            tag_unlocks: Vec::new(),
+            kdf_iterations: 1,
+            kdf_salt: [0; SALT_LEN],
+        }
+    }
+
+    /// Update settings from a `mdwiki.toml` file. Unknown keys are reported as
+    /// warnings, while values of the wrong type are errors.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        self.load_str(path, &text)
+            .map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Update settings from the text of the config file at path. Paths in the
+    /// file are relative to its directory.
+    fn load_str(&mut self, path: &Path, text: &str) -> Result<(), String> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let values = crate::parse::parse_toml(text)?;
+        for (key, value) in values.entries() {
+            if !self.set(dir, key, value)? {
+                log::warning(format!("Unknown config key `{key}` in {}", path.display()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the field for key, returning false if there is no such field.
+    fn set(&mut self, dir: &Path, key: &str, value: &Value) -> Result<bool, String> {
+        match key {
+            "empty_links" => self.empty_links = as_bool(key, value)?,
+            "generate_indexes" => self.generate_indexes = as_bool(key, value)?,
+            "nav_tree" => self.nav_tree = as_bool(key, value)?,
+            "search" => self.search = as_bool(key, value)?,
+            "page_heading" => self.page_heading = as_bool(key, value)?,
+            "add_breadcrumbs" => self.add_breadcrumbs = as_bool(key, value)?,
+            "drafts" => self.drafts = as_bool(key, value)?,
+            "toc_min_headings" => {
+                self.toc_min_headings = value
+                    .as_integer()
+                    .and_then(|n| usize::try_from(n).ok())
+                    .ok_or_else(|| format!("`{key}` must be a non-negative integer"))?
+            }
+            "kdf_iterations" => {
+                self.kdf_iterations = value
+                    .as_integer()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("`{key}` must be a positive integer"))?
+            }
+            "tag_match" => {
+                self.tag_match = TagMatch::from_name(as_str(key, value)?)
+                    .ok_or_else(|| format!("`{key}` must be \"all\" or \"any\""))?
+            }
+            "site_title" => self.site_title = Some(as_str(key, value)?.to_string()),
+            "base_url" => self.base_url = as_str(key, value)?.trim_end_matches('/').to_string(),
+            "theme" => {
+                let path = dir.join(as_str(key, value)?);
+                let css = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("failed to read theme {}: {e}", path.display()))?;
+                self.theme = Some(css);
+            }
+            _ => match key.strip_prefix("tags.").and_then(|k| k.rsplit_once('.')) {
+                Some((tag, "encrypted")) => {
+                    let tag = Tag::from(tag);
+                    let encrypted = as_bool(key, value)?;
+                    self.encrypted_tags.retain(|t| *t != tag);
+                    if encrypted {
+                        self.encrypted_tags.push(tag);
+                    }
+                }
+                Some((tag, "unlocks")) => {
+                    let tag = Tag::from(tag);
+                    let unlocks = value.strings().into_iter().map(Tag::from).collect();
+                    self.tag_unlocks.retain(|(t, _)| *t != tag);
+                    self.tag_unlocks.push((tag, unlocks));
+                }
+                Some((tag, "key")) => {
+                    return Err(format!(
+                        "`{key}`: tag keys can't be stored in the wiki. Set {}, or use a secrets file given with --secrets.",
+                        keys::key_env_var(&tag.into())
+                    ));
+                }
+                _ => return Ok(false),
+            },
+        }
+        Ok(true)
+    }
+}
+
+fn as_bool(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("`{key}` must be true or false"))
+}
+
+fn as_str<'a>(key: &str, value: &'a Value) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("`{key}` must be a string"))
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            empty_links: true,
+            generate_indexes: true,
+            nav_tree: true,
+            search: true,
+            page_heading: false,
+            add_breadcrumbs: true,
+            drafts: false,
+            toc_min_headings: 4,
+            site_title: None,
+            base_url: String::new(),
+            theme: None,
+            encrypted_tags: vec!["dm".into()],
+            tag_keys: HashMap::new(),
+            previous_tag_keys: HashMap::new(),
+            tag_match: TagMatch::All,
+            tag_unlocks: Vec::new(),
+            kdf_iterations: DEFAULT_KDF_ITERATIONS,
+            kdf_salt: random_salt(),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use super::{
+    Config, TagMatch,
+    keys::{KEY_ENV_PREFIX, PREVIOUS_KEY_ENV_PREFIX, Secrets, keys_from_env, parse_secrets},
+};
+use crate::parse::parse_toml;
+
+fn load(text: &str) -> Result<Config, String> {
+    let mut config = Config::none();
+    config
+        .load_str(Path::new("wiki/mdwiki.toml"), text)
+        .map(|_| config)
+}
+
+#[test]
+fn test_load_config() {
+    let config = load(concat!(
+        "site_title = \"The Realm\"\n",
+        "base_url = \"/realm/\"\n",
+        "nav_tree = true\n",
+        "add_breadcrumbs = true\n",
+        "toc_min_headings = 3\n",
+        "\n",
+        "[tags.dm]\n",
+        "encrypted = true\n",
+    ))
+    .unwrap();
+
+    assert_eq!(config.site_title.as_deref(), Some("The Realm"));
+    assert_eq!(config.base_url, "/realm");
+    assert!(config.nav_tree);
+    assert!(config.add_breadcrumbs);
+    assert!(!config.page_heading);
+    assert_eq!(config.toc_min_headings, 3);
+    assert_eq!(config.encrypted_tags, vec!["dm".into()]);
+}
+
+#[test]
+fn test_load_config_unknown_key() {
+    let config = load("nav_tre = true\n[tags.dm]\ncolour = \"red\"").unwrap();
+    assert!(!config.nav_tree);
+    assert!(config.encrypted_tags.is_empty());
+}
+
+#[test]
+fn test_dm_encrypted_by_default() {
+    let mut config = Config::default();
+    assert_eq!(config.encrypted_tags, vec!["dm".into()]);
+    config
+        .load_str(
+            Path::new("wiki/mdwiki.toml"),
+            "[tags.dm]\nencrypted = false\n",
+        )
+        .unwrap();
+    assert!(config.encrypted_tags.is_empty());
+}
+
+#[test]
+fn test_check_keys() {
+    let mut config = Config::default();
+    let unused = HashSet::from(["npc".into()]);
+    let used = HashSet::from(["npc".into(), "dm".into()]);
+
+    // Keys are only needed for encrypted tags the wiki uses.
+    assert_eq!(config.check_keys(&unused), Ok(()));
+    assert!(
+        config
+            .check_keys(&used)
+            .unwrap_err()
+            .starts_with("No key for encrypted tag `dm`.")
+    );
+
+    config.tag_keys.insert("dm".into(), String::from("hunter2"));
+    assert_eq!(config.check_keys(&used), Ok(()));
+}
+
+#[test]
+fn test_load_config_invalid() {
+    assert_eq!(
+        load("nav_tree = \"yes\"").err().unwrap(),
+        "`nav_tree` must be true or false"
+    );
+    assert_eq!(
+        load("toc_min_headings = -1").err().unwrap(),
+        "`toc_min_headings` must be a non-negative integer"
+    );
+    assert!(
+        load("[tags.dm]\nkey = \"hunter2\"")
+            .err()
+            .unwrap()
+            .starts_with("`tags.dm.key`: tag keys can't be stored in the wiki")
+    );
+    assert!(
+        load("theme = \"missing.css\"")
+            .err()
+            .unwrap()
+            .starts_with("failed to read theme wiki/missing.css")
+    );
+}
+
+#[test]
+fn test_load_tag_policies() {
+    let mut config = load(concat!(
+        "tag_match = \"any\"\n",
+        "[tags.dm]\n",
+        "unlocks = [\"paladin\", \"party\"]\n",
+        "[tags.paladin]\n",
+        "unlocks = \"party\"\n",
+    ))
+    .unwrap();
+    assert_eq!(config.tag_match, TagMatch::Any);
+    assert_eq!(
+        config.tag_unlocks,
+        vec![
+            ("dm".into(), vec!["paladin".into(), "party".into()]),
+            ("paladin".into(), vec!["party".into()]),
+        ]
+    );
+
+    // Tags without keys of their own are read by those unlocking them.
+    config.tag_keys = HashMap::from([
+        ("dm".into(), String::from("k1")),
+        ("paladin".into(), String::from("k2")),
+    ]);
+    assert_eq!(
+        config.readers_of(&"party".into()),
+        vec!["dm".into(), "paladin".into()]
+    );
+    assert!(config.is_protected(&"party".into()));
+    assert!(!config.is_protected(&"rogue".into()));
+    assert_eq!(config.access(&["rogue".into()]), None);
+
+    assert_eq!(
+        load("tag_match = \"some\"").err().unwrap(),
+        "`tag_match` must be \"all\" or \"any\""
+    );
+}
+
+#[test]
+fn test_keys_from_env() {
+    let vars = [
+        ("MDWIKI_TAG_KEY_DM", "hunter2"),
+        ("MDWIKI_TAG_KEY_PALADIN", ""),
+        ("MDWIKI_TAG_KEY_", "nothing"),
+        ("MDWIKI_PREVIOUS_TAG_KEY_DM", "swordfish"),
+        ("HOME", "/root"),
+    ];
+    let vars = vars.map(|(k, v)| (k.to_string(), v.to_string()));
+    assert_eq!(
+        keys_from_env(KEY_ENV_PREFIX, vars.clone()),
+        HashMap::from([("dm".into(), "hunter2".to_string())])
+    );
+    assert_eq!(
+        keys_from_env(PREVIOUS_KEY_ENV_PREFIX, vars),
+        HashMap::from([("dm".into(), "swordfish".to_string())])
+    );
+}
+
+#[test]
+fn test_parse_secrets() {
+    let secrets = parse_toml(concat!(
+        "dm = \"hunter2\"\n",
+        "paladin = \"oath keeper\"\n",
+        "\n",
+        "[previous]\n",
+        "dm = [\"swordfish\", \"letmein\"]\n",
+        "paladin = \"smite\"\n",
+    ))
+    .and_then(|values| parse_secrets(&values));
+    assert_eq!(
+        secrets,
+        Ok(Secrets {
+            keys: HashMap::from([
+                ("dm".into(), String::from("hunter2")),
+                ("paladin".into(), String::from("oath keeper")),
+            ]),
+            previous: HashMap::from([
+                (
+                    "dm".into(),
+                    vec![String::from("swordfish"), String::from("letmein")]
+                ),
+                ("paladin".into(), vec![String::from("smite")]),
+            ]),
+        })
+    );
+
+    let invalid = parse_toml("dm = 1").and_then(|values| parse_secrets(&values));
+    assert_eq!(invalid, Err(String::from("key for `dm` must be a string")));
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::CACHE_FILE,
+    config::Config,
+    log,
+    render::{
+        KDF_META_NAME, KEY_TEST_CLASS, OUTPUT_EXT, PAGE_TITLE_ID, PREVIOUS_KEY_TEST_CLASS,
+        SECRET_CLASS, SECRET_MEDIA_CLASS, Sealed, b64_decode,
+    },
+};
+
+#[cfg(test)]
+mod test;
+
+/// Salt and iterations used to derive keys when a site was built.
+type KdfParams = (Vec<u8>, u32);
+
+/// An element with encrypted content in a rendered page.
+struct Secret<'a> {
+    /// Byte range of the whole element in the page.
+    range: Range<usize>,
+    class: &'a str,
+    tags: &'a str,
+    sealed: Sealed,
+}
+
+/// Value of an attribute in the opening tag of an element.
+fn attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let (_, rest) = tag.split_once(&format!(" {name}=\""))?;
+    rest.split_once('"').map(|(value, _)| value)
+}
+
+/// Opening tags of the elements with the given name, and their positions.
+fn opening_tags<'a>(html: &'a str, name: &str) -> Vec<(usize, &'a str)> {
+    let start_tag = format!("<{name} ");
+    html.match_indices(&start_tag)
+        .filter_map(|(start, _)| {
+            let end = start + html[start..].find('>')? + 1;
+            Some((start, &html[start..end]))
+        })
+        .collect()
+}
+
+/// Encrypted elements of a page, in order. These are the spans with `keys`
+/// and `nonce` attributes written when rendering.
+fn find_secrets(html: &str) -> Vec<Secret<'_>> {
+    const CLOSE: &str = "</span>";
+
+    let mut secrets = Vec::new();
+    for (start, tag) in opening_tags(html, "span") {
+        let (Some(keys), Some(nonce)) = (attr(tag, "keys"), attr(tag, "nonce")) else {
+            continue;
+        };
+        let content = start + tag.len();
+        let Some(end) = html[content..].find(CLOSE).map(|i| content + i) else {
+            continue;
+        };
+
+        let nonce = b64_decode(nonce).and_then(|nonce| nonce.try_into().ok());
+        let ciphertext = b64_decode(html[content..end].trim());
+        if let (Some(nonce), Some(ciphertext)) = (nonce, ciphertext) {
+            secrets.push(Secret {
+                range: start..(end + CLOSE.len()),
+                class: attr(tag, "class").unwrap_or_default(),
+                tags: attr(tag, "tags").unwrap_or_default(),
+                sealed: Sealed {
+                    nonce,
+                    ciphertext,
+                    keys: keys.to_string(),
+                },
+            });
+        }
+    }
+    secrets
+}
+
+/// Key derivation parameters embedded in a page, if it has any.
+fn kdf_params(html: &str) -> Option<KdfParams> {
+    let (_, meta) = opening_tags(html, "meta")
+        .into_iter()
+        .find(|(_, tag)| attr(tag, "name") == Some(KDF_META_NAME))?;
+    let salt = b64_decode(attr(meta, "data-salt")?)?;
+    let iterations = attr(meta, "data-iterations")?.parse().ok()?;
+    Some((salt, iterations))
+}
+
+/// Readers of the shares of sealed content which there are no keys for.
+fn missing_keys(sealed: &Sealed, keys: &HashMap<&str, &str>) -> Vec<String> {
+    sealed
+        .share_tags()
+        .into_iter()
+        .filter(|readers| !readers.iter().any(|tag| keys.contains_key(tag)))
+        .map(|readers| format!("`{}`", readers.join("` or `")))
+        .collect()
+}
+
+/// Decrypt sealed content, or describe why it can't be.
+fn open(sealed: &Sealed, keys: &HashMap<&str, &str>, kdf: &KdfParams) -> Result<Vec<u8>, String> {
+    let missing = missing_keys(sealed, keys);
+    if !missing.is_empty() {
+        return Err(format!("no key for {}", missing.join(" and ")));
+    }
+
+    sealed
+        .open(keys, &kdf.0, kdf.1)
+        .ok_or_else(|| String::from("the keys given don't decrypt it"))
+}
+
+/// Whether the key given for a tag is one of its previous keys, tested in the
+/// tag keys menu of a page.
+fn is_previous_key(
+    secrets: &[Secret],
+    tags: &str,
+    keys: &HashMap<&str, &str>,
+    kdf: Option<&KdfParams>,
+) -> bool {
+    let Some(kdf) = kdf else {
+        return false;
+    };
+    secrets
+        .iter()
+        .filter(|secret| secret.class == PREVIOUS_KEY_TEST_CLASS && secret.tags == tags)
+        .any(|secret| open(&secret.sealed, keys, kdf).is_ok())
+}
+
+/// Line of a position in text, counting from 1.
+fn line_of(text: &str, pos: usize) -> usize {
+    text[..pos].matches('\n').count() + 1
+}
+
+/// A page with its encrypted content replaced by the decrypted HTML, and the
+/// problems with any content which couldn't be decrypted. The title of a
+/// protected page is restored, and encrypted media is loaded directly.
+fn decrypt_page(
+    html: &str,
+    keys: &HashMap<&str, &str>,
+    kdf: Option<&KdfParams>,
+) -> (String, Vec<String>) {
+    let mut decrypted = String::new();
+    let mut problems = Vec::new();
+    let mut pos = 0;
+    let secrets = find_secrets(html);
+    for secret in &secrets {
+        // The tests in the tag keys menu only check the keys which are given.
+        let is_content = secret.class == SECRET_CLASS;
+        let is_key_test =
+            secret.class == KEY_TEST_CLASS && missing_keys(&secret.sealed, keys).is_empty();
+        if !is_content && !is_key_test {
+            continue;
+        }
+
+        let line = line_of(html, secret.range.start);
+        let result = kdf
+            .ok_or_else(|| String::from("no key derivation parameters"))
+            .and_then(|kdf| open(&secret.sealed, keys, kdf))
+            .and_then(|plaintext| {
+                String::from_utf8(plaintext).map_err(|_| String::from("content isn't text"))
+            });
+
+        match result {
+            Ok(plaintext) if is_content => {
+                decrypted.push_str(&html[pos..secret.range.start]);
+                decrypted.push_str(&plaintext);
+                pos = secret.range.end;
+            }
+            Ok(_) => {}
+            Err(_) if is_key_test && is_previous_key(&secrets, secret.tags, keys, kdf) => problems
+                .push(format!(
+                    "line {line} (tags {}): the key given has since been changed",
+                    secret.tags
+                )),
+            Err(e) => problems.push(format!("line {line} (tags {}): {e}", secret.tags)),
+        }
+    }
+    decrypted.push_str(&html[pos..]);
+
+    (load_secret_media(&restore_title(decrypted)), problems)
+}
+
+/// Replace the placeholder title of a protected page with its decrypted one.
+fn restore_title(html: String) -> String {
+    let Some((start, tag)) = opening_tags(&html, "span")
+        .into_iter()
+        .find(|(_, tag)| attr(tag, "id") == Some(PAGE_TITLE_ID))
+    else {
+        return html;
+    };
+    let content = start + tag.len();
+    let title = html[content..].split('<').next().unwrap_or_default();
+
+    match (html.find("<title>"), html.find("</title>")) {
+        (Some(open), Some(close)) if open < close => {
+            format!("{}<title>{title}{}", &html[..open], &html[close..])
+        }
+        _ => html,
+    }
+}
+
+/// Point encrypted images at their media directly, as it is decrypted too.
+fn load_secret_media(html: &str) -> String {
+    let mut loaded = String::new();
+    let mut pos = 0;
+    for (start, tag) in opening_tags(html, "img") {
+        let is_secret = attr(tag, "class")
+            .is_some_and(|class| class.split(' ').any(|c| c == SECRET_MEDIA_CLASS));
+        if is_secret {
+            loaded.push_str(&html[pos..start]);
+            loaded.push_str(&tag.replacen(" data-src=\"", " src=\"", 1));
+            pos = start + tag.len();
+        }
+    }
+    loaded.push_str(&html[pos..]);
+    loaded
+}
+
+/// All files beneath a directory, in order, other than the build cache.
+fn site_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read directory {}: {e}", dir.display()))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if entry.file_name() != CACHE_FILE {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_page(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == OUTPUT_EXT)
+}
+
+/// A file of a built site, decrypted as far as possible.
+struct Decrypted {
+    path: PathBuf,
+    contents: Vec<u8>,
+    problems: Vec<String>,
+}
+
+/// Decrypt every page and media file of a built site with the tag keys in
+/// config. Media has no key derivation parameters of its own, so uses those of
+/// the pages, which are the same for every page of a build.
+fn decrypt_files(config: &Config, site: &Path) -> Result<Vec<Decrypted>, String> {
+    if config.tag_keys.is_empty() {
+        return Err(String::from(
+            "No tag keys given. Set MDWIKI_TAG_KEY_<TAG>, or use a secrets file given with --secrets.",
+        ));
+    }
+    let keys: HashMap<&str, &str> = config
+        .tag_keys
+        .iter()
+        .map(|(tag, key)| (tag.as_ref(), key.as_str()))
+        .collect();
+
+    let mut files = Vec::new();
+    for path in site_files(site)? {
+        let contents =
+            std::fs::read(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        files.push((path, contents));
+    }
+    let site_kdf = files
+        .iter()
+        .filter(|(path, _)| is_page(path))
+        .find_map(|(_, contents)| kdf_params(&String::from_utf8_lossy(contents)));
+
+    let mut decrypted = Vec::new();
+    for (path, contents) in files {
+        let (contents, problems) = if is_page(&path) {
+            let html = String::from_utf8_lossy(&contents);
+            let kdf = kdf_params(&html).or_else(|| site_kdf.clone());
+            let (html, problems) = decrypt_page(&html, &keys, kdf.as_ref());
+            (html.into_bytes(), problems)
+        } else if let Some(sealed) = Sealed::from_bytes(&contents) {
+            let opened = site_kdf
+                .as_ref()
+                .ok_or_else(|| String::from("no key derivation parameters"))
+                .and_then(|kdf| open(&sealed, &keys, kdf));
+            match opened {
+                Ok(data) => (data, Vec::new()),
+                Err(e) => (contents, vec![e]),
+            }
+        } else {
+            (contents, Vec::new())
+        };
+
+        decrypted.push(Decrypted {
+            path,
+            contents,
+            problems,
+        });
+    }
+    Ok(decrypted)
+}
+
+/// Write a copy of a built site to `out` with everything the configured tag
+/// keys can read decrypted, for example to print.
+pub fn decrypt_site(config: &Config, site: &Path, out: &Path) -> Result<(), String> {
+    for file in decrypt_files(config, site)? {
+        let relative = file.path.strip_prefix(site).unwrap_or(&file.path);
+        for problem in &file.problems {
+            log::warning(format!(
+                "Couldn't decrypt {}: {problem}",
+                relative.display()
+            ));
+        }
+
+        let destination = out.join(relative);
+        if let Some(dir) = destination.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create directory {}: {e}", dir.display()))?;
+        }
+        std::fs::write(&destination, &file.contents)
+            .map_err(|e| format!("Failed to write file ({}): {e}", destination.display()))?;
+    }
+
+    log::info(format!("Decrypted {} to {}", site.display(), out.display()));
+    Ok(())
+}
+
+/// Check that all encrypted content of a built site decrypts with the
+/// configured tag keys, reporting an error for each element which doesn't.
+pub fn verify_keys(config: &Config, site: &Path) -> Result<(), String> {
+    let files = decrypt_files(config, site)?;
+
+    let mut failures = 0;
+    for file in &files {
+        let relative = file.path.strip_prefix(site).unwrap_or(&file.path);
+        for problem in &file.problems {
+            log::error(format!("{}: {problem}", relative.display()));
+            failures += 1;
+        }
+    }
+
+    log::info(format!(
+        "Verified {} files: {failures} failed to decrypt.",
+        files.len()
+    ));
+    if failures > 0 {
+        Err(String::from("Verification failed."))
+    } else {
+        Ok(())
+    }
+}
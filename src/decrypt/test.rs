@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::Config,
+    model::{Doc, Meta, Node, Value, WikiTree},
+    render::{NavTree, render_document},
+};
+
+use super::{decrypt_page, kdf_params, load_secret_media};
+
+fn render(doc: Doc, title: &str) -> String {
+    let config = Config {
+        tag_keys: HashMap::from([("dm".into(), String::from("password"))]),
+        site_title: Some(String::from("The Realm")),
+        ..Config::none()
+    };
+    let mut tree = WikiTree::new();
+    let page = tree.add_doc(WikiTree::ROOT, "page.html", title, doc);
+    render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap()
+}
+
+fn decrypt(html: &str, keys: &[(&str, &str)]) -> (String, Vec<String>) {
+    let keys = keys.iter().copied().collect();
+    decrypt_page(html, &keys, kdf_params(html).as_ref())
+}
+
+#[test]
+fn test_decrypt_page() {
+    let html = render(
+        Doc::from(vec![
+            Node::text("Town"),
+            Node::text("Lair").with_tags(vec!["dm".into()]),
+        ]),
+        "Page",
+    );
+    assert!(!html.contains("Lair"));
+
+    let (decrypted, problems) = decrypt(&html, &[("dm", "password")]);
+    assert!(decrypted.contains("Town"));
+    assert!(decrypted.contains("Lair"));
+    assert!(!decrypted.contains("class=\"secret\""));
+    assert!(decrypted.contains("class=\"tag-keys-test\""));
+    assert!(problems.is_empty());
+
+    let (decrypted, problems) = decrypt(&html, &[("dm", "hunter2")]);
+    assert!(!decrypted.contains("Lair"));
+    assert_eq!(problems.len(), 2);
+    assert!(problems[0].ends_with("(tags dm): the keys given don't decrypt it"));
+
+    let (_, problems) = decrypt(&html, &[("paladin", "password")]);
+    assert!(problems[0].ends_with("no key for `dm`"));
+}
+
+#[test]
+fn test_decrypt_protected_title() {
+    let mut meta = Meta::default();
+    meta.insert(Meta::TAGS, Value::List(vec![Value::String("dm".into())]));
+    let html = render(
+        Doc::from(vec![Node::text("Traps")]).with_meta(meta),
+        "Secret Lair",
+    );
+    assert!(html.contains("<title>Protected page - The Realm</title>"));
+
+    let (decrypted, problems) = decrypt(&html, &[("dm", "password")]);
+    assert!(problems.is_empty());
+    assert!(decrypted.contains("<title>Secret Lair - The Realm</title>"));
+    assert!(decrypted.contains("Traps"));
+}
+
+#[test]
+fn test_load_secret_media() {
+    assert_eq!(
+        load_secret_media(concat!(
+            "<img data-src=\"/lair.png\" alt=\"Lair\" class=\"secret-media\" tags=\"dm\">",
+            "<img data-src=\"/town.png\" alt=\"Town\">",
+        )),
+        concat!(
+            "<img src=\"/lair.png\" alt=\"Lair\" class=\"secret-media\" tags=\"dm\">",
+            "<img data-src=\"/town.png\" alt=\"Town\">",
+        )
+    );
+}
+
+#[test]
+fn test_previous_key() {
+    let config = Config {
+        tag_keys: HashMap::from([("dm".into(), String::from("password"))]),
+        previous_tag_keys: HashMap::from([("dm".into(), vec![String::from("hunter2")])]),
+        ..Config::none()
+    };
+    let mut tree = WikiTree::new();
+    let doc = Doc::from(vec![Node::text("Lair").with_tags(vec!["dm".into()])]);
+    let page = tree.add_doc(WikiTree::ROOT, "page.html", "Page", doc);
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(html.matches("class=\"tag-keys-previous\"").count(), 1);
+
+    let (_, problems) = decrypt(&html, &[("dm", "password")]);
+    assert!(problems.is_empty());
+
+    let (_, problems) = decrypt(&html, &[("dm", "hunter2")]);
+    assert_eq!(problems.len(), 3);
+    let changed: Vec<_> = problems
+        .iter()
+        .filter(|problem| problem.ends_with("(tags dm): the key given has since been changed"))
+        .collect();
+    assert_eq!(changed.len(), 1);
+}
@@ -1,9 +1,13 @@
 #![allow(dead_code)]
 
-use std::fmt::Display;
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
 
-#[derive(PartialEq, PartialOrd)]
-enum Level {
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
     Debug,
     Info,
     Warning,
@@ -23,10 +27,90 @@ impl Display for Level {
     }
 }
 
-const MIN_LEVEL: Level = Level::Info;
+impl Level {
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Self::Debug,
+            1 => Self::Info,
+            2 => Self::Warning,
+            _ => Self::Error,
+        }
+    }
+}
+
+static MIN_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Messages logged on this thread while they are being captured.
+    static CAPTURED: RefCell<Option<Vec<(Level, String)>>> = const { RefCell::new(None) };
+}
+
+/// Messages held back from being logged, so that those from work done in
+/// parallel can be logged in a consistent order.
+#[derive(Debug, Default, PartialEq)]
+pub struct Captured(Vec<(Level, String)>);
+
+impl Captured {
+    /// Log the messages, as if they were logged now.
+    pub fn replay(self) {
+        for (level, msg) in self.0 {
+            log(level, msg);
+        }
+    }
+
+    /// Log only the errors amongst the messages, discarding the rest.
+    pub fn replay_errors(self) {
+        for (level, msg) in self.0 {
+            if level == Level::Error {
+                log(level, msg);
+            }
+        }
+    }
+}
+
+/// Run f, capturing the messages it logs on this thread rather than logging
+/// them.
+pub fn capture<R>(f: impl FnOnce() -> R) -> (R, Captured) {
+    let outer = CAPTURED.replace(Some(Vec::new()));
+    let result = f();
+    let captured = CAPTURED.replace(outer).unwrap_or_default();
+    (result, Captured(captured))
+}
+
+/// Set the minimum level of messages to print.
+pub fn set_level(level: Level) {
+    MIN_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Number of warnings logged so far, whether or not they were printed.
+pub fn warning_count() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+/// Number of errors logged so far, whether or not they were printed.
+pub fn error_count() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
 
 fn log<D: Display>(level: Level, msg: D) {
-    if level >= MIN_LEVEL {
+    let captured = CAPTURED.with_borrow_mut(|captured| {
+        captured
+            .as_mut()
+            .map(|captured| captured.push((level, msg.to_string())))
+    });
+    if captured.is_some() {
+        return;
+    }
+
+    match level {
+        Level::Warning => WARNINGS.fetch_add(1, Ordering::Relaxed),
+        Level::Error => ERRORS.fetch_add(1, Ordering::Relaxed),
+        Level::Debug | Level::Info => 0,
+    };
+
+    if level >= Level::from_u8(MIN_LEVEL.load(Ordering::Relaxed)) {
         println!("{level} {}", msg);
     }
 }
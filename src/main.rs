@@ -1,19 +1,31 @@
 #![feature(pattern)]
 
 use std::{
+    collections::{BTreeMap, HashMap},
     ffi::OsStr,
     path::{Path, PathBuf},
+    process::ExitCode,
+    sync::LazyLock,
 };
 
-use config::Config;
-use model::{Id, WikiPage, WikiTree};
-use render::{INDEX_FILE, OUTPUT_EXT};
+use cache::Cache;
+use cli::Command;
+use config::{CONFIG_FILE, Config};
+use model::{Doc, Id, WikiPage, WikiTree};
+use render::{INDEX_FILE, NavTree, OUTPUT_EXT};
 
+mod cache;
+mod cli;
 mod config;
+mod decrypt;
 mod log;
 mod model;
+mod parallel;
 mod parse;
+mod rebuild;
 mod render;
+mod serve;
+mod watch;
 
 fn create_outdir(outdir: &Path) {
     if std::fs::create_dir_all(outdir).is_err() {
@@ -25,11 +37,7 @@ fn create_outdir(outdir: &Path) {
 }
 
 fn create_output_path(outdir: &Path, page: &WikiPage) -> PathBuf {
-    let mut output = outdir.to_path_buf();
-    for segment in page.url().split('/').filter(|s| !s.is_empty()) {
-        output.push(segment);
-    }
-
+    let output = cache::output_path(outdir, page.url());
     if let Some(outdir) = output.parent() {
         create_outdir(outdir);
     }
@@ -37,10 +45,17 @@ fn create_output_path(outdir: &Path, page: &WikiPage) -> PathBuf {
     output
 }
 
-fn render_document(outdir: &Path, config: &Config, tree: &WikiTree, page: &WikiPage) {
-    let Ok(html) = render::render_document(config, tree, page) else {
+/// Render a page to outdir, returning whether it was written.
+fn render_document(
+    outdir: &Path,
+    config: &Config,
+    tree: &WikiTree,
+    nav: &NavTree,
+    page: &WikiPage,
+) -> bool {
+    let Ok(html) = render::render_document(config, tree, nav, page) else {
         log::error(format!("Failed to render {}", page.url()));
-        return;
+        return false;
     };
 
     let destination = create_output_path(outdir, page);
@@ -51,24 +66,130 @@ fn render_document(outdir: &Path, config: &Config, tree: &WikiTree, page: &WikiP
             page.url(),
             destination.display()
         ));
+        true
     } else {
         log::error(format!("Failed to write file: {}", destination.display()));
+        false
+    }
+}
+
+/// Copy a media file to outdir, encrypting it if needed, returning whether it
+/// was written.
+fn copy_file(config: &Config, media: &WikiPage, outdir: &Path) -> bool {
+    let contents = match render::media_contents(config, media) {
+        Ok(contents) => contents,
+        Err(e) => {
+            log::error(e);
+            return false;
+        }
+    };
+
+    let destination = create_output_path(outdir, media);
+    if let Err(e) = std::fs::write(&destination, contents) {
+        log::error(format!(
+            "Failed to write file ({}): {e}",
+            destination.display()
+        ));
+        false
+    } else {
+        log::debug(format!(
+            "Copied {} to {}",
+            media.url(),
+            destination.display()
+        ));
+        true
     }
 }
 
-fn copy_file(media: &WikiPage, outdir: &Path) {
-    if let Some(file) = media.file() {
-        let destination = create_output_path(outdir, media);
-        if let Err(e) = std::fs::copy(file, &destination) {
-            log::error(format!("Failed to copy file ({}): {e}", file.display()));
+fn write_search_index(outdir: &Path, config: &Config, tree: &WikiTree) {
+    create_outdir(outdir);
+    let destination = outdir.join(render::SEARCH_INDEX_FILE);
+    if let Err(e) = std::fs::write(&destination, render::search_index(config, tree)) {
+        log::error(format!(
+            "Failed to write file ({}): {e}",
+            destination.display()
+        ));
+    } else {
+        log::debug(format!("Wrote search index to {}", destination.display()));
+    }
+}
+
+/// What was done with a page when writing the site.
+enum Output {
+    Rendered,
+    Copied,
+    Unchanged,
+}
+
+/// Render every page of the tree to outdir and copy its media, along with the
+/// search index, skipping those which haven't changed since they were last
+/// written and removing those which no longer exist. `files` are the sources
+/// of the pages.
+fn write_site(
+    outdir: &Path,
+    config: &Config,
+    tree: &WikiTree,
+    files: &HashMap<PathBuf, Id>,
+    cache: &mut Cache,
+) {
+    let sources: HashMap<Id, &Path> = files
+        .iter()
+        .map(|(path, id)| (*id, path.as_path()))
+        .collect();
+
+    let pages: Vec<&WikiPage> = tree
+        .pages()
+        .iter()
+        .filter(|page| page.is_doc() || page.is_index() || page.is_media())
+        .collect();
+    // Only made if a page has changed.
+    let nav = LazyLock::new(|| NavTree::new(config, tree));
+    let keys = cache.keys(config, tree);
+    // Pages are written in parallel, each giving the key of the file written.
+    let outputs = parallel::map(&pages, |page| {
+        let source = sources.get(&page.id()).copied();
+        let key = keys.key(page, source);
+        if key
+            .as_ref()
+            .is_some_and(|key| cache.is_fresh(page.url(), key))
+        {
+            log::debug(format!("{} is unchanged.", page.url()));
+            return (Output::Unchanged, key);
+        }
+
+        let (output, ok) = if page.is_media() {
+            (Output::Copied, copy_file(config, page, outdir))
         } else {
-            log::debug(format!(
-                "Copied {} to {}",
-                file.display(),
-                destination.display()
-            ));
+            (
+                Output::Rendered,
+                render_document(outdir, config, tree, &nav, page),
+            )
+        };
+        // Files which failed are written again next time.
+        (output, key.filter(|_| ok))
+    });
+
+    let mut written = BTreeMap::new();
+    let (mut rendered, mut copied, mut skipped) = (0, 0, 0);
+    for (page, (output, key)) in pages.iter().zip(outputs) {
+        match output {
+            Output::Rendered => rendered += 1,
+            Output::Copied => copied += 1,
+            Output::Unchanged => skipped += 1,
+        }
+        if let Some(key) = key {
+            written.insert(page.url().to_string(), key);
         }
     }
+    if config.search {
+        write_search_index(outdir, config, tree);
+        written.insert(format!("/{}", render::SEARCH_INDEX_FILE), String::new());
+    }
+
+    cache.replace(written);
+    log::info(format!(
+        "Rendered {rendered} pages and copied {copied} media files, {skipped} unchanged."
+    ));
 }
 
 fn add_indexes(tree: &mut WikiTree) {
@@ -84,69 +205,299 @@ fn add_indexes(tree: &mut WikiTree) {
         if !children.is_empty() && !children.iter().any(|child| child.is_index()) {
             let dir = tree.get(id).unwrap();
             let title = dir.title().to_string();
-            let document = render::create_index(dir, &children);
-            tree.add_index(dir.id(), INDEX_FILE, title, document);
+            let document = render::create_index(tree, dir, &children);
+            tree.add_generated_index(dir.id(), INDEX_FILE, title, document);
         }
     }
 }
 
-fn fail(msg: &str) -> ! {
+/// Exit code when errors were reported, or an operation failed.
+const EXIT_ERRORS: u8 = 1;
+
+/// Exit code when the command line arguments are invalid.
+const EXIT_USAGE: u8 = 2;
+
+fn fail(msg: &str) -> ExitCode {
     log::error(msg);
-    panic!();
+    ExitCode::from(EXIT_ERRORS)
 }
 
-fn main() {
-    let Some(arg) = std::env::args().nth(1) else {
-        fail("Usage: mdwiki file.md");
-    };
+/// Source directory of the wiki the command operates on, if any.
+fn source_dir(command: &Command) -> Option<&Path> {
+    match command {
+        Command::Build { src, .. } if src.is_file() => src.parent(),
+        Command::Build { src, .. } | Command::Check { src } | Command::Serve { src, .. } => {
+            Some(src)
+        }
+        Command::Decrypt { site, .. } | Command::VerifyKeys { site } => Some(site),
+        Command::New { .. } | Command::Help | Command::Version => None,
+    }
+}
+
+/// Default settings, updated from the config file given on the command line
+/// or found in the root of the wiki, then overridden by command line options.
+/// Tag keys are then loaded from outside the wiki. Those missing are asked
+/// for once the wiki is parsed, or straight away for a built site.
+fn load_config(args: &cli::Args) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    let src = source_dir(&args.command);
+    let path = args.config.clone().or_else(|| {
+        src.map(|dir| dir.join(CONFIG_FILE))
+            .filter(|path| path.is_file())
+    });
+    if let Some(path) = path {
+        log::debug(format!("Loading config from {}", path.display()));
+        config.load_file(&path)?;
+    }
+
+    args.overrides.apply(&mut config);
+
+    if let Some(src) = src {
+        config.load_keys(args.secrets.as_deref(), src)?;
+    }
+    if let Command::Decrypt { .. } | Command::VerifyKeys { .. } = args.command {
+        config.prompt_keys();
+    }
+    Ok(config)
+}
+
+fn load_tree(config: &Config, src: &Path) -> Result<WikiTree, String> {
+    load_tree_files(config, src).map(|(tree, _)| tree)
+}
+
+/// Parse the wiki in src, along with the file each page was parsed from,
+/// without preparing it to be rendered.
+fn parse_tree_files(
+    config: &Config,
+    src: &Path,
+) -> Result<(WikiTree, HashMap<PathBuf, Id>), String> {
+    if !src.is_dir() {
+        return Err(format!("Not a directory: {}", src.display()));
+    }
+
+    let tree = parse::parse_directory(config, src);
+    let files = cache::source_files(src, &tree);
+    Ok((tree, files))
+}
+
+/// Load the wiki in src, along with the file each page was parsed from,
+/// failing if it uses an encrypted tag without a key.
+fn load_tree_files(
+    config: &Config,
+    src: &Path,
+) -> Result<(WikiTree, HashMap<PathBuf, Id>), String> {
+    let (mut tree, files) = parse_tree_files(config, src)?;
+    config.check_keys(&tree.used_tags())?;
+    prepare_tree(config, &mut tree);
+    Ok((tree, files))
+}
+
+/// Load the wiki in src, first asking for the keys of any encrypted tags it
+/// uses without one.
+fn load_tree_with_keys(config: &mut Config, src: &Path) -> Result<WikiTree, String> {
+    let (mut tree, _) = parse_tree_files(config, src)?;
+    config.require_keys(&tree.used_tags())?;
+    prepare_tree(config, &mut tree);
+    Ok(tree)
+}
+
+/// Rename protected pages, find the links between pages, generate indexes and
+/// protect media in a freshly parsed tree.
+fn prepare_tree(config: &Config, tree: &mut WikiTree) {
+    render::protect_pages(config, tree);
+    render::link_pages(config, tree);
+    if config.generate_indexes {
+        log::info("Generating indexes for directories which don't have them.");
+        add_indexes(tree);
+    }
+    render::protect_media(config, tree);
+}
+
+/// Suffix of the directory a built wiki is decrypted to by default.
+const DECRYPTED_SUFFIX: &str = "decrypted";
 
-    let Ok(metadata) = std::fs::metadata(&arg) else {
-        fail("Couldn't read argument file metadata.")
+/// Output directory alongside the input directory, named `<dir>-<suffix>`.
+fn default_outdir(indir: &Path, suffix: &str) -> Result<PathBuf, String> {
+    let indir = std::fs::canonicalize(indir)
+        .map_err(|e| format!("Couldn't resolve input directory: {e}"))?;
+    let Some(Some(dirname)) = indir.file_name().map(OsStr::to_str) else {
+        return Err(String::from("Couldn't find filename of input directory."));
+    };
+    let Some(parent) = indir.parent() else {
+        return Err(String::from(
+            "Couldn't choose an output directory for files.",
+        ));
     };
+    Ok(parent.join(format!("{dirname}-{suffix}")))
+}
 
-    let config = Config::default();
+fn build(config: &mut Config, src: &Path, out: Option<PathBuf>, watch: bool) -> Result<(), String> {
+    let metadata =
+        std::fs::metadata(src).map_err(|e| format!("Couldn't read {}: {e}", src.display()))?;
 
-    if metadata.is_file() {
-        let path = PathBuf::from(arg);
-        let Some(parent) = path.parent() else {
-            fail("Couldn't find parent directory of input file.");
+    if metadata.is_file() && watch {
+        return Err(String::from("--watch needs a wiki directory, not a file."));
+    } else if metadata.is_file() {
+        let outdir = match out {
+            Some(outdir) => outdir,
+            None => src
+                .parent()
+                .map(Path::to_path_buf)
+                .ok_or("Couldn't find parent directory of input file.")?,
         };
 
-        if let Ok(page) = parse::parse_file(&path) {
-            render_document(parent, &config, &WikiTree::new(), &page)
-        } else {
-            fail("Unable to process file for rendering.");
-        }
-    } else if metadata.is_dir() {
-        let indir = PathBuf::from(arg);
-        let Some(Some(dirname)) = indir.file_name().map(OsStr::to_str) else {
-            fail("Couldn't find filename of input directory.");
+        let page = parse::parse_file(config, src)
+            .map_err(|_| String::from("Unable to process file for rendering."))?;
+        let used = page.document().map(Doc::used_tags).unwrap_or_default();
+        config.require_keys(&used)?;
+        let tree = WikiTree::new();
+        render_document(&outdir, config, &tree, &NavTree::new(config, &tree), &page);
+    } else {
+        let outdir = match out {
+            Some(outdir) => outdir,
+            None => default_outdir(src, OUTPUT_EXT)?,
         };
+        let (mut tree, files) = parse_tree_files(config, src)?;
+        config.require_keys(&tree.used_tags())?;
+        // Keys are derived with the salt of the last build, which pages are
+        // named with when prepared.
+        let mut cache = Cache::load(&outdir, config);
+        prepare_tree(config, &mut tree);
+        if watch {
+            rebuild::watch(config, src, &outdir, cache, tree, files);
+        }
+        write_site(&outdir, config, &tree, &files, &mut cache);
+        cache.save();
 
-        if let Some(parent) = indir.parent() {
-            let outdir = parent.join(format!("{dirname}-{OUTPUT_EXT}"));
-            let mut tree = parse::parse_directory(&indir);
-
-            if config.generate_indexes {
-                log::info("Generating indexes for directories which don't have them.");
-                add_indexes(&mut tree);
-            }
-
-            for page in tree.pages() {
-                if page.is_doc() || page.is_index() {
-                    render_document(&outdir, &config, &tree, page);
-                } else if page.is_media() {
-                    copy_file(page, &outdir);
-                }
-            }
-
-            log::info(format!(
-                "Successfully rendered {} to {}",
-                indir.display(),
-                outdir.display()
-            ));
-        } else {
-            fail("Couldn't choose an output directory for files.");
+        log::info(format!(
+            "Rendered {} to {}",
+            src.display(),
+            outdir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+fn check(config: &mut Config, src: &Path) -> Result<(), String> {
+    let tree = load_tree_with_keys(config, src)?;
+    let config = &*config;
+
+    // Pages are rendered without being written, for any errors found then.
+    // Their warnings are dropped, as the links and images they're about are
+    // reported below.
+    let pages: Vec<&WikiPage> = tree
+        .pages()
+        .iter()
+        .filter(|page| page.is_doc() || page.is_index())
+        .collect();
+    let nav = NavTree::new(config, &tree);
+    parallel::map(&pages, |page| {
+        let (html, captured) = log::capture(|| render::render_document(config, &tree, &nav, page));
+        captured.replay_errors();
+        if html.is_err() {
+            log::error(format!("Failed to render {}", page.url()));
         }
+    });
+
+    // Warnings other than the problems reported.
+    let (warnings, errors) = (log::warning_count(), log::error_count());
+    let report = render::check_tree(config, &tree);
+    for problem in report.problems() {
+        log::warning(problem);
+    }
+
+    let count = pages.len();
+    log::info(format!(
+        "Checked {count} pages: {} broken links, {} orphan pages, {} duplicate titles, \
+        {warnings} other warnings, {errors} errors.",
+        report.broken.len(),
+        report.orphans.len(),
+        report.duplicates.len(),
+    ));
+
+    if report.problems().count() + warnings + errors > 0 {
+        Err(String::from("Check failed."))
+    } else {
+        Ok(())
+    }
+}
+
+fn new_wiki(dir: &Path) -> Result<(), String> {
+    const INDEX_MD: &str = "index.md";
+
+    if let Ok(mut entries) = std::fs::read_dir(dir)
+        && entries.next().is_some()
+    {
+        return Err(format!("Directory isn't empty: {}", dir.display()));
+    }
+
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory ({}): {e}", dir.display()))?;
+
+    let title = std::fs::canonicalize(dir)
+        .ok()
+        .and_then(|dir| {
+            dir.file_name()
+                .and_then(OsStr::to_str)
+                .map(render::capitalise)
+        })
+        .unwrap_or_else(|| String::from("Wiki"));
+    let files = [
+        (INDEX_MD, format!("# {title}\n\nWelcome to the wiki.\n")),
+        (CONFIG_FILE, format!("site_title = \"{title}\"\n")),
+    ];
+    for (name, content) in files {
+        let path = dir.join(name);
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write file ({}): {e}", path.display()))?;
+    }
+
+    log::info(format!("Created new wiki in {}", dir.display()));
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    log::set_level(args.log_level);
+    let mut config = match load_config(&args) {
+        Ok(config) => config,
+        Err(msg) => return fail(&msg),
+    };
+
+    let result = match args.command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Version => {
+            println!("mdwiki {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        Command::Build { src, out, watch } => build(&mut config, &src, out, watch),
+        Command::Check { src } => check(&mut config, &src),
+        Command::Serve { src, port } => load_tree_with_keys(&mut config, &src)
+            .and_then(|tree| serve::serve(&config, &src, port, tree, || load_tree(&config, &src))),
+        Command::New { dir } => new_wiki(&dir),
+        Command::Decrypt { site, out } => match out {
+            Some(out) => Ok(out),
+            None => default_outdir(&site, DECRYPTED_SUFFIX),
+        }
+        .and_then(|out| decrypt::decrypt_site(&config, &site, &out)),
+        Command::VerifyKeys { site } => decrypt::verify_keys(&config, &site),
+    };
+
+    match result {
+        Err(msg) => fail(&msg),
+        Ok(()) if log::error_count() > 0 => ExitCode::from(EXIT_ERRORS),
+        Ok(()) => ExitCode::SUCCESS,
     }
 }
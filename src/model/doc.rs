@@ -1,11 +1,29 @@
-use super::{El, Node, Tag};
+use std::collections::HashSet;
+
+use crate::render::slugify;
+
+use super::{El, Meta, Node, Tag};
+
+const ID_ATTR: &str = "id";
 
 #[derive(Debug, PartialEq)]
-pub struct Doc(Vec<Node>);
+pub struct Doc {
+    nodes: Vec<Node>,
+    meta: Meta,
+}
 
 impl Doc {
     pub fn nodes(&self) -> &[Node] {
-        &self.0
+        &self.nodes
+    }
+
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    pub fn with_meta(mut self, meta: Meta) -> Self {
+        self.meta = meta;
+        self
     }
 
     pub fn tags(&self) -> &[Tag] {
@@ -16,6 +34,81 @@ impl Doc {
         }
     }
 
+    /// Every tag used by the document, in its front matter or content.
+    pub fn used_tags(&self) -> HashSet<Tag> {
+        let mut tags: HashSet<Tag> = self.meta.tags().into_iter().collect();
+        for node in &self.nodes {
+            node.walk(&mut |node| tags.extend(node.tags().iter().cloned()));
+        }
+        tags
+    }
+
+    /// Give each heading a unique `id` attribute derived from its text, so
+    /// that sections can be linked to. Duplicate headings are numbered, e.g.
+    /// `notes`, `notes-1`, `notes-2`. Existing ids are left as they are.
+    /// Tagged headings are numbered after the rest, so that the ids of public
+    /// headings don't reveal those which may be encrypted.
+    pub fn add_heading_ids(&mut self) {
+        let mut headings = Vec::new();
+        let nodes: Vec<&Node> = self.nodes.iter().collect();
+        walk_tagged(&nodes, false, &mut |node, tagged| {
+            if matches!(node.el(), El::Heading(..)) {
+                headings.push((node, tagged));
+            }
+        });
+
+        let mut used = HashSet::new();
+        let mut ids = vec![None; headings.len()];
+        for tagged in [false, true] {
+            for (i, (node, _)) in headings.iter().enumerate().filter(|(_, h)| h.1 == tagged) {
+                if let Some(id) = node.attrs().get(ID_ATTR) {
+                    used.insert(id.clone());
+                    continue;
+                }
+
+                let mut slug = slugify(&node.plain_text());
+                if slug.is_empty() {
+                    slug = String::from("section");
+                }
+
+                let mut id = slug.clone();
+                let mut n = 0;
+                while used.contains(&id) {
+                    n += 1;
+                    id = format!("{slug}-{n}");
+                }
+                used.insert(id.clone());
+                ids[i] = Some(id);
+            }
+        }
+
+        let mut ids = ids.into_iter();
+        for node in &mut self.nodes {
+            node.walk_mut(&mut |node| {
+                if matches!(node.el(), El::Heading(..))
+                    && let Some(id) = ids.next().flatten()
+                {
+                    node.attr(ID_ATTR, &id);
+                }
+            });
+        }
+    }
+
+    /// Ids of all headings in the document, which may be linked to.
+    pub fn anchors(&self) -> Vec<&str> {
+        let mut anchors = Vec::new();
+        for node in self.nodes() {
+            node.walk(&mut |node| {
+                if let El::Heading(..) = node.el()
+                    && let Some(id) = node.attrs().get(ID_ATTR)
+                {
+                    anchors.push(id.as_str());
+                }
+            });
+        }
+        anchors
+    }
+
     pub fn title_from_heading(&self) -> Option<String> {
         for node in self.nodes() {
             if let El::Heading(1, children) = node.el() {
@@ -32,12 +125,37 @@ impl Doc {
 
     #[cfg(test)]
     pub fn empty() -> Self {
-        Self(Vec::new())
+        Self::from(Vec::new())
     }
 }
 
 impl From<Vec<Node>> for Doc {
     fn from(value: Vec<Node>) -> Self {
-        Doc(value)
+        Doc {
+            nodes: value,
+            meta: Meta::default(),
+        }
+    }
+}
+
+/// Call f with each node in document order, along with whether it's tagged,
+/// in the section of a tagged heading or within such content, as it may then
+/// be encrypted.
+fn walk_tagged<'a>(nodes: &[&'a Node], tagged: bool, f: &mut impl FnMut(&'a Node, bool)) {
+    let mut i = 0;
+    while i < nodes.len() {
+        let node = nodes[i];
+        let end = match node.el() {
+            El::Heading(level, _) if !node.tags().is_empty() => nodes[(i + 1)..]
+                .iter()
+                .position(|n| matches!(n.el(), El::Heading(l, _) if l == level))
+                .map_or(nodes.len(), |p| i + 1 + p),
+            _ => i + 1,
+        };
+        let tagged = tagged || !node.tags().is_empty();
+        f(node, tagged);
+        walk_tagged(&node.children(), tagged, f);
+        walk_tagged(&nodes[(i + 1)..end], tagged, f);
+        i = end;
     }
 }
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use super::Tag;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        if let Value::String(string) = self {
+            Some(string)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Value::Bool(value) = self {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        if let Value::Integer(value) = self {
+            Some(*value)
+        } else {
+            None
+        }
+    }
+
+    /// Strings contained in this value. A list yields each of its string
+    /// elements, while a single string is split on commas and whitespace,
+    /// so that `tags: dm, city` and `tags: [dm, city]` are equivalent.
+    pub fn strings(&self) -> Vec<&str> {
+        match self {
+            Value::String(string) => string.split([',', ' ']).filter(|s| !s.is_empty()).collect(),
+            Value::List(values) => values.iter().filter_map(Value::as_str).collect(),
+            Value::Bool(..) | Value::Integer(..) => Vec::new(),
+        }
+    }
+}
+
+/// Metadata for a page, taken from the front matter at the start of the
+/// document. Keys are stored in lower case.
+#[derive(Debug, Default, PartialEq)]
+pub struct Meta(BTreeMap<String, Value>);
+
+impl Meta {
+    pub const TITLE: &str = "title";
+    pub const ALIASES: &str = "aliases";
+    pub const TAGS: &str = "tags";
+    pub const DATE: &str = "date";
+    pub const DRAFT: &str = "draft";
+    pub const ORDER: &str = "order";
+    pub const TEMPLATE: &str = "template";
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn insert<S: AsRef<str>>(&mut self, key: S, value: Value) {
+        self.0.insert(key.as_ref().to_lowercase(), value);
+    }
+
+    /// All entries, sorted by key.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    /// The title, with a numeric scalar such as `title: 1984` taken as text.
+    pub fn title(&self) -> Option<String> {
+        match self.get(Self::TITLE)? {
+            Value::String(s) => Some(s.clone()),
+            Value::Integer(n) => Some(n.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn aliases(&self) -> Vec<&str> {
+        match self.get(Self::ALIASES) {
+            Some(Value::String(alias)) => vec![alias.as_str()],
+            Some(value) => value.strings(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn tags(&self) -> Vec<Tag> {
+        self.get(Self::TAGS)
+            .map(|tags| {
+                tags.strings()
+                    .into_iter()
+                    .map(|tag| Tag::from(tag.trim_start_matches('#')))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn date(&self) -> Option<&str> {
+        self.get_str(Self::DATE)
+    }
+
+    pub fn draft(&self) -> bool {
+        self.get(Self::DRAFT)
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    pub fn order(&self) -> Option<i64> {
+        self.get(Self::ORDER).and_then(Value::as_integer)
+    }
+
+    pub fn template(&self) -> Option<&str> {
+        self.get_str(Self::TEMPLATE)
+    }
+}
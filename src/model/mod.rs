@@ -1,9 +1,11 @@
 mod doc;
+mod meta;
 mod node;
 mod tag;
 mod wiki;
 
 pub use self::doc::Doc;
-pub use self::node::{Attrs, El, Node, Style, HEADING_MAX_LEVEL};
+pub use self::meta::{Meta, Value};
+pub use self::node::{Align, Attrs, Callout, El, HEADING_MAX_LEVEL, Node, Style};
 pub use self::tag::Tag;
-pub use self::wiki::{Id, WikiPage, WikiTree};
+pub use self::wiki::{Backlink, Id, WikiPage, WikiTree};
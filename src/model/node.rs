@@ -2,18 +2,49 @@ use std::collections::HashMap;
 
 use super::Tag;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Style {
     Bold,
     Italic,
     Strikethrough,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Align {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// Kinds of GitHub-style callout, e.g. `> [!NOTE]`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Callout {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl Callout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "note" => Some(Self::Note),
+            "tip" => Some(Self::Tip),
+            "important" => Some(Self::Important),
+            "warning" => Some(Self::Warning),
+            "caution" => Some(Self::Caution),
+            _ => None,
+        }
+    }
+}
+
 pub const HEADING_MAX_LEVEL: u8 = 6;
 
 pub type Attrs = HashMap<String, String>;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Node {
     element: El,
     attributes: Attrs,
@@ -54,6 +85,24 @@ impl Node {
         ))
     }
 
+    /// Create a `[[target|text]]` link, resolved to the page whose title or
+    /// filename matches target.
+    pub fn wiki_link(target: &str, text: &str) -> Self {
+        Self::new(El::WikiLink(
+            String::from(target.trim()),
+            String::from(text.trim()),
+        ))
+    }
+
+    /// Create a `![[target|alt]]` image, resolved to the image whose title or
+    /// filename matches target.
+    pub fn wiki_image(target: &str, alt: &str) -> Self {
+        Self::new(El::WikiImage(
+            String::from(target.trim()),
+            String::from(alt.trim()),
+        ))
+    }
+
     pub fn text(text: &str) -> Self {
         Self::new(El::Text(text.trim().to_string()))
     }
@@ -66,25 +115,51 @@ impl Node {
         Self::new(El::Heading(size, children))
     }
 
+    pub fn blockquote(callout: Option<Callout>, children: Vec<Node>) -> Self {
+        Self::new(El::Blockquote(callout, children))
+    }
+
     pub fn item(children: Vec<Node>) -> Self {
         Self::new(El::Item(children))
     }
 
-    /// Create a <ul> from a series of nodes. Each node will be wrapped in a
-    /// <li>, if it is not already.
-    pub fn list(children: Vec<Node>) -> Self {
-        let items: Vec<Node> = children
+    /// Nodes wrapped in <li>s, apart from items and HTML rendered already,
+    /// which is taken to be items.
+    fn items(children: Vec<Node>) -> Vec<Node> {
+        children
             .into_iter()
             .map(|n| match n.el() {
-                El::Item(_) => n,
+                El::Item(_) | El::Html(_) => n,
                 _ => Self::item(vec![n]),
             })
-            .collect();
-        Self::new(El::List(items))
+            .collect()
+    }
+
+    /// Create a <ul> from a series of nodes. Each node will be wrapped in a
+    /// <li>, if it is not already.
+    pub fn list(children: Vec<Node>) -> Self {
+        Self::new(El::List(Self::items(children)))
+    }
+
+    /// Create an <ol> numbered from start from a series of nodes. Each node
+    /// will be wrapped in a <li>, if it is not already.
+    pub fn ordered_list(start: usize, children: Vec<Node>) -> Self {
+        Self::new(El::OrderedList(start, Self::items(children)))
     }
 
+    /// Create a <table> with no header row from a series of rows.
     pub fn table(children: Vec<Vec<Vec<Node>>>) -> Self {
-        Self::new(El::Table(children))
+        Self::new(El::Table(Vec::new(), Vec::new(), children))
+    }
+
+    /// Create a <table> with a header row and per-column alignments. Columns
+    /// beyond the end of `align` are left unaligned.
+    pub fn table_with_header(
+        align: Vec<Align>,
+        header: Vec<Vec<Node>>,
+        rows: Vec<Vec<Vec<Node>>>,
+    ) -> Self {
+        Self::new(El::Table(align, header, rows))
     }
 
     pub fn block<S: ToString>(tag: S, children: Vec<Node>) -> Self {
@@ -111,6 +186,17 @@ impl Node {
         Self::new(El::Details(summary, details))
     }
 
+    /// HTML rendered already, such as list items shared between pages. It's
+    /// put on lines of its own, indented to where it appears.
+    pub fn html(html: String) -> Self {
+        Self::new(El::Html(html))
+    }
+
+    /// Marker for the position of the page's table of contents.
+    pub fn toc() -> Self {
+        Self::new(El::Toc)
+    }
+
     pub fn attr(&mut self, key: &str, value: &str) {
         self.attributes
             .insert(key.trim().to_string(), value.trim().to_string());
@@ -145,17 +231,23 @@ impl Node {
         match &self.element {
             El::Empty
             | El::Block(_, _)
+            | El::Blockquote(_, _)
             | El::Inline(_, _)
             | El::Details(_, _)
             | El::Item(_)
             | El::List(_)
+            | El::OrderedList(..)
             | El::Style(_, _)
-            | El::Table(_)
+            | El::Table(..)
+            | El::Toc
+            | El::Html(_)
             | El::Heading(_, _) => None,
             El::Code(text)
             | El::Codeblock(_, text)
             | El::Image(text, _)
             | El::Link(text, _)
+            | El::WikiImage(_, text)
+            | El::WikiLink(_, text)
             | El::Text(text) => Some(text),
         }
     }
@@ -171,41 +263,142 @@ impl Node {
         &self.tags
     }
 
+    /// Child nodes of this node, in document order.
+    pub fn children(&self) -> Vec<&Node> {
+        match &self.element {
+            El::Block(_, children)
+            | El::Blockquote(_, children)
+            | El::Inline(_, children)
+            | El::Heading(_, children)
+            | El::Item(children)
+            | El::List(children)
+            | El::OrderedList(_, children)
+            | El::Style(_, children) => children.iter().collect(),
+            El::Details(summary, details) => summary.iter().chain(details).collect(),
+            El::Table(_, header, rows) => header
+                .iter()
+                .chain(rows.iter().flatten())
+                .flatten()
+                .collect(),
+            El::Empty
+            | El::Code(..)
+            | El::Codeblock(..)
+            | El::Image(..)
+            | El::Link(..)
+            | El::Html(..)
+            | El::Text(..)
+            | El::Toc
+            | El::WikiImage(..)
+            | El::WikiLink(..) => Vec::new(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Node> {
+        match &mut self.element {
+            El::Block(_, children)
+            | El::Blockquote(_, children)
+            | El::Inline(_, children)
+            | El::Heading(_, children)
+            | El::Item(children)
+            | El::List(children)
+            | El::OrderedList(_, children)
+            | El::Style(_, children) => children.iter_mut().collect(),
+            El::Details(summary, details) => summary.iter_mut().chain(details).collect(),
+            El::Table(_, header, rows) => header
+                .iter_mut()
+                .chain(rows.iter_mut().flatten())
+                .flatten()
+                .collect(),
+            El::Empty
+            | El::Code(..)
+            | El::Codeblock(..)
+            | El::Image(..)
+            | El::Link(..)
+            | El::Html(..)
+            | El::Text(..)
+            | El::Toc
+            | El::WikiImage(..)
+            | El::WikiLink(..) => Vec::new(),
+        }
+    }
+
+    /// Call f with this node and each of its descendents, in document order.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a Node)) {
+        f(self);
+        for child in self.children() {
+            child.walk(f);
+        }
+    }
+
+    pub fn walk_mut(&mut self, f: &mut impl FnMut(&mut Node)) {
+        f(self);
+        for child in self.children_mut() {
+            child.walk_mut(f);
+        }
+    }
+
+    /// Text content of this node and its descendents, without markup.
+    pub fn plain_text(&self) -> String {
+        let mut words = Vec::new();
+        self.walk(&mut |node| match node.el() {
+            El::Code(text) | El::Link(text, _) | El::Text(text) | El::WikiLink(_, text) => {
+                words.push(text.as_str())
+            }
+            _ => {}
+        });
+        words.join(" ")
+    }
+
     pub fn with_tags(mut self, tags: Vec<Tag>) -> Self {
         self.tags = tags;
         self
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum El {
     Empty,
-    Block(String, Vec<Node>),          // (tag, children)
-    Inline(String, Vec<Node>),         // (tag, children)
-    Code(String),                      // (code)
-    Codeblock(Option<String>, String), // (lang, code)
-    Details(Vec<Node>, Vec<Node>),     // (summary, details)
-    Heading(u8, Vec<Node>),            // (type, children)
-    Image(String, String),             // (text, url)
-    Item(Vec<Node>),                   // (children)
-    Link(String, String),              // (text, url)
-    List(Vec<Node>),                   // (children)
-    Style(Style, Vec<Node>),           // (style, children)
-    Table(Vec<Vec<Vec<Node>>>),        // (rows(columns(cells)))
-    Text(String),                      // (text)
+    Block(String, Vec<Node>),               // (tag, children)
+    Blockquote(Option<Callout>, Vec<Node>), // (callout, children)
+    Inline(String, Vec<Node>),              // (tag, children)
+    Code(String),                           // (code)
+    Codeblock(Option<String>, String),      // (lang, code)
+    Details(Vec<Node>, Vec<Node>),          // (summary, details)
+    Heading(u8, Vec<Node>),                 // (type, children)
+    Html(String),                           // (html) rendered already
+    Image(String, String),                  // (text, url)
+    Item(Vec<Node>),                        // (children)
+    Link(String, String),                   // (text, url)
+    List(Vec<Node>),                        // (children)
+    OrderedList(usize, Vec<Node>),          // (start, children)
+    Style(Style, Vec<Node>),                // (style, children)
+    Table(Vec<Align>, Vec<Vec<Node>>, Vec<Vec<Vec<Node>>>), // (align, header, rows)
+    Text(String),                           // (text)
+    Toc,
+    WikiImage(String, String), // (target, text)
+    WikiLink(String, String),  // (target, text)
 }
 
 impl El {
     fn add_text(&mut self, text: &str) {
         match self {
-            El::Empty | El::Image(..) | El::Link(..) | El::Table(..) => (),
+            El::Empty
+            | El::Image(..)
+            | El::Link(..)
+            | El::Html(..)
+            | El::Table(..)
+            | El::Toc
+            | El::WikiImage(..)
+            | El::WikiLink(..) => (),
             El::Block(_, children)
+            | El::Blockquote(_, children)
             | El::Inline(_, children)
             | El::Details(_, children)
             | El::Style(_, children)
             | El::Heading(_, children)
             | El::Item(children)
-            | El::List(children) => children.push(Node::text(text)),
+            | El::List(children)
+            | El::OrderedList(_, children) => children.push(Node::text(text)),
             El::Code(string) | El::Codeblock(_, string) | El::Text(string) => {
                 if !string.is_empty() {
                     string.push(' ');
@@ -218,25 +411,26 @@ impl El {
     fn is_empty(&self) -> bool {
         match self {
             El::Empty => true,
-            El::Image(text, url) | El::Link(text, url) => text.is_empty() && url.is_empty(),
+            El::Toc => false,
+            El::Html(html) => html.is_empty(),
+            El::Image(text, url)
+            | El::Link(text, url)
+            | El::WikiImage(url, text)
+            | El::WikiLink(url, text) => text.is_empty() && url.is_empty(),
             El::Block(_, children)
+            | El::Blockquote(_, children)
             | El::Inline(_, children)
             | El::Style(_, children)
             | El::Heading(_, children)
             | El::Item(children)
-            | El::List(children) => all_empty(children),
+            | El::List(children)
+            | El::OrderedList(_, children) => all_empty(children),
             El::Details(summary, details) => all_empty(summary) && all_empty(details),
             El::Code(string) | El::Codeblock(_, string) | El::Text(string) => {
                 string.trim().is_empty()
             }
-            El::Table(rows) => {
-                rows.is_empty()
-                    || rows.iter().all(|row| {
-                        row.is_empty()
-                            || row
-                                .iter()
-                                .all(|col| col.is_empty() || col.iter().all(|node| node.is_empty()))
-                    })
+            El::Table(_, header, rows) => {
+                row_empty(header) && (rows.is_empty() || rows.iter().all(|row| row_empty(row)))
             }
         }
     }
@@ -246,6 +440,10 @@ fn all_empty(nodes: &[Node]) -> bool {
     nodes.iter().all(Node::is_empty)
 }
 
+fn row_empty(row: &[Vec<Node>]) -> bool {
+    row.is_empty() || row.iter().all(|col| all_empty(col))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -255,5 +453,9 @@ mod test {
         assert!(Node::text("").is_empty());
         assert!(!Node::text("hi").is_empty());
         assert!(!Node::table(vec![vec![vec![Node::text("hi")]]]).is_empty());
+        assert!(
+            !Node::table_with_header(vec![Align::None], vec![vec![Node::text("hi")]], vec![])
+                .is_empty()
+        );
     }
 }
@@ -1,8 +1,11 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use crate::render::capitalise;
 
-use super::{Doc, Tag};
+use super::{Doc, Meta, Tag};
 
 pub type Id = usize;
 
@@ -11,7 +14,7 @@ enum WikiPageInner {
     Document(Doc),
     Directory,
     Index(Doc),
-    Media(PathBuf),
+    Media(PathBuf, Vec<Vec<Tag>>), // (file, groups of tags protecting it)
 }
 
 #[derive(Debug)]
@@ -20,6 +23,8 @@ pub struct WikiPage {
     path: Vec<Id>,
     title: String,
     url: String,
+    /// Whether this is an index generated for a directory without one.
+    generated: bool,
 }
 
 impl WikiPage {
@@ -36,14 +41,52 @@ impl WikiPage {
         }
     }
 
-    pub fn tags(&self) -> &[Tag] {
-        if let Some(doc) = self.document() {
-            doc.tags()
-        } else {
-            &[]
+    /// Tags applying to this page: those listed in its front matter followed
+    /// by those of its first node. For media, the tags it is protected by.
+    pub fn tags(&self) -> Vec<Tag> {
+        match &self.inner {
+            WikiPageInner::Document(doc) | WikiPageInner::Index(doc) => {
+                let mut tags = doc.meta().tags();
+                tags.extend(doc.tags().iter().cloned());
+                tags
+            }
+            WikiPageInner::Media(_, groups) => {
+                let mut tags: Vec<Tag> = Vec::new();
+                for tag in groups.iter().flatten() {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                    }
+                }
+                tags
+            }
+            WikiPageInner::Directory => Vec::new(),
         }
     }
 
+    /// For media, the groups of tags it's protected by, of which readers need
+    /// one tag from each group. Empty for other pages.
+    pub fn tag_groups(&self) -> &[Vec<Tag>] {
+        match &self.inner {
+            WikiPageInner::Media(_, groups) => groups,
+            _ => &[],
+        }
+    }
+
+    pub fn meta(&self) -> Option<&Meta> {
+        self.document().map(Doc::meta)
+    }
+
+    /// Alternative titles for this page, from its front matter.
+    pub fn aliases(&self) -> Vec<&str> {
+        self.meta().map(Meta::aliases).unwrap_or_default()
+    }
+
+    /// Sort key for this page amongst its siblings. Pages with an order set in
+    /// their front matter come first, in ascending order.
+    pub fn order(&self) -> i64 {
+        self.meta().and_then(Meta::order).unwrap_or(i64::MAX)
+    }
+
     pub fn url(&self) -> &str {
         &self.url
     }
@@ -61,7 +104,7 @@ impl WikiPage {
     }
 
     pub fn file(&self) -> Option<&Path> {
-        if let WikiPageInner::Media(path) = &self.inner {
+        if let WikiPageInner::Media(path, _) = &self.inner {
             Some(path)
         } else {
             None
@@ -99,10 +142,34 @@ impl WikiPage {
     pub fn is_media(&self) -> bool {
         matches!(self.inner, WikiPageInner::Media(..))
     }
+
+    pub fn is_generated(&self) -> bool {
+        self.generated
+    }
+}
+
+/// A link to a page from another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backlink {
+    pub from: Id,
+    /// The sentence containing the link.
+    pub context: String,
+    /// Groups of tags of which a reader needs a key from each to see the
+    /// link, as it is in encrypted content. Empty if the link is public.
+    pub access: Vec<Vec<Tag>>,
 }
 
 pub struct WikiTree {
     nodes: Vec<WikiPage>,
+    /// Children of each page, by ID, in the order they were added.
+    children: Vec<Vec<Id>>,
+    /// Pages by URL.
+    urls: HashMap<String, Id>,
+    /// Pages by file name, in the order they were added.
+    filenames: HashMap<String, Vec<Id>>,
+    /// Pages by title and alias, lowercase, in the order they were added.
+    titles: HashMap<String, Vec<Id>>,
+    backlinks: HashMap<Id, Vec<Backlink>>,
 }
 
 impl WikiTree {
@@ -115,13 +182,54 @@ impl WikiTree {
                 path: vec![Self::ROOT],
                 title: "Index".to_string(),
                 url: "".to_string(),
+                generated: false,
             }],
+            children: vec![Vec::new()],
+            urls: HashMap::from([(String::new(), Self::ROOT)]),
+            filenames: HashMap::new(),
+            titles: HashMap::new(),
+            backlinks: HashMap::new(),
+        }
+    }
+
+    /// Title and aliases a page can be linked by, lowercase.
+    fn names(page: &WikiPage) -> Vec<String> {
+        std::iter::once(page.title())
+            .chain(page.aliases())
+            .map(str::to_ascii_lowercase)
+            .collect()
+    }
+
+    fn index_names(&mut self, id: Id) {
+        for name in Self::names(&self.nodes[id]) {
+            let ids = self.titles.entry(name).or_default();
+            if let Err(i) = ids.binary_search(&id) {
+                ids.insert(i, id);
+            }
+        }
+    }
+
+    fn unindex_names(&mut self, id: Id) {
+        for name in Self::names(&self.nodes[id]) {
+            if let Some(ids) = self.titles.get_mut(&name) {
+                ids.retain(|page| *page != id);
+            }
+        }
+    }
+
+    fn index_filename(&mut self, id: Id) {
+        let ids = self
+            .filenames
+            .entry(self.nodes[id].filename().to_string())
+            .or_default();
+        if let Err(i) = ids.binary_search(&id) {
+            ids.insert(i, id);
         }
     }
 
     fn add<D: std::fmt::Display, S: ToString>(
         &mut self,
-        inner: WikiPageInner,
+        mut inner: WikiPageInner,
         parent: Id,
         filename: D,
         title: S,
@@ -135,12 +243,24 @@ impl WikiTree {
         let mut path = parent.path.clone();
         path.push(id);
 
+        if let WikiPageInner::Document(doc) | WikiPageInner::Index(doc) = &mut inner {
+            doc.add_heading_ids();
+        }
+
+        let parent = parent.id();
+        let url = format!("{}/{}", self.nodes[parent].url(), filename);
         self.nodes.push(WikiPage {
             inner,
             path,
             title: title.to_string(),
-            url: format!("{}/{}", parent.url(), filename),
+            url: url.clone(),
+            generated: false,
         });
+        self.children.push(Vec::new());
+        self.children[parent].push(id);
+        self.urls.entry(url).or_insert(id);
+        self.index_filename(id);
+        self.index_names(id);
 
         id
     }
@@ -170,6 +290,19 @@ impl WikiTree {
         self.add(WikiPageInner::Index(document), parent, filename, title)
     }
 
+    /// Add an index generated for a directory which doesn't have one.
+    pub fn add_generated_index<D: std::fmt::Display, S: ToString>(
+        &mut self,
+        parent: Id,
+        filename: D,
+        title: S,
+        document: Doc,
+    ) -> Id {
+        let id = self.add_index(parent, filename, title, document);
+        self.nodes[id].generated = true;
+        id
+    }
+
     pub fn add_media<D: std::fmt::Display, S: ToString, P: Into<PathBuf>>(
         &mut self,
         parent: Id,
@@ -177,7 +310,97 @@ impl WikiTree {
         title: S,
         path: P,
     ) -> Id {
-        self.add(WikiPageInner::Media(path.into()), parent, filename, title)
+        self.add(
+            WikiPageInner::Media(path.into(), Vec::new()),
+            parent,
+            filename,
+            title,
+        )
+    }
+
+    /// Replace the document and title of a doc or index page, e.g. after its
+    /// file has changed. Has no effect on other pages.
+    pub fn set_document<S: ToString>(&mut self, id: Id, title: S, mut document: Doc) {
+        if self.get(id).is_none_or(|page| page.document().is_none()) {
+            return;
+        }
+
+        self.unindex_names(id);
+        let page = &mut self.nodes[id];
+        if let WikiPageInner::Document(doc) | WikiPageInner::Index(doc) = &mut page.inner {
+            document.add_heading_ids();
+            *doc = document;
+            page.title = title.to_string();
+        }
+        self.index_names(id);
+    }
+
+    /// Set the groups of tags protecting a media file, see `tag_groups`. Has
+    /// no effect on other pages.
+    pub fn protect_media(&mut self, id: Id, groups: Vec<Vec<Tag>>) {
+        if let Some(WikiPage {
+            inner: WikiPageInner::Media(_, media_groups),
+            ..
+        }) = self.nodes.get_mut(id)
+        {
+            *media_groups = groups;
+        }
+    }
+
+    /// Change the file name of a page, updating the URLs of it and of any
+    /// pages beneath it.
+    pub fn rename(&mut self, id: Id, filename: &str) {
+        let Some(page) = self.get(id).filter(|page| !page.is_root()) else {
+            return;
+        };
+        let old = page.url().to_string();
+        let new = format!(
+            "{}/{filename}",
+            self.get_parent(page).map(WikiPage::url).unwrap_or_default()
+        );
+
+        let old_filename = page.filename().to_string();
+        if let Some(ids) = self.filenames.get_mut(&old_filename) {
+            ids.retain(|page| *page != id);
+        }
+        let mut pages = vec![id];
+        while let Some(page) = pages.pop() {
+            pages.extend(&self.children[page]);
+            let page = &mut self.nodes[page];
+            self.urls.remove(&page.url);
+            page.url = format!("{new}{}", &page.url[old.len()..]);
+            self.urls.insert(page.url.clone(), page.id());
+        }
+        self.index_filename(id);
+    }
+
+    /// The index page of a directory, if it has one.
+    pub fn index_of(&self, dir: Id) -> Option<&WikiPage> {
+        self.children(dir).into_iter().find(|page| page.is_index())
+    }
+
+    /// Tags protecting a page. A directory is protected by those of its index.
+    pub fn page_tags(&self, page: &WikiPage) -> Vec<Tag> {
+        if page.is_dir() {
+            self.index_of(page.id())
+                .map(WikiPage::tags)
+                .unwrap_or_default()
+        } else {
+            page.tags()
+        }
+    }
+
+    /// Links to a page from others, in the order of the linking pages.
+    pub fn backlinks(&self, id: Id) -> &[Backlink] {
+        self.backlinks.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set_backlinks(&mut self, backlinks: HashMap<Id, Vec<Backlink>>) {
+        self.backlinks = backlinks;
+    }
+
+    pub fn find_url(&self, url: &str) -> Option<&WikiPage> {
+        self.urls.get(url).and_then(|id| self.get(*id))
     }
 
     pub fn get(&self, id: Id) -> Option<&WikiPage> {
@@ -189,46 +412,76 @@ impl WikiTree {
     }
 
     pub fn children(&self, id: Id) -> Vec<&WikiPage> {
-        self.nodes
-            .iter()
-            .filter(|n| n.parent() == Some(id))
-            .collect()
+        self.children
+            .get(id)
+            .map(|ids| ids.iter().map(|id| &self.nodes[*id]).collect())
+            .unwrap_or_default()
     }
 
     pub fn pages(&self) -> &[WikiPage] {
         &self.nodes
     }
 
+    /// Every tag used by the pages of the wiki.
+    pub fn used_tags(&self) -> HashSet<Tag> {
+        self.nodes
+            .iter()
+            .filter_map(WikiPage::document)
+            .flat_map(Doc::used_tags)
+            .collect()
+    }
+
     pub fn into_pages(self) -> Vec<WikiPage> {
         self.nodes
     }
 
     fn link_target_filename(title: &str, ext: &str) -> String {
         let mut rectified_name = title.to_lowercase().replace(' ', "-");
-        rectified_name.push('.');
-        rectified_name.push_str(ext);
+        if !rectified_name.ends_with(&format!(".{ext}")) {
+            rectified_name.push('.');
+            rectified_name.push_str(ext);
+        }
         rectified_name
     }
 
+    /// The page a link by title or file name leads to: the first with that
+    /// title, alias or file name found breadth first beneath the linking page,
+    /// then beneath each of its ancestors in turn.
     pub fn find_link_target(&self, title: &str, ext: &str, from: &WikiPage) -> Option<&WikiPage> {
-        let filename = &Self::link_target_filename(title, ext);
+        let filename = Self::link_target_filename(title, ext);
+        let by_filename = self.filenames.get(&filename);
+        let by_title = self.titles.get(&title.to_ascii_lowercase());
+        let candidates: Vec<&WikiPage> = by_filename
+            .into_iter()
+            .chain(by_title)
+            .flatten()
+            .map(|id| &self.nodes[*id])
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
 
-        let mut frontier = self.children(from.id());
-        let mut i = 0;
-        while let Some(descendent) = frontier.get(i) {
-            if descendent.filename() == filename || descendent.title().eq_ignore_ascii_case(title) {
-                return Some(descendent);
+        // Pages are found nearest first and, as children are searched in the
+        // order they were added, then by the IDs along their paths.
+        for ancestor in from.path.iter().rev() {
+            let found = candidates
+                .iter()
+                .filter(|page| page.id() != *ancestor && page.is_descendent_of(*ancestor))
+                .min_by(|a, b| {
+                    a.path
+                        .len()
+                        .cmp(&b.path.len())
+                        .then_with(|| a.path.cmp(&b.path))
+                });
+            if found.is_some() {
+                return found.copied();
             }
-
-            frontier.extend(self.children(descendent.id()));
-            i += 1;
         }
-
-        self.get_parent(from)
-            .and_then(|p| self.find_link_target(title, ext, p))
+        None
     }
 
     #[cfg(test)]
+    #[allow(dead_code)]
     fn print_subtree(&self, node: &WikiPage, indent: usize) {
         println!("{}{}", "  ".repeat(indent), node.filename());
         for child in self.children(node.id()) {
@@ -237,6 +490,7 @@ impl WikiTree {
     }
 
     #[cfg(test)]
+    #[allow(dead_code)]
     pub fn print(&self) {
         self.print_subtree(self.get(Self::ROOT).unwrap(), 0);
     }
@@ -244,7 +498,10 @@ impl WikiTree {
 
 #[cfg(test)]
 mod test {
+    use std::time::Instant;
+
     use super::*;
+    use crate::{config::Config, model::Value, parse::parse_document, render};
 
     #[test]
     fn test_filename() {
@@ -253,11 +510,53 @@ mod test {
             path: vec![0, 1],
             title: "Title".into(),
             url: "/rootdir/title.html".into(),
+            generated: false,
         };
 
         assert_eq!(node.filename(), "title.html");
     }
 
+    #[test]
+    fn test_rename() {
+        let mut tree = WikiTree::new();
+        let dir = tree.add_dir(WikiTree::ROOT, "lair");
+        let doc = tree.add_doc(dir, "vault.html", "Vault", Doc::empty());
+
+        tree.rename(dir, "0123abcd");
+        assert_eq!(tree.get(dir).unwrap().url(), "/0123abcd");
+        assert_eq!(tree.get(doc).unwrap().url(), "/0123abcd/vault.html");
+
+        tree.rename(doc, "4567cdef.html");
+        assert_eq!(tree.get(doc).unwrap().url(), "/0123abcd/4567cdef.html");
+        assert_eq!(tree.get(doc).unwrap().filename(), "4567cdef.html");
+    }
+
+    #[test]
+    fn test_find_link_target_alias() {
+        let mut meta = Meta::default();
+        meta.insert(
+            Meta::ALIASES,
+            Value::List(vec![Value::String("The Capital".into())]),
+        );
+
+        let mut tree = WikiTree::new();
+        let city = tree.add_doc(
+            WikiTree::ROOT,
+            "city.html",
+            "City",
+            Doc::empty().with_meta(meta),
+        );
+        let from = tree.add_doc(WikiTree::ROOT, "other.html", "Other", Doc::empty());
+        let from = tree.get(from).unwrap();
+
+        assert_eq!(
+            tree.find_link_target("the capital", "html", from)
+                .unwrap()
+                .id(),
+            city
+        );
+    }
+
     #[test]
     fn test_find_link_target() {
         let mut tree = WikiTree::new();
@@ -297,4 +596,93 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_find_link_target_nearest() {
+        let mut tree = WikiTree::new();
+        let a = tree.add_dir(WikiTree::ROOT, "a");
+        let deep = tree.add_dir(a, "deep");
+        tree.add_doc(deep, "inn.html", "Inn", Doc::empty());
+        let b = tree.add_dir(WikiTree::ROOT, "b");
+        let near = tree.add_doc(b, "tavern.html", "Inn", Doc::empty());
+        let from = tree.add_doc(a, "road.html", "Road", Doc::empty());
+        let shallow = tree.add_doc(a, "inn.html", "Other", Doc::empty());
+
+        // Pages beneath the nearest ancestor come first, shallowest first.
+        let road = tree.get(from).unwrap();
+        assert_eq!(
+            tree.find_link_target("Inn", "html", road).unwrap().id(),
+            shallow
+        );
+
+        tree.rename(shallow, "0123abcd.html");
+        let road = tree.get(from).unwrap();
+        let found = tree.find_link_target("Inn", "html", road).unwrap();
+        assert_eq!(found.url(), "/a/deep/inn.html");
+        assert_eq!(tree.find_url("/a/0123abcd.html").unwrap().id(), shallow);
+        assert!(tree.find_url("/a/inn.html").is_none());
+
+        let root = tree.get(WikiTree::ROOT).unwrap();
+        assert_eq!(
+            tree.find_link_target("Inn", "html", root).unwrap().id(),
+            near
+        );
+    }
+
+    /// Build a wiki of 100 directories of 100 pages, each linking to others
+    /// by title, and time finding the links between them and rendering
+    /// pages. Run with `cargo test --release bench_10k_pages -- --ignored
+    /// --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_10k_pages() {
+        const DIRS: usize = 100;
+        const PAGES: usize = 100;
+
+        let start = Instant::now();
+        let mut tree = WikiTree::new();
+        for d in 0..DIRS {
+            let dir = tree.add_dir(WikiTree::ROOT, format!("dir-{d}"));
+            for p in 0..PAGES {
+                let text = format!(
+                    "# Page {d} {p}\n\nNear [[Page {} {p}]] and [Page {p} {}]().",
+                    (d + 1) % DIRS,
+                    (d + 2) % DIRS
+                );
+                let title = format!("Page {d} {p}");
+                tree.add_doc(
+                    dir,
+                    format!("page-{d}-{p}.html"),
+                    title,
+                    parse_document(&text),
+                );
+            }
+        }
+        println!(
+            "Built {} pages in {:?}",
+            tree.pages().len(),
+            start.elapsed()
+        );
+
+        let config = Config {
+            empty_links: true,
+            nav_tree: true,
+            ..Config::none()
+        };
+        let start = Instant::now();
+        render::link_pages(&config, &mut tree);
+        let links: usize = (0..tree.pages().len())
+            .map(|id| tree.backlinks(id).len())
+            .sum();
+        println!("Found {links} links in {:?}", start.elapsed());
+        assert_eq!(links, 2 * DIRS * PAGES);
+
+        let start = Instant::now();
+        let nav = render::NavTree::new(&config, &tree);
+        let pages: Vec<_> = tree.pages().iter().filter(|p| p.is_doc()).collect();
+        for page in &pages {
+            render::render_document(&config, &tree, &nav, page).unwrap();
+        }
+        println!("Rendered {} pages in {:?}", pages.len(), start.elapsed());
+    }
 }
@@ -0,0 +1,78 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use crate::log::{self, Captured};
+
+/// Apply f to each item on as many threads as there are CPUs, returning the
+/// results in the order of the items. Messages logged by f are printed in
+/// that order too, as soon as those for the items before are.
+pub fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(1, usize::from);
+    map_on(threads, items, f)
+}
+
+fn map_on<T: Sync, R: Send>(threads: usize, items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    // Messages of each item not yet printed, and the first of those.
+    let logs: Mutex<(usize, Vec<Option<Captured>>)> =
+        Mutex::new((0, items.iter().map(|_| None).collect()));
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let (result, captured) = log::capture(|| f(item));
+                    results.lock().unwrap()[i] = Some(result);
+
+                    let mut logs = logs.lock().unwrap();
+                    let (first, pending) = &mut *logs;
+                    pending[i] = Some(captured);
+                    while let Some(captured) = pending.get_mut(*first).and_then(Option::take) {
+                        captured.replay();
+                        *first += 1;
+                    }
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is mapped"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_map_in_order() {
+        let items: Vec<usize> = (0..1000).collect();
+        let doubled: Vec<usize> = (0..2000).step_by(2).collect();
+        for threads in [1, 4] {
+            assert_eq!(map_on(threads, &items, |n| n * 2), doubled);
+            assert_eq!(
+                map_on(threads, &[] as &[usize], |n| *n),
+                Vec::<usize>::new()
+            );
+        }
+    }
+}
@@ -1,12 +1,17 @@
-use std::{ffi::OsStr, path::Path};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 use crate::{
+    config::Config,
     log,
-    model::WikiTree,
-    render::{capitalise, INDEX_FILE, OUTPUT_EXT},
+    model::{Doc, Id, Meta, WikiTree},
+    parallel,
+    render::{INDEX_FILE, OUTPUT_EXT, capitalise},
 };
 
-const INPUT_EXT: &str = "md";
+pub const INPUT_EXT: &str = "md";
 pub const IMAGE_EXTS: &[&str] = &["jpg", "jpeg", "png"];
 
 fn is_hidden(path: &Path) -> bool {
@@ -24,26 +29,51 @@ fn title_from_filename(filename: &str) -> String {
         .unwrap_or_else(|| capitalise(filename))
 }
 
-pub fn process_document(tree: &mut WikiTree, parent: usize, file: &Path) {
+/// Parse a markdown file into the file name of its output, its title and its
+/// document. None if it can't be read, or is a draft which isn't rendered.
+pub fn read_document(config: &Config, file: &Path) -> Option<(String, String, Doc)> {
     let Some(Some(name)) = file.file_name().map(std::ffi::OsStr::to_str) else {
         log::error(format!(
             "Couldn't find file name for file: {}",
             file.display()
         ));
-        return;
+        return None;
     };
 
     let Ok(markdown) = std::fs::read_to_string(file) else {
         log::error(format!("Failed to read input file: {}", file.display()));
-        return;
+        return None;
+    };
+
+    let (markdown, meta) = match super::meta::parse_front_matter(&markdown) {
+        (rest, Ok(meta)) => (rest, meta),
+        (rest, Err(e)) => {
+            log::warning(format!("Invalid front matter in {}: {e}", file.display()));
+            (rest, Meta::default())
+        }
     };
 
+    if meta.draft() && !config.drafts {
+        log::info(format!("Skipping draft: {}", file.display()));
+        return None;
+    }
+
     let filename = name.replace(&format!(".{INPUT_EXT}"), &format!(".{OUTPUT_EXT}"));
-    let document = super::md::parse_document(&markdown);
+    let document = super::md::parse_document(markdown).with_meta(meta);
     let title = document
-        .title_from_heading()
+        .meta()
+        .title()
+        .or_else(|| document.title_from_heading())
         .unwrap_or_else(|| title_from_filename(&filename));
+    Some((filename, title, document))
+}
 
+/// Add a parsed document to the tree, as an index if it is one.
+fn add_document(
+    tree: &mut WikiTree,
+    parent: Id,
+    (filename, title, document): (String, String, Doc),
+) {
     if filename == INDEX_FILE {
         tree.add_index(parent, filename, title, document);
     } else {
@@ -51,52 +81,85 @@ pub fn process_document(tree: &mut WikiTree, parent: usize, file: &Path) {
     }
 }
 
-pub fn process_directory(
-    tree: &mut WikiTree,
-    tree_exclude: bool,
-    parent: usize,
-    indir: &Path,
-    outdir: &Path,
-) {
+pub fn process_document(config: &Config, tree: &mut WikiTree, parent: Id, file: &Path) {
+    if let Some(document) = read_document(config, file) {
+        add_document(tree, parent, document);
+    }
+}
+
+/// A directory or file of the wiki.
+enum Entry {
+    Dir(String),
+    Doc(PathBuf),
+    Media(String, PathBuf),
+}
+
+/// Find the entries in a directory and those within it, each with the index
+/// of the directory it's in among them, or None if in the root.
+fn find_entries(indir: &Path, parent: Option<usize>, entries: &mut Vec<(Option<usize>, Entry)>) {
     let Ok(dir) = std::fs::read_dir(indir) else {
         log::error(format!("Couldn't read directory: {}", indir.display()));
         return;
     };
 
-    let Some(name) = indir.file_name() else {
-        log::error(format!("Couldn't read file name of {}", indir.display()));
-        return;
-    };
-
-    let node = if tree_exclude {
-        WikiTree::ROOT
-    } else {
-        tree.add_dir(parent, name.to_string_lossy())
-    };
-
     for entry in dir.flatten() {
         if let Ok(filetype) = entry.file_type() {
             let file_path = entry.path();
             if is_hidden(&file_path) {
             } else if filetype.is_dir() {
                 let name = entry.file_name();
-                process_directory(tree, false, node, &indir.join(&name), &outdir.join(&name));
-            } else if filetype.is_file() {
-                if let Some(Some(ext)) = file_path.extension().map(OsStr::to_str) {
-                    if ext == INPUT_EXT {
-                        process_document(tree, node, &file_path);
-                    } else if IMAGE_EXTS.contains(&ext) {
-                        if let Some(name) = file_path
-                            .file_name()
-                            .and_then(OsStr::to_str)
-                            .map(|s| s.to_string())
-                        {
-                            let title = title_from_filename(&name);
-                            tree.add_media(node, name, title, file_path);
-                        }
-                    }
+                entries.push((parent, Entry::Dir(name.to_string_lossy().into_owned())));
+                find_entries(&indir.join(&name), Some(entries.len() - 1), entries);
+            } else if filetype.is_file()
+                && let Some(Some(ext)) = file_path.extension().map(OsStr::to_str)
+            {
+                if ext == INPUT_EXT {
+                    entries.push((parent, Entry::Doc(file_path)));
+                } else if IMAGE_EXTS.contains(&ext)
+                    && let Some(name) = file_path
+                        .file_name()
+                        .and_then(OsStr::to_str)
+                        .map(|s| s.to_string())
+                {
+                    entries.push((parent, Entry::Media(name, file_path)));
                 }
             }
         }
     }
 }
+
+/// Add the contents of a directory to the root of the tree, parsing its
+/// documents in parallel.
+pub fn process_directory(config: &Config, tree: &mut WikiTree, indir: &Path) {
+    let mut entries = Vec::new();
+    find_entries(indir, None, &mut entries);
+
+    let files: Vec<&Path> = entries
+        .iter()
+        .filter_map(|(_, entry)| match entry {
+            Entry::Doc(path) => Some(path.as_path()),
+            _ => None,
+        })
+        .collect();
+    let mut documents = parallel::map(&files, |file| read_document(config, file)).into_iter();
+
+    // Node of each entry, once added to the tree.
+    let mut nodes = Vec::with_capacity(entries.len());
+    for (parent, entry) in entries {
+        let parent = parent.map_or(WikiTree::ROOT, |i| nodes[i]);
+        let node = match entry {
+            Entry::Dir(name) => tree.add_dir(parent, name),
+            Entry::Doc(_) => {
+                if let Some(Some(document)) = documents.next() {
+                    add_document(tree, parent, document);
+                }
+                parent
+            }
+            Entry::Media(name, path) => {
+                let title = title_from_filename(&name);
+                tree.add_media(parent, name, title, path)
+            }
+        };
+        nodes.push(node);
+    }
+}
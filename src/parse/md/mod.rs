@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use crate::model::{Doc, El, HEADING_MAX_LEVEL, Node, Style, Tag};
+use crate::model::{Align, Callout, Doc, El, HEADING_MAX_LEVEL, Node, Style, Tag};
 
 #[cfg(test)]
 mod test;
@@ -14,7 +14,7 @@ fn add_node(to: &mut Vec<Node>, node: Node) {
 }
 
 fn is_empty(input: &str) -> bool {
-    return input.trim().is_empty();
+    input.trim().is_empty()
 }
 
 fn drop_n(input: &str, n: usize) -> &str {
@@ -74,7 +74,7 @@ fn second_char_matches(input: &str, pred: impl Fn(char) -> bool) -> bool {
     input.chars().nth(1).map(pred).unwrap_or(false)
 }
 
-fn consume<'a, P>(input: &'a str, condition: P) -> (&'a str, &'a str)
+fn consume<P>(input: &str, condition: P) -> (&str, &str)
 where
     P: std::str::pattern::Pattern,
 {
@@ -119,6 +119,21 @@ fn parse_tags(input: &str) -> (&str, Vec<Tag>) {
     (rest, tags)
 }
 
+/// Lines consisting of only one of these markers are replaced with the page's
+/// table of contents.
+const TOC_MARKERS: [&str; 2] = ["[toc]", "#toc"];
+
+fn is_toc_marker(input: &str) -> bool {
+    let (_, line) = consume(input, '\n');
+    TOC_MARKERS
+        .iter()
+        .any(|marker| line.trim().eq_ignore_ascii_case(marker))
+}
+
+fn parse_toc_marker(input: &str) -> (&str, Node) {
+    (consume(input, '\n').0, Node::toc())
+}
+
 fn parse_heading_or_tags(input: &str) -> (&str, Node) {
     if input
         .chars()
@@ -170,6 +185,13 @@ fn parse_text(input: &str) -> (&str, Node) {
         (rest, text) = consume(rest.trim_start(), |c| CONTROL.contains(c) || c == '\n');
         node.add_text(text);
 
+        // List item or blockquote on the following line, parse separately.
+        if first_char(rest) == Some('\n')
+            && (interrupts_paragraph(rest) || first_solid(rest) == Some('>'))
+        {
+            break;
+        }
+
         // Control character, parse separately.
         if starts_with_any(rest.trim_start(), CONTROL) {
             if first_solid(rest) == Some('!') {
@@ -197,36 +219,118 @@ fn parse_text(input: &str) -> (&str, Node) {
     (rest, node)
 }
 
-fn list_prefix_size(input: &str) -> Option<usize> {
-    let mut n = 0;
-    for c in input.chars() {
+const BULLETS: &str = "*-+";
+const ORDERED_DELIMITERS: &str = ".)";
+const ORDERED_MAX_DIGITS: usize = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ListKind {
+    Bullet(char),         // (bullet)
+    Ordered(usize, char), // (number, delimiter)
+}
+
+impl ListKind {
+    /// Whether an item with this marker continues a list started by an item
+    /// with the other marker. As in CommonMark, changing the bullet character
+    /// or the delimiter after the number starts a new list.
+    fn continues(self, other: ListKind) -> bool {
+        match (self, other) {
+            (ListKind::Bullet(a), ListKind::Bullet(b)) => a == b,
+            (ListKind::Ordered(_, a), ListKind::Ordered(_, b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+struct ListMarker<'a> {
+    indent: usize,
+    kind: ListKind,
+    rest: &'a str,
+}
+
+/// If the first non-empty line of input begins with a list marker (`*`, `-`,
+/// `+`, `1.` or `1)` followed by whitespace), returns the indentation of the
+/// marker, the kind of list it starts and the input following the marker.
+fn list_marker(input: &str) -> Option<ListMarker<'_>> {
+    let mut indent = 0;
+    for (i, c) in input.char_indices() {
         match c {
-            '*' => return Some(n),
-            '\n' => n = 0,
-            _ if c.is_whitespace() => n += 1,
-            _ => break,
+            '\n' => indent = 0,
+            _ if c.is_whitespace() => indent += 1,
+            _ => {
+                let line = &input[i..];
+                let (kind, len) = if BULLETS.contains(c) {
+                    (ListKind::Bullet(c), 1)
+                } else {
+                    let (after, digits) = consume(line, |c: char| !c.is_ascii_digit());
+                    let delimiter = first_char(after)?;
+                    if digits.is_empty()
+                        || digits.len() > ORDERED_MAX_DIGITS
+                        || !ORDERED_DELIMITERS.contains(delimiter)
+                    {
+                        return None;
+                    }
+                    (
+                        ListKind::Ordered(digits.parse().ok()?, delimiter),
+                        digits.len() + 1,
+                    )
+                };
+
+                let rest = drop_n(line, len);
+                return if first_char(rest).is_none_or(char::is_whitespace) {
+                    Some(ListMarker { indent, kind, rest })
+                } else {
+                    None
+                };
+            }
         }
     }
     None
 }
 
+/// Whether a list beginning the next line of input ends a paragraph. As in
+/// CommonMark, only ordered lists starting from 1 may do so, so that a line
+/// beginning with e.g. a year isn't mistaken for a list.
+fn interrupts_paragraph(input: &str) -> bool {
+    list_marker(input)
+        .is_some_and(|marker| matches!(marker.kind, ListKind::Bullet(_) | ListKind::Ordered(1, _)))
+}
+
+fn list_prefix_size(input: &str) -> Option<usize> {
+    list_marker(input).map(|marker| marker.indent)
+}
+
+/// Length of the text belonging to a list item before the next list item or
+/// empty line.
+fn list_item_text_len(input: &str) -> usize {
+    for (i, _) in input.match_indices('\n') {
+        let rest = &input[i..];
+        if starts_with_empty_line(rest) || list_marker(rest).is_some() {
+            return i;
+        }
+    }
+    input.len()
+}
+
 fn parse_list_item(input: &str) -> (&str, Node) {
-    let prefix_size = list_prefix_size(input);
+    let Some(marker) = list_marker(input) else {
+        return parse_text(input);
+    };
     let mut nodes = Vec::new();
-    let mut rest = drop_first(consume(input, '*').0);
+    let mut rest = marker.rest;
 
     while !is_empty(rest) && !starts_with_empty_line(rest) {
-        while !is_empty(rest) && first_char(rest) != Some('\n') {
-            let node;
-            (rest, node) = parse_node(rest);
+        let len = list_item_text_len(rest);
+        for node in parse(&rest[..len], false) {
             add_node(&mut nodes, node);
         }
+        rest = &rest[len..];
 
-        if first_solid(rest) == Some('*') && list_prefix_size(rest) <= prefix_size {
+        if list_prefix_size(rest).is_some_and(|size| size <= marker.indent) {
             return (rest, Node::item(nodes));
         }
 
-        if starts_with_empty_line(rest) {
+        if is_empty(rest) || starts_with_empty_line(rest) {
             break;
         }
 
@@ -238,21 +342,51 @@ fn parse_list_item(input: &str) -> (&str, Node) {
 }
 
 fn parse_list(input: &str) -> (&str, Node) {
+    let Some(first) = list_marker(input) else {
+        return parse_text(input);
+    };
     let mut nodes = Vec::new();
 
-    let prefix_size = list_prefix_size(input);
-
     let mut rest = input;
-    while list_prefix_size(rest) == prefix_size {
+    while let Some(marker) = list_marker(rest)
+        && marker.indent == first.indent
+        && marker.kind.continues(first.kind)
+    {
         let node;
         (rest, node) = parse_list_item(rest);
         add_node(&mut nodes, node);
     }
 
-    (rest, Node::list(nodes))
+    match first.kind {
+        ListKind::Bullet(_) => (rest, Node::list(nodes)),
+        ListKind::Ordered(start, _) => (rest, Node::ordered_list(start, nodes)),
+    }
+}
+
+/// Parse a wiki link like `[[Target]]`, `[[Target|Text]]` or
+/// `[[Target#Section]]`, if the input begins with one.
+fn parse_wiki_link(input: &str) -> Option<(&str, Node)> {
+    let inner = input.trim_start().strip_prefix("[[")?;
+    let (rest, inner) = consume(inner, "]]");
+    if rest.is_empty() || inner.contains('\n') || is_empty(inner) {
+        return None;
+    }
+
+    let node = if let Some((target, text)) = inner.split_once('|') {
+        Node::wiki_link(target, text)
+    } else if let Some(section) = inner.trim_start().strip_prefix('#') {
+        Node::wiki_link(inner, section)
+    } else {
+        Node::wiki_link(inner, &inner.replace('#', " > "))
+    };
+    Some((drop_n(rest, 2), node))
 }
 
 fn parse_link(input: &str) -> (&str, Node) {
+    if let Some(result) = parse_wiki_link(input) {
+        return result;
+    }
+
     let mut rest = drop_first(input.trim_start());
     let text;
     (rest, text) = consume(rest, ']');
@@ -278,14 +412,24 @@ fn parse_link(input: &str) -> (&str, Node) {
     (rest, node) = parse_text(rest);
 
     if let El::Text(text) = node.el_mut() {
-        node = Node::text(&format!("{} {}", &input[..consumed].trim(), &text.trim()));
+        node = Node::text(&format!("{} {}", input[..consumed].trim(), text.trim()));
     }
     (rest, node)
 }
 
 fn parse_image(input: &str) -> (&str, Node) {
     let (rest, result) = parse_link(drop_first(input.trim_start()));
-    if let (Some(text), Some(url)) = (result.el_text(), result.el_url()) {
+    if let El::WikiLink(target, text) = result.el() {
+        // As in Obsidian, `![[image.png|300]]` sets the width of the image.
+        if !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()) {
+            (
+                rest,
+                Node::wiki_image(target, target).with_attr("width", text),
+            )
+        } else {
+            (rest, Node::wiki_image(target, text))
+        }
+    } else if let (Some(text), Some(url)) = (result.el_text(), result.el_url()) {
         (rest, Node::image(text, url))
     } else {
         (rest, result)
@@ -330,26 +474,109 @@ fn parse_row(input: &str) -> (&str, Vec<Vec<Node>>) {
     while !rest.is_empty() && !rest.starts_with('\n') {
         let text;
         (rest, text) = consume_until_any(rest, "|\n");
-        cols.push(parse(text, false));
         if rest.starts_with('|') {
             rest = drop_first(rest);
+        } else if is_empty(text) {
+            // Trailing whitespace after the closing '|'.
+            break;
         }
+        cols.push(parse(text, false));
     }
 
     (rest, cols)
 }
 
+fn parse_alignment(cell: &str) -> Option<Align> {
+    let cell = cell.trim();
+    let dashes = cell.trim_matches(':');
+    if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+        return None;
+    }
+
+    let left = cell.starts_with(':');
+    let right = cell.len() > 1 && cell.ends_with(':');
+    Some(match (left, right) {
+        (true, true) => Align::Center,
+        (true, false) => Align::Left,
+        (false, true) => Align::Right,
+        (false, false) => Align::None,
+    })
+}
+
+/// Parse a delimiter row like `|---|:---:|` from the line following a table
+/// header, returning the alignment of each column. It must have a cell for
+/// each of the header's columns.
+fn parse_delimiter_row(input: &str, columns: usize) -> Option<(&str, Vec<Align>)> {
+    if !input.starts_with('\n') {
+        return None;
+    }
+
+    let (rest, line) = consume(drop_first(input), '\n');
+    let line = line.trim();
+    if !line.starts_with('|') {
+        return None;
+    }
+
+    let line = drop_first(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let align = line
+        .split('|')
+        .map(parse_alignment)
+        .collect::<Option<Vec<Align>>>()?;
+    (align.len() == columns).then_some((rest, align))
+}
+
 fn parse_table(input: &str) -> (&str, Node) {
-    let mut rest = input;
-    let mut rows = Vec::new();
-    while {
+    let (mut rest, first) = parse_row(input);
+    let (align, header, mut rows) = match parse_delimiter_row(rest, first.len()) {
+        Some((after, align)) => {
+            rest = after;
+            (align, first, Vec::new())
+        }
+        None => (Vec::new(), Vec::new(), vec![first]),
+    };
+
+    while rest.starts_with('\n') && !starts_with_empty_line(rest) && first_solid(rest) == Some('|')
+    {
         let row;
         (rest, row) = parse_row(rest);
         rows.push(row);
-        rest.starts_with('\n') && first_solid(rest) == Some('|')
-    } {}
+    }
+
+    if header.is_empty() {
+        (rest, Node::table(rows))
+    } else {
+        (rest, Node::table_with_header(align, header, rows))
+    }
+}
+
+/// Parse a callout marker like `[!NOTE]` from the first line of a blockquote,
+/// returning the kind of callout and the remainder of the blockquote.
+fn parse_callout(input: &str) -> (&str, Option<Callout>) {
+    let trimmed = input.trim_start();
+    if let Some(marker) = trimmed.strip_prefix("[!") {
+        let (rest, name) = consume(marker, |c: char| c == ']' || c == '\n');
+        if first_char(rest) == Some(']')
+            && let Some(callout) = Callout::from_name(name)
+        {
+            return (drop_first(rest), Some(callout));
+        }
+    }
+    (input, None)
+}
+
+fn parse_blockquote(input: &str) -> (&str, Node) {
+    let mut rest = input;
+    let mut quoted = String::new();
+    while !starts_with_empty_line(rest) && first_solid(rest) == Some('>') {
+        let line;
+        (rest, line) = consume(drop_first(consume(rest, '>').0), '\n');
+        quoted.push_str(line.strip_prefix(' ').unwrap_or(line));
+        quoted.push('\n');
+    }
 
-    (rest, Node::table(rows))
+    let (body, callout) = parse_callout(&quoted);
+    (rest, Node::blockquote(callout, parse(body, true)))
 }
 
 fn _parse_node(input: &str, at_line_start: bool) -> (&str, Node) {
@@ -361,12 +588,14 @@ fn _parse_node(input: &str, at_line_start: bool) -> (&str, Node) {
     match first_solid(rest) {
         None => ("", Node::empty()),
         Some('`') => parse_code(rest),
+        Some(_) if at_line_start && list_marker(rest).is_some() => parse_list(rest),
+        Some('[') | Some('#') if at_line_start && is_toc_marker(rest) => parse_toc_marker(rest),
         Some('#') => parse_heading_or_tags(rest),
-        Some('*') if at_line_start => parse_list(rest),
         Some('*') | Some('~') => parse_style(rest),
         Some('[') => parse_link(rest),
         Some('!') if nth_solid(rest, 2) == Some('[') => parse_image(rest),
         Some('|') if at_line_start => parse_table(rest),
+        Some('>') if at_line_start => parse_blockquote(rest),
         _ => parse_text(rest),
     }
 }
@@ -1,4 +1,4 @@
-use crate::model::{Doc, Node, Style};
+use crate::model::{Align, Callout, Doc, El, Node, Style};
 
 #[test]
 fn test_parse_heading() {
@@ -223,8 +223,13 @@ fn test_last_el_image() {
 fn test_parse_doc_tags() {
     assert_eq!(
         super::parse_document("#tag1 #tag2 #tag3\n# My page"),
-        Doc::from(vec![Node::heading(1, vec![Node::text("My page")])
-            .with_tags(vec!["tag1".into(), "tag2".into(), "tag3".into()])],)
+        Doc::from(vec![
+            Node::heading(1, vec![Node::text("My page")]).with_tags(vec![
+                "tag1".into(),
+                "tag2".into(),
+                "tag3".into()
+            ])
+        ],)
     )
 }
 
@@ -232,14 +237,280 @@ fn test_parse_doc_tags() {
 fn test_parse_inline_tags() {
     assert_eq!(
         super::parse_document("#headingtag # My #styletag *page*"),
-        Doc::from(vec![Node::heading(
+        Doc::from(vec![
+            Node::heading(
+                1,
+                vec![
+                    Node::text("My"),
+                    Node::style(Style::Italic, vec![Node::text("page")])
+                        .with_tags(vec!["styletag".into()])
+                ]
+            )
+            .with_tags(vec!["headingtag".into()])
+        ])
+    )
+}
+
+#[test]
+fn test_parse_table_header() {
+    assert_eq!(
+        super::parse_document("| Name | Level | Gold |\n|:---|:-:|--:|\n| Aria | 3 | 40 |"),
+        Doc::from(vec![Node::table_with_header(
+            vec![Align::Left, Align::Center, Align::Right],
+            vec![
+                vec![Node::text("Name")],
+                vec![Node::text("Level")],
+                vec![Node::text("Gold")]
+            ],
+            vec![vec![
+                vec![Node::text("Aria")],
+                vec![Node::text("3")],
+                vec![Node::text("40")]
+            ]]
+        )])
+    )
+}
+
+#[test]
+fn test_parse_table_delimiter_not_row() {
+    let doc = super::parse_document("| a | b |\n| --- | --- |\n| c | d |\n| e | f |");
+    let [node] = doc.nodes() else {
+        panic!("Expected a single table node.");
+    };
+    let El::Table(align, header, rows) = node.el() else {
+        panic!("Expected a table.");
+    };
+    assert_eq!(align, &vec![Align::None, Align::None]);
+    assert_eq!(header.len(), 2);
+    assert_eq!(rows.len(), 2);
+}
+
+#[test]
+fn test_parse_table_delimiter_columns() {
+    let doc = super::parse_document("| a | b |\n| --- |\n| c | d |");
+    let [node] = doc.nodes() else {
+        panic!("Expected a single table node.");
+    };
+    let El::Table(align, header, rows) = node.el() else {
+        panic!("Expected a table.");
+    };
+    assert!(align.is_empty());
+    assert!(header.is_empty());
+    assert_eq!(rows.len(), 3);
+}
+
+#[test]
+fn test_parse_table_ends_at_empty_line() {
+    assert_eq!(
+        super::parse_document("| a |  \n\n| b |"),
+        Doc::from(vec![
+            Node::table(vec![vec![vec![Node::text("a")]]]),
+            Node::table(vec![vec![vec![Node::text("b")]]])
+        ])
+    )
+}
+
+#[test]
+fn test_ordered_list() {
+    assert_eq!(
+        super::parse_document("3. Third\n4. Fourth\n\nAfter"),
+        Doc::from(vec![
+            Node::ordered_list(3, vec![Node::text("Third"), Node::text("Fourth")]),
+            Node::text("After")
+        ])
+    )
+}
+
+#[test]
+fn test_list_markers() {
+    assert_eq!(
+        super::parse_document("- dash\n- dash\n+ plus\n1) paren\n2) paren"),
+        Doc::from(vec![
+            Node::list(vec![Node::text("dash"), Node::text("dash")]),
+            Node::list(vec![Node::text("plus")]),
+            Node::ordered_list(1, vec![Node::text("paren"), Node::text("paren")])
+        ])
+    )
+}
+
+#[test]
+fn test_mixed_nested_lists() {
+    assert_eq!(
+        super::parse_document(
+            r#"
+                1. Prepare
+                    * Sharpen sword
+                    * Pack rations
+                2. Travel
+                    1. North
+                    2. East
+            "#
+        ),
+        Doc::from(vec![Node::ordered_list(
             1,
             vec![
-                Node::text("My"),
-                Node::style(Style::Italic, vec![Node::text("page")])
-                    .with_tags(vec!["styletag".into()])
+                Node::item(vec![
+                    Node::text("Prepare"),
+                    Node::list(vec![
+                        Node::text("Sharpen sword"),
+                        Node::text("Pack rations")
+                    ])
+                ]),
+                Node::item(vec![
+                    Node::text("Travel"),
+                    Node::ordered_list(1, vec![Node::text("North"), Node::text("East")])
+                ])
             ]
-        )
-        .with_tags(vec!["headingtag".into()])])
+        )])
+    )
+}
+
+#[test]
+fn test_list_after_text() {
+    assert_eq!(
+        super::parse_document("Steps:\n- one\n- two"),
+        Doc::from(vec![
+            Node::text("Steps:"),
+            Node::list(vec![Node::text("one"), Node::text("two")])
+        ])
     )
 }
+
+#[test]
+fn test_not_list() {
+    assert_eq!(
+        super::parse_document("In the year\n1066. things happened\n-not a list"),
+        Doc::from(vec![Node::text(
+            "In the year 1066. things happened -not a list"
+        )])
+    );
+    assert_eq!(
+        super::parse_document("*italic* text"),
+        Doc::from(vec![
+            Node::style(Style::Italic, vec![Node::text("italic")]),
+            Node::text("text")
+        ])
+    );
+}
+
+#[test]
+fn test_blockquote() {
+    assert_eq!(
+        super::parse_document("Before\n> Quoted text\n> continues\n\nAfter"),
+        Doc::from(vec![
+            Node::text("Before"),
+            Node::blockquote(None, vec![Node::text("Quoted text continues")]),
+            Node::text("After")
+        ])
+    )
+}
+
+#[test]
+fn test_nested_blockquote() {
+    assert_eq!(
+        super::parse_document(
+            r#"
+                > ## Letter
+                > * First point
+                > * Second point
+                >
+                > > Quoted within a quote
+                > ```
+                > code
+                > ```
+            "#
+        ),
+        Doc::from(vec![Node::blockquote(
+            None,
+            vec![
+                Node::heading(2, vec![Node::text("Letter")]),
+                Node::list(vec![Node::text("First point"), Node::text("Second point")]),
+                Node::blockquote(None, vec![Node::text("Quoted within a quote")]),
+                Node::codeblock(None, "code")
+            ]
+        )])
+    )
+}
+
+#[test]
+fn test_callout() {
+    assert_eq!(
+        super::parse_document("> [!WARNING]\n> Here be dragons."),
+        Doc::from(vec![Node::blockquote(
+            Some(Callout::Warning),
+            vec![Node::text("Here be dragons.")]
+        )])
+    );
+    assert_eq!(
+        super::parse_document("> [!unknown] text"),
+        Doc::from(vec![Node::blockquote(
+            None,
+            vec![Node::text("[!unknown] text")]
+        )])
+    );
+}
+
+#[test]
+fn test_parse_wiki_links() {
+    assert_eq!(
+        super::parse_document("See [[The Capital]], [[city|the city]] and [[City#Market Square]]."),
+        Doc::from(vec![
+            Node::text("See"),
+            Node::wiki_link("The Capital", "The Capital"),
+            Node::text(","),
+            Node::wiki_link("city", "the city"),
+            Node::text("and"),
+            Node::wiki_link("City#Market Square", "City > Market Square"),
+            Node::text(".")
+        ])
+    )
+}
+
+#[test]
+fn test_parse_wiki_section_link() {
+    assert_eq!(
+        super::parse_node("[[#History]]").1,
+        Node::wiki_link("#History", "History")
+    )
+}
+
+#[test]
+fn test_parse_wiki_image() {
+    assert_eq!(
+        super::parse_node("![[world-map.png]]").1,
+        Node::wiki_image("world-map.png", "world-map.png")
+    );
+    assert_eq!(
+        super::parse_node("![[world-map.png|The World]]").1,
+        Node::wiki_image("world-map.png", "The World")
+    );
+    assert_eq!(
+        super::parse_node("![[world-map.png|300]]").1,
+        Node::wiki_image("world-map.png", "world-map.png").with_attr("width", "300")
+    );
+}
+
+#[test]
+fn test_parse_not_wiki_link() {
+    assert_eq!(
+        super::parse_document("[[unclosed link"),
+        Doc::from(vec![Node::text("[[unclosed link")])
+    )
+}
+
+#[test]
+fn test_parse_toc_marker() {
+    let heading = Node::heading(1, vec![Node::text("Title")]);
+    assert_eq!(
+        super::parse_document("# Title\n[TOC]\nText"),
+        Doc::from(vec![heading, Node::toc(), Node::text("Text")])
+    );
+    assert_eq!(
+        super::parse_document("#toc\n\n## Section").nodes()[0],
+        Node::toc()
+    );
+    assert_eq!(
+        super::parse_document("#toc ## Section").nodes()[0],
+        Node::heading(2, vec![Node::text("Section")]).with_tags(vec!["toc".into()])
+    );
+}
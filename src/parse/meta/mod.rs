@@ -0,0 +1,318 @@
+use crate::model::{Meta, Value};
+
+#[cfg(test)]
+mod test;
+
+const YAML_FENCE: &str = "---";
+const YAML_END: &str = "...";
+const TOML_FENCE: &str = "+++";
+
+type MetaParser = fn(&str) -> Result<Meta, String>;
+
+/// Split front matter, fenced by `---` (YAML) or `+++` (TOML) lines, from the
+/// start of a document. Returns the remainder of the document and the parsed
+/// metadata, which is empty if the document has no front matter.
+pub fn parse_front_matter(input: &str) -> (&str, Result<Meta, String>) {
+    let trimmed = input.trim_start_matches('\u{feff}');
+    let Some((first, block)) = trimmed.split_once('\n') else {
+        return (input, Ok(Meta::default()));
+    };
+
+    let (fence, parser): (&str, MetaParser) = match first.trim_end() {
+        YAML_FENCE => (YAML_FENCE, parse_yaml),
+        TOML_FENCE => (TOML_FENCE, parse_toml),
+        _ => return (input, Ok(Meta::default())),
+    };
+
+    let mut offset = 0;
+    for line in block.split_inclusive('\n') {
+        let end = line.trim_end();
+        if end == fence || (fence == YAML_FENCE && end == YAML_END) {
+            let rest = &block[(offset + line.len())..];
+
+            // Leading line in place of the opening fence so that line numbers
+            // in errors match those of the document.
+            return (rest, parser(&format!("\n{}", &block[..offset])));
+        }
+        offset += line.len();
+    }
+
+    (input, Err(format!("no closing `{fence}` found")))
+}
+
+/// Parse a TOML document. Tables are flattened, so that `key` in the table
+/// `[section]` is stored as `section.key`.
+pub fn parse_toml(input: &str) -> Result<Meta, String> {
+    let mut meta = Meta::default();
+    let mut section = String::new();
+    let mut pending: Option<(String, String)> = None;
+
+    for (i, line) in input.lines().enumerate() {
+        let line_no = i + 1;
+        let content = strip_comment(line).trim();
+
+        if let Some((key, mut value)) = pending.take() {
+            value.push(' ');
+            value.push_str(content);
+            if bracket_depth(&value) > 0 {
+                pending = Some((key, value));
+            } else {
+                meta.insert(key, parse_toml_value(&value).map_err(|e| at(line_no, e))?);
+            }
+            continue;
+        }
+
+        if content.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = content.strip_prefix('[') {
+            let Some(name) = header.strip_suffix(']') else {
+                return Err(at(line_no, "unterminated table header"));
+            };
+            if name.starts_with('[') {
+                return Err(at(line_no, "arrays of tables are not supported"));
+            }
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let Some((key, value)) = content.split_once('=') else {
+            return Err(at(line_no, "expected `key = value`"));
+        };
+
+        let key = unquote(key.trim());
+        if key.is_empty() {
+            return Err(at(line_no, "missing key"));
+        }
+        let key = if section.is_empty() {
+            key.to_string()
+        } else {
+            format!("{section}.{key}")
+        };
+
+        let value = value.trim();
+        if bracket_depth(value) > 0 {
+            pending = Some((key, value.to_string()));
+        } else {
+            meta.insert(key, parse_toml_value(value).map_err(|e| at(line_no, e))?);
+        }
+    }
+
+    if let Some((key, _)) = pending {
+        Err(format!("unterminated array for `{key}`"))
+    } else {
+        Ok(meta)
+    }
+}
+
+/// Parse a subset of YAML sufficient for front matter: `key: value` pairs,
+/// where values may be scalars, inline `[a, b]` lists or block lists of
+/// `- item` lines.
+pub fn parse_yaml(input: &str) -> Result<Meta, String> {
+    let mut meta = Meta::default();
+    let mut list: Option<(String, Vec<Value>)> = None;
+
+    for (i, line) in input.lines().enumerate() {
+        let line_no = i + 1;
+        let content = strip_comment(line).trim_end();
+        let trimmed = content.trim_start();
+
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some(item) = trimmed.strip_prefix('-')
+            && (item.is_empty() || item.starts_with(char::is_whitespace))
+        {
+            let Some((_, items)) = &mut list else {
+                return Err(at(line_no, "list item without a key"));
+            };
+            items.push(parse_yaml_scalar(item.trim()));
+            continue;
+        }
+
+        if content.starts_with(char::is_whitespace) {
+            return Err(at(line_no, "nested values are not supported"));
+        }
+
+        if let Some((key, items)) = list.take() {
+            meta.insert(key, Value::List(items));
+        }
+
+        let Some((key, value)) = content.split_once(':') else {
+            return Err(at(line_no, "expected `key: value`"));
+        };
+
+        let key = unquote(key.trim());
+        if key.is_empty() {
+            return Err(at(line_no, "missing key"));
+        }
+
+        let value = value.trim();
+        if value.is_empty() {
+            list = Some((key.to_string(), Vec::new()));
+        } else if let Some(inner) = value.strip_prefix('[') {
+            let Some(inner) = inner.strip_suffix(']') else {
+                return Err(at(line_no, "unterminated list"));
+            };
+            let items = split_list(inner)
+                .into_iter()
+                .map(parse_yaml_scalar)
+                .collect();
+            meta.insert(key, Value::List(items));
+        } else {
+            meta.insert(key, parse_yaml_scalar(value));
+        }
+    }
+
+    if let Some((key, items)) = list {
+        meta.insert(key, Value::List(items));
+    }
+
+    Ok(meta)
+}
+
+fn at<D: std::fmt::Display>(line: usize, err: D) -> String {
+    format!("line {line}: {err}")
+}
+
+/// Remove a trailing `# comment` from a line, ignoring `#` within quotes or
+/// not preceded by whitespace.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '#') if prev.is_whitespace() => return &line[..i],
+            _ => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+/// Number of unclosed `[` in the input, ignoring those within quotes.
+fn bracket_depth(input: &str) -> isize {
+    let mut quote = None;
+    let mut depth = 0;
+    for c in input.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// Split the contents of a list on commas which aren't within quotes or
+/// nested lists. Empty elements, e.g. from a trailing comma, are dropped.
+fn split_list(input: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(input[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+fn unquote(input: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = input
+            .strip_prefix(quote)
+            .and_then(|s| s.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    input
+}
+
+fn unescape(input: &str) -> String {
+    let mut output = String::new();
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => output.push('\n'),
+                Some('t') => output.push('\t'),
+                Some(c) => output.push(c),
+                None => output.push('\\'),
+            }
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+fn parse_yaml_scalar(input: &str) -> Value {
+    if input.len() >= 2 && input.starts_with('"') && input.ends_with('"') {
+        Value::String(unescape(&input[1..(input.len() - 1)]))
+    } else if input.len() >= 2 && input.starts_with('\'') && input.ends_with('\'') {
+        Value::String(input[1..(input.len() - 1)].replace("''", "'"))
+    } else if input.eq_ignore_ascii_case("true") {
+        Value::Bool(true)
+    } else if input.eq_ignore_ascii_case("false") {
+        Value::Bool(false)
+    } else if let Ok(integer) = input.parse() {
+        Value::Integer(integer)
+    } else {
+        Value::String(input.to_string())
+    }
+}
+
+fn is_date(input: &str) -> bool {
+    let bytes = input.as_bytes();
+    bytes.len() >= 10
+        && bytes[..10].iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+fn parse_toml_value(input: &str) -> Result<Value, String> {
+    if input.len() >= 2 && input.starts_with('"') && input.ends_with('"') {
+        Ok(Value::String(unescape(&input[1..(input.len() - 1)])))
+    } else if input.len() >= 2 && input.starts_with('\'') && input.ends_with('\'') {
+        Ok(Value::String(input[1..(input.len() - 1)].to_string()))
+    } else if let Some(inner) = input.strip_prefix('[') {
+        let Some(inner) = inner.strip_suffix(']') else {
+            return Err(format!("unterminated array `{input}`"));
+        };
+        split_list(inner)
+            .into_iter()
+            .map(parse_toml_value)
+            .collect::<Result<Vec<Value>, String>>()
+            .map(Value::List)
+    } else if input == "true" {
+        Ok(Value::Bool(true))
+    } else if input == "false" {
+        Ok(Value::Bool(false))
+    } else if let Ok(integer) = input.replace('_', "").parse() {
+        Ok(Value::Integer(integer))
+    } else if is_date(input) {
+        Ok(Value::String(input.to_string()))
+    } else {
+        Err(format!("invalid value `{input}`"))
+    }
+}
@@ -0,0 +1,102 @@
+use crate::model::{Meta, Value};
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+#[test]
+fn test_no_front_matter() {
+    let input = "# Heading\n\ntext";
+    assert_eq!(
+        super::parse_front_matter(input),
+        (input, Ok(Meta::default()))
+    );
+}
+
+#[test]
+fn test_yaml_front_matter() {
+    let (rest, meta) = super::parse_front_matter(concat!(
+        "---\n",
+        "title: The Capital # comment\n",
+        "aliases:\n",
+        "  - Capital City\n",
+        "  - \"The City\"\n",
+        "tags: [dm, city]\n",
+        "date: 2024-05-01\n",
+        "draft: false\n",
+        "order: 3\n",
+        "template: 'location'\n",
+        "---\n",
+        "# Heading\n"
+    ));
+    let meta = meta.unwrap();
+
+    assert_eq!(rest, "# Heading\n");
+    assert_eq!(meta.title().as_deref(), Some("The Capital"));
+    assert_eq!(meta.aliases(), vec!["Capital City", "The City"]);
+    assert_eq!(meta.tags(), vec!["dm".into(), "city".into()]);
+    assert_eq!(meta.date(), Some("2024-05-01"));
+    assert!(!meta.draft());
+    assert_eq!(meta.order(), Some(3));
+    assert_eq!(meta.template(), Some("location"));
+}
+
+#[test]
+fn test_toml_front_matter() {
+    let (rest, meta) = super::parse_front_matter(concat!(
+        "+++\n",
+        "title = \"Session \\\"One\\\"\"\n",
+        "tags = [\n",
+        "  \"npc\", # comment\n",
+        "  'villain',\n",
+        "]\n",
+        "date = 2024-05-01\n",
+        "draft = true\n",
+        "+++\n",
+        "text"
+    ));
+    let meta = meta.unwrap();
+
+    assert_eq!(rest, "text");
+    assert_eq!(meta.title().as_deref(), Some("Session \"One\""));
+    assert_eq!(meta.tags(), vec!["npc".into(), "villain".into()]);
+    assert_eq!(meta.date(), Some("2024-05-01"));
+    assert!(meta.draft());
+}
+
+#[test]
+fn test_toml_tables() {
+    let meta = super::parse_toml("title = 'Wiki'\n\n[tags.dm]\nreaders = ['dm']\n").unwrap();
+    assert_eq!(meta.get("title"), Some(&string("Wiki")));
+    assert_eq!(
+        meta.get("tags.dm.readers"),
+        Some(&Value::List(vec![string("dm")]))
+    );
+}
+
+#[test]
+fn test_invalid_front_matter() {
+    let (rest, meta) = super::parse_front_matter("---\ntitle: ok\n  nested: value\n---\nbody");
+    assert_eq!(rest, "body");
+    assert_eq!(
+        meta,
+        Err("line 3: nested values are not supported".to_string())
+    );
+
+    let (_, meta) = super::parse_front_matter("+++\ntitle = unquoted\n+++\n");
+    assert_eq!(meta, Err("line 2: invalid value `unquoted`".to_string()));
+}
+
+#[test]
+fn test_unterminated_front_matter() {
+    let input = "---\ntitle: never closed\n";
+    let (rest, meta) = super::parse_front_matter(input);
+    assert_eq!(rest, input);
+    assert!(meta.is_err());
+}
+
+#[test]
+fn test_numeric_title() {
+    let (_, meta) = super::parse_front_matter("---\ntitle: 1984\n---\n");
+    assert_eq!(meta.unwrap().title().as_deref(), Some("1984"));
+}
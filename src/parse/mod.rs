@@ -1,18 +1,23 @@
 use std::path::Path;
 
-use crate::model::{WikiPage, WikiTree};
+use crate::{
+    config::Config,
+    model::{WikiPage, WikiTree},
+};
 
 mod fs;
 mod md;
+mod meta;
 
 #[cfg(test)]
 pub use self::md::parse_document;
 
-pub use self::fs::IMAGE_EXTS;
+pub use self::fs::{IMAGE_EXTS, INPUT_EXT, read_document};
+pub use self::meta::parse_toml;
 
-pub fn parse_file(path: &Path) -> Result<WikiPage, ()> {
+pub fn parse_file(config: &Config, path: &Path) -> Result<WikiPage, ()> {
     let mut tree = WikiTree::new();
-    fs::process_document(&mut tree, WikiTree::ROOT, path);
+    fs::process_document(config, &mut tree, WikiTree::ROOT, path);
     if let Some(page) = tree.into_pages().into_iter().find(|p| !p.is_root()) {
         Ok(page)
     } else {
@@ -20,14 +25,8 @@ pub fn parse_file(path: &Path) -> Result<WikiPage, ()> {
     }
 }
 
-pub fn parse_directory(path: &Path) -> WikiTree {
+pub fn parse_directory(config: &Config, path: &Path) -> WikiTree {
     let mut tree = WikiTree::new();
-    fs::process_directory(
-        &mut tree,
-        true,
-        WikiTree::ROOT,
-        path,
-        &std::path::PathBuf::from("value"),
-    );
+    fs::process_directory(config, &mut tree, path);
     tree
 }
@@ -0,0 +1,320 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cache::Cache,
+    config::{CONFIG_FILE, Config},
+    log,
+    model::{Backlink, Doc, Id, Tag, WikiPage, WikiTree},
+    parallel,
+    parse::{self, IMAGE_EXTS, INPUT_EXT},
+    render,
+    watch::Watcher,
+};
+
+#[cfg(test)]
+mod test;
+
+/// Title, order and aliases of a page, which other pages list or link by.
+type Names = (String, i64, Vec<String>);
+
+fn names(page: &WikiPage) -> Names {
+    let aliases = page.aliases().into_iter().map(String::from).collect();
+    (page.title().to_string(), page.order(), aliases)
+}
+
+/// Links to each page, indexed by ID.
+fn all_backlinks(tree: &WikiTree) -> Vec<Vec<Backlink>> {
+    tree.pages()
+        .iter()
+        .map(|page| tree.backlinks(page.id()).to_vec())
+        .collect()
+}
+
+/// Pages each page links to, indexed by ID.
+fn outgoing(backlinks: &[Vec<Backlink>]) -> Vec<BTreeSet<Id>> {
+    let mut outgoing = vec![BTreeSet::new(); backlinks.len()];
+    for (target, links) in backlinks.iter().enumerate() {
+        for link in links {
+            outgoing[link.from].insert(target);
+        }
+    }
+    outgoing
+}
+
+fn media_tags(tree: &WikiTree) -> Vec<Vec<Vec<Tag>>> {
+    tree.pages()
+        .iter()
+        .filter(|page| page.is_media())
+        .map(|page| page.tag_groups().to_vec())
+        .collect()
+}
+
+/// Replace the documents of changed pages with their new title and document,
+/// returning the pages whose output could have changed: the pages themselves,
+/// those whose backlinks changed or whose links now lead elsewhere and, if
+/// titles changed, those listing them in the nav tree or an index. None if
+/// the wiki must be rebuilt from scratch, as protection changed.
+fn update_pages(
+    config: &Config,
+    tree: &mut WikiTree,
+    changes: Vec<(Id, String, Doc)>,
+) -> Option<BTreeSet<Id>> {
+    let backlinks = all_backlinks(tree);
+    let media = media_tags(tree);
+
+    let mut pages = BTreeSet::new();
+    let mut renamed = Vec::new();
+    for (id, title, doc) in changes {
+        let page = tree.get(id)?;
+        let (tags, old_names) = (page.tags(), names(page));
+        tree.set_document(id, title, doc);
+
+        let page = tree.get(id)?;
+        if page.tags() != tags {
+            log::info(format!("Tags of {} changed.", page.url()));
+            return None;
+        }
+        if names(page) != old_names {
+            renamed.push(id);
+        }
+        pages.insert(id);
+    }
+
+    render::link_pages(config, tree);
+    let new_backlinks = all_backlinks(tree);
+    let (links, new_links) = (outgoing(&backlinks), outgoing(&new_backlinks));
+    for id in 0..new_backlinks.len() {
+        if backlinks[id] != new_backlinks[id] || links[id] != new_links[id] {
+            pages.insert(id);
+        }
+    }
+
+    if !renamed.is_empty() {
+        // Backlinks list the titles of the pages linking to them.
+        pages.extend(renamed.iter().flat_map(|id| new_links[*id].iter().copied()));
+
+        let generated: Vec<Id> = tree
+            .pages()
+            .iter()
+            .filter(|page| page.is_generated())
+            .map(WikiPage::id)
+            .collect();
+        for id in generated {
+            let Some(dir) = tree.get(id).and_then(|page| tree.get_parent(page)) else {
+                continue;
+            };
+            let children: Vec<&WikiPage> = tree
+                .children(dir.id())
+                .into_iter()
+                .filter(|child| child.id() != id)
+                .collect();
+            let title = dir.title().to_string();
+            let doc = render::create_index(tree, dir, &children);
+            if renamed
+                .iter()
+                .any(|page| tree.get(*page).and_then(WikiPage::parent) == Some(dir.id()))
+            {
+                pages.insert(id);
+            }
+            tree.set_document(id, title, doc);
+        }
+
+        // The nav tree of every page lists every other.
+        if config.nav_tree {
+            let all = tree.pages().iter().filter(|p| p.is_doc() || p.is_index());
+            pages.extend(all.map(WikiPage::id));
+        }
+    }
+
+    render::protect_media(config, tree);
+    if media_tags(tree) != media {
+        log::info("Protection of media changed.");
+        return None;
+    }
+
+    Some(pages)
+}
+
+/// Whether a new file would be part of the wiki.
+fn is_wiki_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext == INPUT_EXT || IMAGE_EXTS.contains(&ext))
+}
+
+/// A wiki built to a directory, kept in memory so that it can be rebuilt as
+/// files change by parsing only those files and rendering only the pages they
+/// affect.
+struct Build<'a> {
+    config: &'a Config,
+    src: &'a Path,
+    outdir: &'a Path,
+    tree: WikiTree,
+    files: HashMap<PathBuf, Id>,
+    cache: Cache,
+}
+
+impl<'a> Build<'a> {
+    fn new(
+        config: &'a Config,
+        src: &'a Path,
+        outdir: &'a Path,
+        mut cache: Cache,
+        tree: WikiTree,
+        files: HashMap<PathBuf, Id>,
+    ) -> Self {
+        crate::write_site(outdir, config, &tree, &files, &mut cache);
+        cache.save();
+        Self {
+            config,
+            src,
+            outdir,
+            tree,
+            files,
+            cache,
+        }
+    }
+
+    /// Rebuild the whole wiki, though only writing files which have changed.
+    fn rebuild_all(&mut self) {
+        log::info("Rebuilding everything.");
+        match crate::load_tree_files(self.config, self.src) {
+            Ok((tree, files)) => {
+                crate::write_site(self.outdir, self.config, &tree, &files, &mut self.cache);
+                self.cache.save();
+                (self.tree, self.files) = (tree, files);
+            }
+            Err(e) => log::error(e),
+        }
+    }
+
+    /// Record the keys of the output of pages just written, so that a later
+    /// build can skip them.
+    fn record(&mut self, ids: &[Id]) {
+        let keys = self.cache.keys(self.config, &self.tree);
+        let keys: Vec<(String, String)> = ids
+            .iter()
+            .filter_map(|id| {
+                let page = self.tree.get(*id)?;
+                let source = self.files.iter().find(|(_, page)| *page == id);
+                let source = source.map(|(path, _)| path.as_path());
+                Some((page.url().to_string(), keys.key(page, source)?))
+            })
+            .collect();
+        for (url, key) in keys {
+            self.cache.insert(&url, key);
+        }
+    }
+
+    /// Rebuild the wiki after files have changed.
+    fn update(&mut self, changed: &[PathBuf]) {
+        let mut docs = Vec::new();
+        let mut media = Vec::new();
+        for path in changed {
+            let page = self.files.get(path).copied().or_else(|| {
+                let mut pages = self.tree.pages().iter();
+                pages
+                    .find(|page| page.file() == Some(path))
+                    .map(WikiPage::id)
+            });
+
+            match page {
+                Some(_) if !path.is_file() => {
+                    log::info(format!("{} was removed.", path.display()));
+                    return self.rebuild_all();
+                }
+                Some(id) if self.files.contains_key(path) => docs.push((id, path)),
+                Some(id) => media.push(id),
+                None if is_wiki_file(path) => {
+                    log::info(format!("{} was added.", path.display()));
+                    return self.rebuild_all();
+                }
+                None if path.file_name().is_some_and(|name| name == CONFIG_FILE) => {
+                    log::warning(format!(
+                        "Changes to {} take effect when restarted.",
+                        path.display()
+                    ));
+                }
+                None => log::debug(format!("Ignoring {}.", path.display())),
+            }
+        }
+
+        if docs.is_empty() && media.is_empty() {
+            return;
+        }
+
+        let mut changes = Vec::new();
+        for (id, path) in docs {
+            log::info(format!("Parsing {}", path.display()));
+            let Some((_, title, doc)) = parse::read_document(self.config, path) else {
+                return self.rebuild_all();
+            };
+            changes.push((id, title, doc));
+        }
+
+        let Some(pages) = update_pages(self.config, &mut self.tree, changes) else {
+            return self.rebuild_all();
+        };
+        // Newly tagged content mustn't be written unencrypted.
+        if let Err(e) = self.config.check_keys(&self.tree.used_tags()) {
+            return log::error(e);
+        }
+        let mut written = Vec::new();
+        for id in &media {
+            if let Some(page) = self.tree.get(*id) {
+                log::info(format!("Copying {}", page.url()));
+                if crate::copy_file(self.config, page, self.outdir) {
+                    written.push(*id);
+                }
+            }
+        }
+        let ids: Vec<Id> = pages.iter().copied().collect();
+        let nav = render::NavTree::new(self.config, &self.tree);
+        let rendered = parallel::map(&ids, |id| {
+            self.tree.get(*id).is_some_and(|page| {
+                crate::render_document(self.outdir, self.config, &self.tree, &nav, page)
+            })
+        });
+        for (id, ok) in ids.into_iter().zip(rendered) {
+            if ok {
+                written.push(id);
+            }
+        }
+        self.record(&written);
+        self.cache.save();
+        if self.config.search && !pages.is_empty() {
+            crate::write_search_index(self.outdir, self.config, &self.tree);
+        }
+        log::info(format!(
+            "Rendered {} pages and copied {} media files.",
+            pages.len(),
+            media.len()
+        ));
+    }
+}
+
+/// Build the wiki in src to outdir from the tree loaded from it, then keep
+/// rebuilding it as files change.
+pub fn watch(
+    config: &Config,
+    src: &Path,
+    outdir: &Path,
+    cache: Cache,
+    tree: WikiTree,
+    files: HashMap<PathBuf, Id>,
+) -> ! {
+    let mut build = Build::new(config, src, outdir, cache, tree, files);
+    log::info(format!(
+        "Rendered {} to {}, watching for changes.",
+        src.display(),
+        outdir.display()
+    ));
+
+    let mut watcher = Watcher::new(src).exclude(outdir);
+    loop {
+        build.update(&watcher.wait());
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    config::Config,
+    model::{Id, Meta, Value, WikiTree},
+    parse::parse_document,
+    render::{INDEX_FILE, create_index, link_pages},
+};
+
+use super::update_pages;
+
+struct Wiki {
+    tree: WikiTree,
+    index: Id,
+    a: Id,
+    b: Id,
+    c: Id,
+    places: Id,
+    town: Id,
+}
+
+fn wiki(config: &Config) -> Wiki {
+    let mut tree = WikiTree::new();
+    let index = tree.add_index(WikiTree::ROOT, INDEX_FILE, "Home", parse_document("# Home"));
+    let a = tree.add_doc(
+        WikiTree::ROOT,
+        "a.html",
+        "A",
+        parse_document("# A\n\n[[B]] is near."),
+    );
+    let b = tree.add_doc(WikiTree::ROOT, "b.html", "B", parse_document("# B"));
+    let c = tree.add_doc(WikiTree::ROOT, "c.html", "C", parse_document("# C"));
+    let dir = tree.add_dir(WikiTree::ROOT, "places");
+    let town = tree.add_doc(dir, "town.html", "Town", parse_document("# Town"));
+    link_pages(config, &mut tree);
+
+    let children = tree.children(dir);
+    let doc = create_index(&tree, tree.get(dir).unwrap(), &children);
+    let places = tree.add_generated_index(dir, INDEX_FILE, "Places", doc);
+    Wiki {
+        tree,
+        index,
+        a,
+        b,
+        c,
+        places,
+        town,
+    }
+}
+
+#[test]
+fn test_update_content() {
+    let config = Config::none();
+    let mut wiki = wiki(&config);
+
+    // Moving a link re-renders the pages which gain and lose a backlink.
+    let doc = parse_document("# A\n\n[[C]] is near.");
+    let changes = vec![(wiki.a, String::from("A"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(pages, BTreeSet::from([wiki.a, wiki.b, wiki.c]));
+    assert!(wiki.tree.backlinks(wiki.b).is_empty());
+    assert_eq!(wiki.tree.backlinks(wiki.c)[0].from, wiki.a);
+
+    // Changing the sentence a link is in changes the backlink.
+    let doc = parse_document("# A\n\n[[C]] is far.");
+    let changes = vec![(wiki.a, String::from("A"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(pages, BTreeSet::from([wiki.a, wiki.c]));
+
+    let doc = parse_document("# B\n\nNothing to see.");
+    let changes = vec![(wiki.b, String::from("B"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(pages, BTreeSet::from([wiki.b]));
+}
+
+#[test]
+fn test_update_title() {
+    let config = Config::none();
+    let mut wiki = wiki(&config);
+
+    // The generated index lists the page, and the page it links to lists it
+    // as a backlink.
+    let doc = parse_document("# Village\n\n[[B]]");
+    let changes = vec![(wiki.town, String::from("Village"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(pages, BTreeSet::from([wiki.b, wiki.town, wiki.places]));
+    let index = wiki.tree.get(wiki.places).unwrap().document().unwrap();
+    assert!(index.nodes()[1].plain_text().contains("Village"));
+
+    // Pages whose links resolve differently are re-rendered.
+    let doc = parse_document("# C\n\n[[Hamlet]]");
+    let changes = vec![(wiki.c, String::from("C"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(pages, BTreeSet::from([wiki.c]));
+    let mut meta = Meta::default();
+    meta.insert(
+        Meta::ALIASES,
+        Value::List(vec![Value::String("Hamlet".into())]),
+    );
+    let doc = parse_document("# Village").with_meta(meta);
+    let changes = vec![(wiki.town, String::from("Village"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(
+        pages,
+        BTreeSet::from([wiki.b, wiki.c, wiki.town, wiki.places])
+    );
+}
+
+#[test]
+fn test_update_nav_tree() {
+    let config = Config {
+        nav_tree: true,
+        ..Config::none()
+    };
+    let mut wiki = wiki(&config);
+
+    let doc = parse_document("# Bee");
+    let changes = vec![(wiki.b, String::from("Bee"), doc)];
+    let pages = update_pages(&config, &mut wiki.tree, changes).unwrap();
+    assert_eq!(
+        pages,
+        BTreeSet::from([wiki.index, wiki.a, wiki.b, wiki.c, wiki.town, wiki.places])
+    );
+}
+
+#[test]
+fn test_update_tags() {
+    let config = Config::none();
+    let mut wiki = wiki(&config);
+
+    let doc = parse_document("#dm\n# B");
+    let changes = vec![(wiki.b, String::from("B"), doc)];
+    assert_eq!(update_pages(&config, &mut wiki.tree, changes), None);
+}
@@ -1,83 +1,365 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{LazyLock, Mutex},
+};
 
 use aes_gcm::{
-    aead::{Aead, OsRng},
     AeadCore, Aes256Gcm, KeyInit,
+    aead::{Aead, OsRng, rand_core::RngCore},
 };
 use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 use crate::log;
 
+pub const SALT_LEN: usize = 16;
+
+/// Name of the key derivation function, as understood by WebCrypto.
+pub const KDF_NAME: &str = "PBKDF2";
+
+type Key = [u8; 32];
+
+/// Password, salt and iterations a key was derived from.
+type KeyParams = (String, Vec<u8>, u32);
+
+/// Keys already derived, by their parameters. Deriving is slow
+/// by design, so is done once per tag rather than once per element.
+static DERIVED_KEYS: LazyLock<Mutex<HashMap<KeyParams, Key>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 fn warn(err: impl Display) {
     log::warning(err)
 }
 
-fn b64_encode(data: &[u8]) -> String {
+pub fn b64_encode(data: &[u8]) -> String {
     base64::engine::general_purpose::STANDARD.encode(data)
 }
 
-fn cipher(key: &str) -> Aes256Gcm {
-    let mut key_bytes: [u8; 32] = [0; 32];
-    for (u, v) in key_bytes.iter_mut().zip(key.as_bytes().iter()) {
-        *u = *v;
+pub fn b64_decode(data: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::STANDARD.decode(data).ok()
+}
+
+pub fn random_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// Derive an AES-256 key from a password using PBKDF2-SHA256. The cache
+/// isn't locked while deriving, so that threads needing other keys aren't held
+/// up, though two threads may derive the same key at once.
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> Key {
+    let params = (password.to_string(), salt.to_vec(), iterations);
+    let cache = || DERIVED_KEYS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = cache().get(&params) {
+        return *key;
+    }
+
+    let key = pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(password.as_bytes(), salt, iterations);
+    *cache().entry(params).or_insert(key)
+}
+
+/// Keys derived from passwords as for encryption, one after another. These
+/// identify the passwords without making them any quicker to guess than from
+/// encrypted content.
+pub fn derive_keys(passwords: &[&str], salt: &[u8], iterations: u32) -> Vec<u8> {
+    passwords
+        .iter()
+        .flat_map(|password| derive_key(password, salt, iterations))
+        .collect()
+}
+
+fn cipher(password: &str, salt: &[u8], iterations: u32) -> Aes256Gcm {
+    Aes256Gcm::new(&derive_key(password, salt, iterations).into())
+}
+
+/// Nonce and ciphertext of data encrypted with the key derived from password,
+/// both base64 encoded.
+fn wrap(data: &[u8], password: &str, salt: &[u8], iterations: u32) -> Result<String, ()> {
+    let cipher = cipher(password, salt, iterations);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, data).map_err(warn)?;
+    Ok(format!(
+        "{}{FIELD_SEP}{}",
+        b64_encode(&nonce),
+        b64_encode(&ciphertext)
+    ))
+}
+
+/// Separators in the description of a content key's shares: shares are
+/// separated by `;`, the readers of a share by `,`, and the tag, nonce and
+/// wrapped share of each reader by `:`.
+pub const SHARE_SEP: char = ';';
+pub const READER_SEP: char = ',';
+pub const FIELD_SEP: char = ':';
+
+pub const NONCE_LEN: usize = 12;
+
+/// Data encrypted with a random content key, along with the shares of that
+/// key wrapped for each of their readers.
+pub struct Sealed {
+    pub nonce: [u8; NONCE_LEN],
+    pub ciphertext: Vec<u8>,
+    pub keys: String,
+}
+
+impl Sealed {
+    /// Layout for binary files: the length of the keys as four big-endian
+    /// bytes, the keys, the nonce and then the ciphertext.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = self.keys.len() as u32;
+        [
+            &len.to_be_bytes(),
+            self.keys.as_bytes(),
+            &self.nonce,
+            &self.ciphertext,
+        ]
+        .concat()
+    }
+
+    /// Read data in the layout written by `to_bytes`, if it is sealed.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (len, rest) = data.split_first_chunk::<4>()?;
+        let len = usize::try_from(u32::from_be_bytes(*len)).ok()?;
+        let keys = std::str::from_utf8(rest.get(..len)?).ok()?;
+        let (nonce, ciphertext) = rest[len..].split_first_chunk::<NONCE_LEN>()?;
+
+        let sealed = Self {
+            nonce: *nonce,
+            ciphertext: ciphertext.to_vec(),
+            keys: keys.to_string(),
+        };
+        let well_formed = sealed
+            .share_tags()
+            .iter()
+            .flatten()
+            .all(|tag| !tag.is_empty());
+        (!keys.is_empty() && well_formed).then_some(sealed)
+    }
+
+    /// Tags of the readers of each share of the content key.
+    pub fn share_tags(&self) -> Vec<Vec<&str>> {
+        self.keys
+            .split(SHARE_SEP)
+            .map(|share| {
+                share
+                    .split(READER_SEP)
+                    .map(
+                        |reader| match reader.split(FIELD_SEP).collect::<Vec<_>>()[..] {
+                            [tag, _, _] => tag,
+                            _ => "",
+                        },
+                    )
+                    .collect()
+            })
+            .collect()
     }
 
-    Aes256Gcm::new(&key_bytes.into())
+    /// Decrypt with whichever of the passwords, by tag, are able to.
+    pub fn open(
+        &self,
+        passwords: &HashMap<&str, &str>,
+        salt: &[u8],
+        iterations: u32,
+    ) -> Option<Vec<u8>> {
+        let mut key: Key = [0; 32];
+        for share in self.keys.split(SHARE_SEP) {
+            let share = share.split(READER_SEP).find_map(|reader| {
+                let mut fields = reader.split(FIELD_SEP);
+                let (tag, nonce, wrapped) = (fields.next()?, fields.next()?, fields.next()?);
+                let cipher = cipher(passwords.get(tag)?, salt, iterations);
+                let (nonce, wrapped) = (b64_decode(nonce)?, b64_decode(wrapped)?);
+                cipher
+                    .decrypt(nonce.as_slice().into(), wrapped.as_slice())
+                    .ok()
+            })?;
+            key.iter_mut().zip(share).for_each(|(k, s)| *k ^= s);
+        }
+
+        Aes256Gcm::new(&key.into())
+            .decrypt(&self.nonce.into(), self.ciphertext.as_slice())
+            .ok()
+    }
 }
 
-pub fn encrypt(plaintext: &str, key: &str) -> Result<(String, String), ()> {
-    let cipher = cipher(key);
+/// Encrypt data with a random key which is split into a share for each group
+/// of readers, so that the key is the XOR of all shares. Each share is
+/// wrapped with the keys of its readers, given as tag and password, so that
+/// a reader needs a key from every group.
+pub fn seal(
+    data: &[u8],
+    groups: &[Vec<(&str, &str)>],
+    salt: &[u8],
+    iterations: u32,
+) -> Result<Sealed, ()> {
+    let mut key: Key = [0; 32];
+    OsRng.fill_bytes(&mut key);
+
+    let mut remaining = key;
+    let mut shares = Vec::new();
+    for (i, readers) in groups.iter().enumerate() {
+        let share = if i + 1 == groups.len() {
+            remaining
+        } else {
+            let mut share: Key = [0; 32];
+            OsRng.fill_bytes(&mut share);
+            remaining.iter_mut().zip(share).for_each(|(r, s)| *r ^= s);
+            share
+        };
+
+        let wrapped = readers
+            .iter()
+            .map(|(tag, password)| {
+                wrap(&share, password, salt, iterations)
+                    .map(|wrapped| format!("{tag}{FIELD_SEP}{wrapped}"))
+            })
+            .collect::<Result<Vec<String>, ()>>()?;
+        shares.push(wrapped.join(&READER_SEP.to_string()));
+    }
+
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
-    let ciphertext = cipher.encrypt(&nonce, plaintext.as_bytes()).map_err(warn)?;
-    Ok((b64_encode(&nonce), b64_encode(&ciphertext)))
+    let ciphertext = Aes256Gcm::new(&key.into())
+        .encrypt(&nonce, data)
+        .map_err(warn)?;
+    Ok(Sealed {
+        nonce: nonce.into(),
+        ciphertext,
+        keys: shares.join(&SHARE_SEP.to_string()),
+    })
+}
+
+/// Appended to the salt of the site to derive the keys of opaque names, so
+/// that they differ from those content is encrypted with.
+const NAME_SALT: &[u8] = b"mdwiki page name";
+
+/// Hex name derived from text and the keys protecting it, which is stable
+/// between builds with the same salt but can't be linked back to the text
+/// without the keys. It's an HMAC of the text keyed with keys derived as for
+/// encryption, so guessing keys from names is as slow as from encrypted
+/// content, and can't be done for every site at once.
+pub fn opaque_name(keys: &[&str], text: &str, salt: &[u8], iterations: u32) -> String {
+    const NAME_BYTES: usize = 8;
+
+    let key = derive_keys(keys, &[salt, NAME_SALT].concat(), iterations);
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC takes any key");
+    mac.update(text.as_bytes());
+    mac.finalize().into_bytes()[..NAME_BYTES]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    fn b64_decode(data: &str) -> Result<Vec<u8>, ()> {
-        base64::engine::general_purpose::STANDARD
-            .decode(data)
-            .map_err(warn)
+    const SALT: &[u8] = b"saltsaltsaltsalt";
+    const ITERATIONS: u32 = 1000;
+
+    #[test]
+    fn test_seal_open() {
+        let data = b"<h1>Super secret section!</h1>";
+        let sealed = seal(data, &[vec![("dm", "key1")]], SALT, ITERATIONS).unwrap();
+        assert_ne!(sealed.ciphertext, data);
+        assert_eq!(
+            sealed
+                .open(&HashMap::from([("dm", "key1")]), SALT, ITERATIONS)
+                .unwrap(),
+            data
+        );
+        assert!(
+            sealed
+                .open(&HashMap::from([("dm", "key2")]), SALT, ITERATIONS)
+                .is_none()
+        );
+        assert!(
+            sealed
+                .open(&HashMap::from([("paladin", "key1")]), SALT, ITERATIONS)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_seal_shares() {
+        let data = b"secret";
+        // Readers need dm or the party leader, and the paladin.
+        let groups = [
+            vec![("dm", "key1"), ("leader", "key2")],
+            vec![("paladin", "key3")],
+        ];
+        let sealed = seal(data, &groups, SALT, ITERATIONS).unwrap();
+
+        for passwords in [
+            HashMap::from([("dm", "key1"), ("paladin", "key3")]),
+            HashMap::from([("leader", "key2"), ("paladin", "key3")]),
+        ] {
+            assert_eq!(sealed.open(&passwords, SALT, ITERATIONS).unwrap(), data);
+        }
+        for passwords in [
+            HashMap::from([("dm", "key1"), ("leader", "key2")]),
+            HashMap::from([("paladin", "key3")]),
+        ] {
+            assert!(sealed.open(&passwords, SALT, ITERATIONS).is_none());
+        }
     }
 
-    fn decrypt(nonce: &str, ciphertext: &str, key: &str) -> Result<String, ()> {
-        let cipher = cipher(key);
-        let nonce_bytes: &[u8] = &b64_decode(nonce)?;
-        let nonce: &[u8; 12] = nonce_bytes.try_into().map_err(warn)?;
-        let data: &[u8] = &b64_decode(ciphertext)?;
-        let plain_bytes = cipher.decrypt(nonce.into(), data).map_err(warn)?;
-        let plaintext = String::from_utf8_lossy(&plain_bytes);
-        Ok(plaintext.to_string())
+    #[test]
+    fn test_sealed_bytes() {
+        let sealed = seal(&[0, 1, 2, 255], &[vec![("dm", "key")]], SALT, ITERATIONS).unwrap();
+        let bytes = sealed.to_bytes();
+        let (len, rest) = bytes.split_at(4);
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        assert_eq!(&rest[..len], sealed.keys.as_bytes());
+        assert_eq!(&rest[len..(len + NONCE_LEN)], sealed.nonce);
+        assert_eq!(&rest[(len + NONCE_LEN)..], sealed.ciphertext);
     }
 
     #[test]
-    fn test_aes_encode_decode() {
-        let plaintext = "plaintext";
-        let key = "mysupersafepassword";
-        let (nonce, ciphertext) = encrypt(plaintext, key).unwrap();
-        assert_ne!(ciphertext, plaintext);
-        assert_eq!(decrypt(&nonce, &ciphertext, key).unwrap(), plaintext);
+    fn test_opaque_name() {
+        let name = opaque_name(&["key"], "/lair.html", SALT, ITERATIONS);
+        assert_eq!(name.len(), 16);
+        assert_eq!(opaque_name(&["key"], "/lair.html", SALT, ITERATIONS), name);
+        assert_ne!(opaque_name(&["key"], "/vault.html", SALT, ITERATIONS), name);
+        assert_ne!(
+            opaque_name(&["other"], "/lair.html", SALT, ITERATIONS),
+            name
+        );
+        assert_ne!(
+            opaque_name(&["key"], "/lair.html", SALT, ITERATIONS + 1),
+            name
+        );
+        // Each site has its own salt, and so its own names.
+        assert_ne!(
+            opaque_name(&["key"], "/lair.html", b"pepperpepperpepp", ITERATIONS),
+            name
+        );
+
+        // The name is keyed with the derived key, not the key itself.
+        let derived = derive_key("key", &[SALT, NAME_SALT].concat(), ITERATIONS);
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&derived).unwrap();
+        mac.update(b"/lair.html");
+        let expected: String = mac.finalize().into_bytes()[..8]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        assert_eq!(name, expected);
     }
 
     #[test]
-    fn test_multiple_encode() {
-        let plaintext = "<h1>Super secret section!</h1>";
-        let key1 = "key1";
-        let key2 = "key2";
-        let key3 = "key3";
-
-        // ciphertext = encode(encode(encode(plaintext, key1), key2), key3)
-        let (nonce1, ciphertext) = encrypt(plaintext, key1).unwrap();
-        let (nonce2, ciphertext) = encrypt(&ciphertext, key2).unwrap();
-        let (nonce3, ciphertext) = encrypt(&ciphertext, key3).unwrap();
-
-        // decoded = decode(decode(decode(ciphertext, key1), key2), key3)
-        let decoded = decrypt(&nonce3, &ciphertext, key3).unwrap();
-        let decoded = decrypt(&nonce2, &decoded, key2).unwrap();
-        let decoded = decrypt(&nonce1, &decoded, key1).unwrap();
-
-        assert_eq!(decoded, plaintext);
+    fn test_key_derivation() {
+        // RFC 7914 test vector for PBKDF2-HMAC-SHA256.
+        let key = derive_key("passwd", b"salt", 1);
+        assert_eq!(
+            key,
+            [
+                0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f, 0xec, 0x16, 0x91, 0xc2, 0x25, 0x44,
+                0xb6, 0x05, 0xf9, 0x41, 0x85, 0x21, 0x6d, 0xde, 0x04, 0x65, 0xe6, 0x8b, 0x9d, 0x57,
+                0xc2, 0x0d, 0xac, 0xbc,
+            ]
+        );
+        assert_ne!(derive_key("passwd", b"pepper", 1), key);
+        assert_ne!(derive_key("passwd", b"salt", 2), key);
     }
 }
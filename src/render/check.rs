@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::Config,
+    model::{El, Id, Node, WikiPage, WikiTree},
+    parse::IMAGE_EXTS,
+};
+
+use super::{OUTPUT_EXT, html::find_section, links::find_url};
+
+/// Problems found with the pages of a wiki, each described with the URL of
+/// the page it is on.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Links and images which don't lead anywhere in the wiki.
+    pub broken: Vec<String>,
+    /// Pages which no other page links to.
+    pub orphans: Vec<String>,
+    /// Titles and aliases shared by several pages, making links to them
+    /// depend on where they are linked from.
+    pub duplicates: Vec<String>,
+}
+
+impl Report {
+    pub fn problems(&self) -> impl Iterator<Item = &String> {
+        self.broken
+            .iter()
+            .chain(&self.orphans)
+            .chain(&self.duplicates)
+    }
+}
+
+/// Whether a URL leads outside the wiki, having a scheme such as `https:` or
+/// `mailto:`, or a host.
+fn is_external(url: &str) -> bool {
+    url.starts_with("//")
+        || url.split_once(':').is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        })
+}
+
+/// Checks of the links and images of a single page.
+struct PageCheck<'a> {
+    config: &'a Config,
+    tree: &'a WikiTree,
+    page: &'a WikiPage,
+    broken: &'a mut Vec<String>,
+}
+
+impl PageCheck<'_> {
+    fn report(&mut self, msg: String) {
+        self.broken.push(format!("{}: {msg}", self.page.url()));
+    }
+
+    fn check_section(&mut self, kind: &str, text: &str, target: &WikiPage, section: &str) {
+        if find_section(target, section).is_none() {
+            self.report(format!(
+                "no section \"{section}\" of {} for {kind} \"{text}\"",
+                target.url()
+            ));
+        }
+    }
+
+    /// Check a link by title or filename, as `[[target]]` or with an empty URL,
+    /// to a page with one of the extensions.
+    fn check_titled(&mut self, kind: &str, text: &str, target: &str, exts: &[&str]) {
+        let (title, section) = match target.split_once('#') {
+            Some((title, section)) => (title.trim(), Some(section)),
+            None => (target.trim(), None),
+        };
+
+        let found = if title.is_empty() {
+            Some(self.page)
+        } else {
+            exts.iter()
+                .find_map(|ext| self.tree.find_link_target(title, ext, self.page))
+        };
+        match (found, section) {
+            (None, _) => self.report(format!("no page found for {kind} \"{text}\"")),
+            (Some(found), Some(section)) => self.check_section(kind, text, found, section),
+            (Some(_), None) => {}
+        }
+    }
+
+    /// Check a link by URL, absolute or relative to the page.
+    fn check_url(&mut self, kind: &str, text: &str, url: &str) {
+        if is_external(url) {
+            return;
+        }
+
+        let (path, section) = match url.split_once('#') {
+            Some((path, section)) => (path, Some(section)),
+            None => (url, None),
+        };
+        let found = if path.is_empty() {
+            Some(self.page)
+        } else {
+            find_url(self.tree, self.page, path)
+        };
+        match (found, section) {
+            (None, _) => self.report(format!("no page at {url} for {kind} \"{text}\"")),
+            (Some(found), Some(section)) => self.check_section(kind, text, found, section),
+            (Some(_), None) => {}
+        }
+    }
+
+    fn check_empty(&mut self, kind: &str, text: &str, exts: &[&str]) {
+        if self.config.empty_links {
+            self.check_titled(kind, text, text, exts);
+        } else {
+            self.report(format!("{kind} \"{text}\" has no URL"));
+        }
+    }
+
+    fn check_node(&mut self, node: &Node) {
+        match node.el() {
+            El::Link(text, url) if url.is_empty() => self.check_empty("link", text, &[OUTPUT_EXT]),
+            El::Link(text, url) => self.check_url("link", text, url),
+            El::WikiLink(target, text) => self.check_titled("link", text, target, &[OUTPUT_EXT]),
+            El::Image(alt, url) if url.is_empty() => self.check_empty("image", alt, IMAGE_EXTS),
+            El::Image(alt, url) => self.check_url("image", alt, url),
+            El::WikiImage(target, _) => self.check_titled("image", target, target, IMAGE_EXTS),
+            _ => {}
+        }
+    }
+}
+
+/// Doc pages which no other page links to, ignoring generated indexes.
+fn find_orphans(tree: &WikiTree) -> Vec<String> {
+    tree.pages()
+        .iter()
+        .filter(|page| page.is_doc() && tree.backlinks(page.id()).is_empty())
+        .map(|page| format!("{}: no other page links to it", page.url()))
+        .collect()
+}
+
+/// Titles and aliases of pages which are the same, ignoring case, as those
+/// of other pages, ignoring generated indexes.
+fn find_duplicates(tree: &WikiTree) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut pages: HashMap<String, Vec<Id>> = HashMap::new();
+    let written = tree.pages().iter().filter(|p| p.is_doc() || p.is_index());
+    for page in written.filter(|p| !p.is_generated()) {
+        let aliases = page.aliases();
+        for name in std::iter::once(page.title()).chain(aliases) {
+            let ids = pages.entry(name.to_ascii_lowercase()).or_insert_with(|| {
+                names.push(name.to_string());
+                Vec::new()
+            });
+            if !ids.contains(&page.id()) {
+                ids.push(page.id());
+            }
+        }
+    }
+
+    names
+        .iter()
+        .filter_map(|name| {
+            let ids = &pages[&name.to_ascii_lowercase()];
+            let urls: Vec<&str> = ids
+                .iter()
+                .filter_map(|id| tree.get(*id))
+                .map(WikiPage::url)
+                .collect();
+            (urls.len() > 1).then(|| format!("\"{name}\" is the title of {}", urls.join(", ")))
+        })
+        .collect()
+}
+
+/// Check every link and image in the wiki leads to a page, media file or
+/// section of it, and find orphaned pages and duplicate titles. Expects the
+/// links between pages to have been found with `link_pages`.
+pub fn check_tree(config: &Config, tree: &WikiTree) -> Report {
+    let mut broken = Vec::new();
+    for page in tree.pages() {
+        let Some(doc) = page.document() else {
+            continue;
+        };
+        let mut check = PageCheck {
+            config,
+            tree,
+            page,
+            broken: &mut broken,
+        };
+        for node in doc.nodes() {
+            node.walk(&mut |node| check.check_node(node));
+        }
+    }
+
+    Report {
+        broken,
+        orphans: find_orphans(tree),
+        duplicates: find_duplicates(tree),
+    }
+}
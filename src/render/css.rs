@@ -1,15 +1,23 @@
-use crate::model::Node;
+use crate::model::{Attrs, Node};
 
-pub fn with_class(node: Node, class: &str) -> Node {
-    const CSS_CLASS_ATTR: &str = "class";
+const CSS_CLASS_ATTR: &str = "class";
 
-    let class = match node.attrs().get(CSS_CLASS_ATTR) {
+fn class_list(attrs: &Attrs, class: &str) -> String {
+    match attrs.get(CSS_CLASS_ATTR) {
         Some(existing) => format!("{} {}", existing, class),
         None => class.to_string(),
-    };
+    }
+}
+
+pub fn with_class(node: Node, class: &str) -> Node {
+    let class = class_list(node.attrs(), class);
     node.with_attr(CSS_CLASS_ATTR, &class)
 }
 
+pub fn add_class(attrs: &mut Attrs, class: &str) {
+    attrs.insert(CSS_CLASS_ATTR.to_string(), class_list(attrs, class));
+}
+
 pub fn with_id(node: Node, id: &str) -> Node {
     const CSS_ID_ATTR: &str = "id";
 
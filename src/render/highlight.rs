@@ -0,0 +1,463 @@
+/// Classes of highlighted tokens, styled by the theme in the stylesheet.
+const COMMENT: &str = "hl-comment";
+const KEYWORD: &str = "hl-keyword";
+const LITERAL: &str = "hl-literal";
+const STRING: &str = "hl-string";
+const NUMBER: &str = "hl-number";
+const TYPE: &str = "hl-type";
+const FUNCTION: &str = "hl-function";
+const VARIABLE: &str = "hl-variable";
+const KEY: &str = "hl-key";
+
+/// Highlighting rules for a language.
+struct Syntax {
+    keywords: &'static [&'static str],
+    /// Words such as `true` and `null`.
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Quotes whose strings may continue over several lines.
+    multiline_quotes: &'static [char],
+    /// Strings may be tripled quoted, as in Python, and span lines.
+    triple_quotes: bool,
+    /// Identifiers starting with a capital letter are types.
+    capitalised_types: bool,
+    /// `'a` is a lifetime rather than the start of a string.
+    lifetimes: bool,
+    /// `$name` and `${name}` are variables.
+    variables: bool,
+    /// Keywords match regardless of case.
+    ignore_case: bool,
+    /// Separator following a key, e.g. `:` in JSON.
+    key_sep: Option<char>,
+    /// Lines beginning `[` are table headers, as in TOML.
+    tables: bool,
+    /// Characters other than alphanumerics and `_` allowed in identifiers.
+    ident_chars: &'static str,
+}
+
+const PLAIN: Syntax = Syntax {
+    keywords: &[],
+    literals: &[],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &[],
+    multiline_quotes: &[],
+    triple_quotes: false,
+    capitalised_types: false,
+    lifetimes: false,
+    variables: false,
+    ignore_case: false,
+    key_sep: None,
+    tables: false,
+    ident_chars: "",
+};
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    literals: &["true", "false"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    multiline_quotes: &['"'],
+    capitalised_types: true,
+    lifetimes: true,
+    ..PLAIN
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield",
+    ],
+    literals: &["True", "False", "None"],
+    line_comments: &["#"],
+    quotes: &['"', '\''],
+    triple_quotes: true,
+    ..PLAIN
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "debugger",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "finally",
+        "for",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "let",
+        "new",
+        "of",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "try",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    multiline_quotes: &['`'],
+    ..PLAIN
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+        "in", "local", "readonly", "return", "then", "until", "while",
+    ],
+    literals: &["true", "false"],
+    line_comments: &["#"],
+    quotes: &['"', '\''],
+    multiline_quotes: &['"', '\''],
+    variables: true,
+    ..PLAIN
+};
+
+const JSON: Syntax = Syntax {
+    literals: &["true", "false", "null"],
+    quotes: &['"'],
+    key_sep: Some(':'),
+    ..PLAIN
+};
+
+const TOML: Syntax = Syntax {
+    literals: &["true", "false"],
+    line_comments: &["#"],
+    quotes: &['"', '\''],
+    triple_quotes: true,
+    key_sep: Some('='),
+    tables: true,
+    ident_chars: "-.",
+    ..PLAIN
+};
+
+const YAML: Syntax = Syntax {
+    literals: &["true", "false", "null", "yes", "no", "on", "off"],
+    line_comments: &["#"],
+    quotes: &['"', '\''],
+    key_sep: Some(':'),
+    ident_chars: "-.",
+    ..PLAIN
+};
+
+const SQL: Syntax = Syntax {
+    keywords: &[
+        "add",
+        "all",
+        "alter",
+        "and",
+        "as",
+        "asc",
+        "begin",
+        "between",
+        "by",
+        "case",
+        "check",
+        "commit",
+        "constraint",
+        "create",
+        "default",
+        "delete",
+        "desc",
+        "distinct",
+        "drop",
+        "else",
+        "end",
+        "exists",
+        "foreign",
+        "from",
+        "full",
+        "group",
+        "having",
+        "in",
+        "index",
+        "inner",
+        "insert",
+        "into",
+        "is",
+        "join",
+        "key",
+        "left",
+        "like",
+        "limit",
+        "not",
+        "offset",
+        "on",
+        "or",
+        "order",
+        "outer",
+        "primary",
+        "references",
+        "returning",
+        "right",
+        "rollback",
+        "select",
+        "set",
+        "table",
+        "then",
+        "union",
+        "unique",
+        "update",
+        "values",
+        "view",
+        "when",
+        "where",
+        "with",
+    ],
+    literals: &["true", "false", "null"],
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\'', '"'],
+    multiline_quotes: &['\''],
+    ignore_case: true,
+    ..PLAIN
+};
+
+/// Rules for the language named by a code block, if it is one highlighted.
+fn syntax(lang: &str) -> Option<&'static Syntax> {
+    match lang.to_lowercase().as_str() {
+        "rust" | "rs" => Some(&RUST),
+        "python" | "py" => Some(&PYTHON),
+        "javascript" | "js" | "mjs" => Some(&JAVASCRIPT),
+        "shell" | "sh" | "bash" | "zsh" | "console" => Some(&SHELL),
+        "json" => Some(&JSON),
+        "toml" => Some(&TOML),
+        "yaml" | "yml" => Some(&YAML),
+        "sql" => Some(&SQL),
+        _ => None,
+    }
+}
+
+/// Escape text for inclusion in HTML.
+pub fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_ident(syntax: &Syntax, c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || syntax.ident_chars.contains(c)
+}
+
+/// End of the run of characters from `pos` matching a predicate.
+fn scan(code: &str, pos: usize, pred: impl Fn(char) -> bool) -> usize {
+    code[pos..]
+        .find(|c| !pred(c))
+        .map_or(code.len(), |len| pos + len)
+}
+
+fn line_end(code: &str, pos: usize) -> usize {
+    code[pos..].find('\n').map_or(code.len(), |len| pos + len)
+}
+
+/// Whether the token ending at `end` is followed by a key separator. A word
+/// followed by `:` is only a key if there's a space after, so that URLs in
+/// YAML aren't.
+fn is_key(syntax: &Syntax, code: &str, end: usize, is_string: bool) -> bool {
+    let Some(sep) = syntax.key_sep else {
+        return false;
+    };
+    let rest = code[end..].trim_start_matches([' ', '\t']);
+    let mut chars = rest.chars();
+    chars.next() == Some(sep)
+        && (is_string || sep != ':' || chars.next().is_none_or(char::is_whitespace))
+}
+
+/// End of a string opened by the quote at `pos`, or `None` if the quote
+/// doesn't start one.
+fn string_end(syntax: &Syntax, code: &str, pos: usize, quote: char) -> Option<usize> {
+    let rest = &code[pos..];
+    if syntax.lifetimes && quote == '\'' {
+        // A character literal, otherwise the quote begins a lifetime.
+        let mut chars = rest.char_indices().skip(1);
+        let len = match chars.next()? {
+            (_, '\\') => rest[2..].find('\'')? + 3,
+            _ => match chars.next()? {
+                (i, '\'') => i + 1,
+                _ => return None,
+            },
+        };
+        return Some(pos + len);
+    }
+
+    let triple: String = std::iter::repeat_n(quote, 3).collect();
+    if syntax.triple_quotes && rest.starts_with(&triple) {
+        let close = rest[3..]
+            .find(&triple)
+            .map_or(code.len(), |i| pos + 3 + i + 3);
+        return Some(close);
+    }
+
+    let multiline = syntax.multiline_quotes.contains(&quote);
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if !multiline => return Some(pos + i),
+            c if c == quote => return Some(pos + i + 1),
+            _ => {}
+        }
+    }
+    Some(code.len())
+}
+
+/// The token starting at `pos`, as its end and class. Words which aren't
+/// highlighted have no class, and `None` is returned for other characters.
+fn token(syntax: &Syntax, code: &str, pos: usize) -> Option<(usize, Option<&'static str>)> {
+    let rest = &code[pos..];
+    let c = rest.chars().next()?;
+    let prev = code[..pos].chars().next_back();
+    let line_start = code[..pos]
+        .rsplit('\n')
+        .next()
+        .is_none_or(|line| line.trim().is_empty());
+
+    for prefix in syntax.line_comments {
+        // `#` starts a comment at the start of a word, not e.g. in `$#`.
+        if rest.starts_with(prefix) && (*prefix != "#" || prev.is_none_or(char::is_whitespace)) {
+            return Some((line_end(code, pos), Some(COMMENT)));
+        }
+    }
+    if let Some((open, close)) = syntax.block_comment
+        && rest.starts_with(open)
+    {
+        let end = rest[open.len()..]
+            .find(close)
+            .map_or(code.len(), |i| pos + open.len() + i + close.len());
+        return Some((end, Some(COMMENT)));
+    }
+
+    if syntax.tables && c == '[' && line_start {
+        let line = &code[pos..line_end(code, pos)];
+        let end = line.rfind(']').map_or(pos + line.len(), |i| pos + i + 1);
+        return Some((end, Some(KEY)));
+    }
+
+    if syntax.quotes.contains(&c) {
+        let end = string_end(syntax, code, pos, c)?;
+        let class = if is_key(syntax, code, end, true) {
+            KEY
+        } else {
+            STRING
+        };
+        return Some((end, Some(class)));
+    }
+
+    if syntax.variables && c == '$' {
+        let end = match rest[1..].chars().next() {
+            Some('{') => rest.find('}').map_or(code.len(), |i| pos + i + 1),
+            Some(c) if is_ident(syntax, c) => scan(code, pos + 1, |c| is_ident(syntax, c)),
+            Some(c @ ('#' | '?' | '@' | '*' | '$' | '!')) => pos + 1 + c.len_utf8(),
+            _ => return None,
+        };
+        return Some((end, Some(VARIABLE)));
+    }
+
+    if prev.is_some_and(|prev| is_ident(syntax, prev)) {
+        return None;
+    }
+
+    if c.is_ascii_digit() {
+        let mut end = pos;
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let decimal = c == '.' && chars.peek().is_some_and(|(_, next)| next.is_ascii_digit());
+            if !(c.is_alphanumeric() || c == '_' || decimal) {
+                break;
+            }
+            end = pos + i + c.len_utf8();
+        }
+        return Some((end, Some(NUMBER)));
+    }
+
+    if c.is_alphabetic() || c == '_' {
+        let end = scan(code, pos, |c| is_ident(syntax, c));
+        let word = &code[pos..end];
+        let is_word = |words: &[&str]| {
+            words
+                .iter()
+                .any(|w| *w == word || (syntax.ignore_case && w.eq_ignore_ascii_case(word)))
+        };
+
+        let class = if is_key(syntax, code, end, false) {
+            Some(KEY)
+        } else if is_word(syntax.keywords) {
+            Some(KEYWORD)
+        } else if is_word(syntax.literals) {
+            Some(LITERAL)
+        } else if code[end..].starts_with('(') {
+            Some(FUNCTION)
+        } else if syntax.capitalised_types && c.is_uppercase() {
+            Some(TYPE)
+        } else {
+            None
+        };
+        return Some((end, class));
+    }
+
+    None
+}
+
+/// HTML for a code block, with tokens of languages which are recognised
+/// wrapped in classed spans. Other languages are only escaped.
+pub fn highlight(lang: Option<&str>, code: &str) -> String {
+    let Some(syntax) = lang.and_then(syntax) else {
+        return escape_text(code);
+    };
+
+    let mut html = String::new();
+    let mut plain = 0;
+    let mut pos = 0;
+    while let Some(c) = code[pos..].chars().next() {
+        match token(syntax, code, pos) {
+            Some((end, Some(class))) => {
+                html.push_str(&escape_text(&code[plain..pos]));
+                html.push_str(&format!(
+                    "<span class=\"{class}\">{}</span>",
+                    escape_text(&code[pos..end])
+                ));
+                pos = end;
+                plain = end;
+            }
+            Some((end, None)) => pos = end,
+            None => pos += c.len_utf8(),
+        }
+    }
+    html.push_str(&escape_text(&code[plain..]));
+    html
+}
@@ -2,16 +2,60 @@ use core::slice;
 use std::collections::HashMap;
 
 use crate::{
-    config::Config,
+    config::{Access, Config},
     log,
-    model::{Attrs, El, Node, Style, Tag, WikiPage, WikiTree},
-    render::css::{floating_menu, with_class, with_id},
+    model::{Align, Attrs, Callout, El, Node, Style, Tag, WikiPage, WikiTree},
+    render::css::{add_class, floating_menu, with_class, with_id},
 };
 
-use super::{OUTPUT_EXT, RenderState, encryption_pairs};
+use super::{
+    OUTPUT_EXT, RenderState, access_tags,
+    aes::b64_encode,
+    encryption_access,
+    highlight::highlight,
+    links::find_url,
+    nav::NavTree,
+    nested_access, page_access, reader_keys,
+    search::{SEARCH_INDEX_FILE, make_search_menu},
+    slugify,
+};
 
 pub const TABSIZE: usize = 2;
 
+/// Separator for lists of tags and nonces in attributes.
+const SEP: &str = ";";
+
+/// Title of protected pages until they are decrypted.
+const PROTECTED_TITLE: &str = "Protected page";
+
+/// Class of elements with encrypted content.
+pub const SECRET_CLASS: &str = "secret";
+
+/// Class of the element wrapping a code block and its copy button.
+const CODEBLOCK_CLASS: &str = "codeblock";
+
+/// Class of the buttons copying the contents of code blocks.
+const COPY_BUTTON_CLASS: &str = "copy-code";
+
+/// Class of images whose media is encrypted.
+pub const SECRET_MEDIA_CLASS: &str = "secret-media";
+
+/// Class of the spans in the keys menu which test a tag key.
+pub const KEY_TEST_CLASS: &str = "tag-keys-test";
+
+/// Class of the spans in the keys menu which test for a previous tag key.
+pub const PREVIOUS_KEY_TEST_CLASS: &str = "tag-keys-previous";
+
+/// Value the spans in the keys menu seal, which a key is correct if it
+/// decrypts them to. Filled in as `TEST_VALUE` in decrypt.js.
+pub const KEY_TEST_VALUE: &str = "correct";
+
+/// ID of the element holding the title of a protected page.
+pub const PAGE_TITLE_ID: &str = "page-title";
+
+/// Name of the meta element holding key derivation parameters.
+pub const KDF_META_NAME: &str = "mdwiki-kdf";
+
 pub struct Html {
     content: String,
     stack: Vec<String>,
@@ -46,7 +90,7 @@ impl Html {
 
     fn finish(&mut self, attrs: &Attrs) {
         let mut sorted: Vec<(&String, &String)> = attrs.iter().collect();
-        sorted.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        sorted.sort_by_key(|(k1, _)| *k1);
         sorted.into_iter().for_each(|(k, v)| self.attr(k, v));
         self.push('>');
     }
@@ -167,15 +211,162 @@ pub fn indent(string: &str, by: usize) -> String {
 }
 
 fn handle_empty_url(state: &RenderState, text: &str, ext: &str, url: &str) -> String {
-    if url.is_empty() && state.config.empty_links {
-        if let Some(target) = state.tree.find_link_target(text, ext, state.page) {
-            return target.url().to_string();
-        }
+    if url.is_empty()
+        && state.config.empty_links
+        && let Some(url) = resolve_target(state, text, ext)
+    {
+        return url;
+    }
+
+    if let Some(section) = url.strip_prefix('#') {
+        return format!("#{}", section_anchor(state, state.page, section));
     }
 
     url.to_string()
 }
 
+/// Find the URL of the page with the given title or filename, searching the
+/// nearest relatives of the current page first. A `#section` suffix on the
+/// target is kept as an anchor in the URL.
+fn resolve_target(state: &RenderState, target: &str, ext: &str) -> Option<String> {
+    let (title, section) = match target.split_once('#') {
+        Some((title, section)) => (title.trim(), Some(section)),
+        None => (target.trim(), None),
+    };
+
+    let target = if title.is_empty() {
+        state.page
+    } else {
+        state.tree.find_link_target(title, ext, state.page)?
+    };
+
+    let mut url = if title.is_empty() {
+        String::new()
+    } else {
+        target.url().to_string()
+    };
+
+    if let Some(section) = section {
+        url.push('#');
+        url.push_str(&section_anchor(state, target, section));
+    }
+
+    Some(url)
+}
+
+/// Find the anchor for a section of a page, accepting either the anchor
+/// itself or the text of the heading. Pages without a document have no
+/// sections to check, so any section is accepted.
+pub fn find_section(target: &WikiPage, section: &str) -> Option<String> {
+    let Some(doc) = target.document() else {
+        return Some(slugify(section));
+    };
+
+    let anchors = doc.anchors();
+    if anchors.contains(&section) {
+        return Some(section.to_string());
+    }
+
+    let slug = slugify(section);
+    anchors.contains(&slug.as_str()).then_some(slug)
+}
+
+/// Find the anchor for a section of the target page, as `find_section`. Emits
+/// a warning if the page has no such section.
+fn section_anchor(state: &RenderState, target: &WikiPage, section: &str) -> String {
+    find_section(target, section).unwrap_or_else(|| {
+        log::warning(format!(
+            "Failed to find section \"{section}\" of {} linked from {}",
+            target.url(),
+            state.page.url()
+        ));
+        slugify(section)
+    })
+}
+
+fn permalink(id: &str) -> Node {
+    const CSS_CLASS: &str = "permalink";
+
+    with_class(
+        Node::inline("a", vec![Node::text("¶")]).with_attr("href", &format!("#{id}")),
+        CSS_CLASS,
+    )
+}
+
+/// Prefix absolute URLs with the base URL of the site.
+fn site_url(state: &RenderState, url: &str) -> String {
+    if url.starts_with('/') && !url.starts_with("//") {
+        format!("{}{url}", state.config.base_url)
+    } else {
+        url.to_string()
+    }
+}
+
+/// URL of the image for an image node, resolving empty URLs and wiki image
+/// targets to media in the tree.
+pub fn image_url(state: &RenderState, node: &Node) -> Option<String> {
+    match node.el() {
+        El::Image(_, url) if !url.is_empty() => Some(url.clone()),
+        El::Image(text, _) => crate::parse::IMAGE_EXTS
+            .iter()
+            .map(|ext| handle_empty_url(state, text, ext, ""))
+            .find(|url| !url.is_empty()),
+        El::WikiImage(target, _) => crate::parse::IMAGE_EXTS
+            .iter()
+            .find_map(|ext| resolve_target(state, target, ext)),
+        _ => None,
+    }
+}
+
+fn render_image(state: &mut RenderState, node: &Node, text: &str, url: &str) {
+    if url.is_empty() {
+        log::warning(format!(
+            "Failed to find URL for image \"{text}\" on {}",
+            state.page.url()
+        ))
+    }
+
+    // Encrypted media is fetched and decrypted by the browser, so the image
+    // has no source until then.
+    let access = find_url(state.tree, state.page, url)
+        .and_then(|media| state.config.group_access(media.tag_groups()));
+    let (src_attr, tags) = match &access {
+        Some(access) => ("data-src", access_tags(access)),
+        None => ("src", Vec::new()),
+    };
+
+    state.space_if_needed();
+    let url = site_url(state, url);
+    state.singleton("img");
+    state.attr(src_attr, &escape(&url));
+    state.attr("alt", text);
+    if tags.is_empty() {
+        state.finish(node.attrs());
+    } else {
+        let mut attrs = node.attrs().clone();
+        add_class(&mut attrs, SECRET_MEDIA_CLASS);
+        attrs.insert(String::from("tags"), tags.join(SEP));
+        state.finish(&attrs);
+    }
+}
+
+fn render_link(state: &mut RenderState, node: &Node, text: &str, url: &str) {
+    if url.is_empty() {
+        log::warning(format!(
+            "Failed to find URL for link \"{text}\" on {}",
+            state.page.url()
+        ))
+    }
+
+    state.space_if_needed();
+    let url = site_url(state, url);
+    state.start("a");
+    state.attr("href", &escape(&url));
+    state.finish(node.attrs());
+    state.push_str(text);
+    state.close();
+}
+
 fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
     if !skip_encryption && handle_encryption_node(state, node) {
         return;
@@ -188,6 +379,26 @@ fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
             render_nodes(state, children, false);
             state.lclose();
         }
+        El::Blockquote(callout, children) => {
+            let mut attrs = node.attrs().clone();
+            if let Some(callout) = callout {
+                add_class(&mut attrs, "callout");
+                add_class(&mut attrs, callout_class(*callout));
+            }
+            state.lopen("blockquote", &attrs);
+
+            if let Some(callout) = callout {
+                let mut title_attrs = HashMap::new();
+                add_class(&mut title_attrs, "callout-title");
+                state.lopen("p", &title_attrs);
+                state.push_str(callout_title(*callout));
+                state.close();
+            }
+
+            let content = render_root_range(state, children, false);
+            state.push_str(&content);
+            state.lclosel();
+        }
         El::Inline(tag, children) => {
             state.space_if_needed();
             state.open(tag, node.attrs());
@@ -199,10 +410,27 @@ fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
             state.push_str(&escape(code));
             state.close();
         }
-        El::Codeblock(_lang, code) => {
-            state.lopenl("pre", node.attrs());
-            let indent_size = state.stack.len();
-            state.push_str(&indent(&escape(code), indent_size));
+        El::Codeblock(lang, code) => {
+            let mut attrs = node.attrs().clone();
+            add_class(&mut attrs, CODEBLOCK_CLASS);
+            state.lopen("div", &attrs);
+
+            let mut button_attrs = HashMap::new();
+            add_class(&mut button_attrs, COPY_BUTTON_CLASS);
+            state.lopen("button", &button_attrs);
+            state.push_str("Copy");
+            state.close();
+
+            // Code is written unindented, as whitespace in `pre` is shown.
+            let mut pre_attrs = HashMap::new();
+            if let Some(lang) = lang {
+                add_class(&mut pre_attrs, &format!("language-{}", lang.to_lowercase()));
+            }
+            state.lopen("pre", &pre_attrs);
+            state.open("code", &HashMap::new());
+            state.push_str(&highlight(lang.as_deref(), code));
+            state.close();
+            state.close();
             state.lclosel();
         }
         El::Details(summary, details) => {
@@ -216,30 +444,14 @@ fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
         El::Heading(level, children) => {
             state.lopen(&format!("h{level}"), node.attrs());
             render_nodes(state, children, false);
+            if let Some(id) = node.attrs().get("id") {
+                render(state, &permalink(id), true);
+            }
             state.closel();
         }
-        El::Image(text, url) => {
-            let mut url: String = url.clone();
-            for ext in crate::parse::IMAGE_EXTS {
-                if url.is_empty() {
-                    url = handle_empty_url(state, text, ext, &url);
-                } else {
-                    break;
-                }
-            }
-
-            if url.is_empty() {
-                log::warning(format!(
-                    "Failed to find URL for image \"{text}\" on {}",
-                    state.page.url()
-                ))
-            }
-
-            state.space_if_needed();
-            state.singleton("img");
-            state.attr("src", &escape(&url));
-            state.attr("alt", text);
-            state.finish(node.attrs());
+        El::Image(text, _) => {
+            let url = image_url(state, node).unwrap_or_default();
+            render_image(state, node, text, &url);
         }
         El::Item(children) => {
             state.lopen("li", node.attrs());
@@ -248,26 +460,23 @@ fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
         }
         El::Link(text, url) => {
             let url = handle_empty_url(state, text, OUTPUT_EXT, url);
-
-            if url.is_empty() {
-                log::warning(format!(
-                    "Failed to find URL for link \"{text}\" on {}",
-                    state.page.url()
-                ))
-            }
-
-            state.space_if_needed();
-            state.start("a");
-            state.attr("href", &escape(&url));
-            state.finish(node.attrs());
-            state.push_str(text);
-            state.close();
+            render_link(state, node, text, &url);
         }
         El::List(children) => {
             state.lopen("ul", node.attrs());
             render_nodes(state, children, false);
             state.lclosel();
         }
+        El::OrderedList(start, children) => {
+            let mut attrs = node.attrs().clone();
+            if *start != 1 {
+                attrs.insert("start".to_string(), start.to_string());
+            }
+
+            state.lopen("ol", &attrs);
+            render_nodes(state, children, false);
+            state.lclosel();
+        }
         El::Style(style, children) => {
             let tag = match style {
                 Style::Bold => "b",
@@ -282,7 +491,46 @@ fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
             state.close();
             state.space();
         }
-        El::Table(_rows) => {}
+        El::Table(align, header, rows) => {
+            let columns = if header.is_empty() {
+                rows.iter().map(Vec::len).max().unwrap_or(0)
+            } else {
+                header.len()
+            };
+
+            state.lopen("table", node.attrs());
+            if !header.is_empty() {
+                state.lopen("thead", &HashMap::new());
+                render_table_row(state, "th", align, columns, header);
+                state.lclose();
+            }
+            if !rows.is_empty() {
+                state.lopen("tbody", &HashMap::new());
+                for row in rows {
+                    render_table_row(state, "td", align, columns, row);
+                }
+                state.lclose();
+            }
+            state.lclosel();
+        }
+        El::WikiImage(_, text) => {
+            let url = image_url(state, node).unwrap_or_default();
+            render_image(state, node, text, &url);
+        }
+        El::WikiLink(target, text) => {
+            let url = resolve_target(state, target, OUTPUT_EXT).unwrap_or_default();
+            render_link(state, node, text, &url);
+        }
+        El::Html(html) => {
+            let depth = state.stack.len();
+            let indent = " ".repeat((depth + state.indent_adjust) * TABSIZE);
+            state.indent(depth);
+            state.push_str(&html.replace('\n', &format!("\n{indent}")));
+        }
+        El::Toc => {
+            let toc = super::toc::make_toc(state);
+            render(state, &toc, true);
+        }
         El::Text(text) => {
             if text.starts_with(char::is_alphanumeric) || text.starts_with('/') {
                 state.space_if_needed();
@@ -292,40 +540,95 @@ fn render(state: &mut RenderState, node: &Node, skip_encryption: bool) {
     }
 }
 
-fn encrypt_text(pairs: &[(&Tag, &String)], plaintext: &str) -> Node {
-    const CSS_CLASS: &str = "secret";
-    const SEP: &str = ";";
+fn callout_class(callout: Callout) -> &'static str {
+    match callout {
+        Callout::Note => "callout-note",
+        Callout::Tip => "callout-tip",
+        Callout::Important => "callout-important",
+        Callout::Warning => "callout-warning",
+        Callout::Caution => "callout-caution",
+    }
+}
 
-    let mut tags = Vec::new();
-    let mut nonces = Vec::new();
+fn callout_title(callout: Callout) -> &'static str {
+    match callout {
+        Callout::Note => "Note",
+        Callout::Tip => "Tip",
+        Callout::Important => "Important",
+        Callout::Warning => "Warning",
+        Callout::Caution => "Caution",
+    }
+}
+
+fn render_table_row(
+    state: &mut RenderState,
+    tag: &str,
+    align: &[Align],
+    columns: usize,
+    row: &[Vec<Node>],
+) {
+    state.lopen("tr", &HashMap::new());
+    for i in 0..columns {
+        let mut attrs = HashMap::new();
+        let text_align = match align.get(i) {
+            Some(Align::Left) => Some("left"),
+            Some(Align::Center) => Some("center"),
+            Some(Align::Right) => Some("right"),
+            Some(Align::None) | None => None,
+        };
+        if let Some(text_align) = text_align {
+            attrs.insert("style".to_string(), format!("text-align: {text_align}"));
+        }
 
-    let mut ciphertext = String::new();
-    for (tag, password) in pairs {
-        if let Ok((nonce, encrypted)) = super::aes::encrypt(plaintext, password) {
-            tags.push(tag.as_ref());
-            nonces.push(nonce);
-            ciphertext = encrypted;
+        state.lopen(tag, &attrs);
+        if let Some(cell) = row.get(i) {
+            render_nodes(state, cell, false);
         }
+        state.close();
     }
+    state.lclose();
+}
 
-    with_class(Node::span(vec![Node::text(&ciphertext)]), CSS_CLASS)
-        .with_attr("tags", &tags.join(SEP))
-        .with_attr("nonces", &nonces.join(SEP))
+/// Encrypt text for the readers given by access. The content key is wrapped
+/// for each reader in the `keys` attribute, in the layout described by
+/// `aes::seal`.
+fn encrypt_text(config: &Config, access: &Access, plaintext: &str) -> Node {
+    let groups = reader_keys(config, access);
+    seal_text(config, &groups, &access_tags(access), plaintext)
 }
 
-pub fn encrypt_nodes(
-    state: &RenderState,
-    pairs: &[(&Tag, &String)],
-    nodes: &[Node],
-    at_root: bool,
+/// A span of text sealed for the given groups of readers' tags and keys.
+fn seal_text(
+    config: &Config,
+    groups: &[Vec<(&str, &str)>],
+    tags: &[&str],
+    plaintext: &str,
 ) -> Node {
+    let Ok(sealed) = super::aes::seal(
+        plaintext.as_bytes(),
+        groups,
+        &config.kdf_salt,
+        config.kdf_iterations,
+    ) else {
+        log::error("Failed to encrypt content.");
+        return Node::empty();
+    };
+
+    let ciphertext = b64_encode(&sealed.ciphertext);
+    with_class(Node::span(vec![Node::text(&ciphertext)]), SECRET_CLASS)
+        .with_attr("tags", &tags.join(SEP))
+        .with_attr("nonce", &b64_encode(&sealed.nonce))
+        .with_attr("keys", &sealed.keys)
+}
+
+pub fn encrypt_nodes(state: &RenderState, access: &Access, nodes: &[Node], at_root: bool) -> Node {
     let plaintext = if at_root {
         render_root_range(state, nodes, true)
     } else {
         render_nodes_only(state.config, state.tree, state.page, nodes, true)
     };
 
-    encrypt_text(pairs, &plaintext)
+    encrypt_text(state.config, access, &plaintext)
 }
 
 fn render_nodes(state: &mut RenderState, nodes: &[Node], skip_encryption: bool) {
@@ -347,11 +650,15 @@ fn render_nodes(state: &mut RenderState, nodes: &[Node], skip_encryption: bool)
 }
 
 fn add_page_heading(state: &mut RenderState, page: &WikiPage) {
-    render(
-        state,
-        &Node::heading(1, vec![Node::text(page.title())]),
-        false,
-    );
+    let heading = Node::heading(1, vec![Node::text(page.title())]).with_tags(page.tags());
+    render(state, &heading, false);
+}
+
+fn add_page_date(state: &mut RenderState, date: &str) {
+    const CSS_CLASS: &str = "page-date";
+
+    let time = Node::inline("time", vec![Node::text(date)]).with_attr("datetime", date);
+    render(state, &with_class(Node::div(vec![time]), CSS_CLASS), true);
 }
 
 fn add_page_path(state: &mut RenderState) {
@@ -390,9 +697,9 @@ pub fn render_node_only(config: &Config, tree: &WikiTree, page: &WikiPage, node:
 }
 
 fn handle_encryption_node(state: &mut RenderState, node: &Node) -> bool {
-    if let Some(pairs) = encryption_pairs(state, node.tags()) {
+    if let Some(access) = encryption_access(state, node.tags()) {
         let nodes = slice::from_ref(node);
-        render(state, &encrypt_nodes(state, &pairs, nodes, false), true);
+        render(state, &encrypt_nodes(state, &access, nodes, false), true);
         true
     } else {
         false
@@ -405,47 +712,86 @@ fn handle_encryption_section(
     at_root: bool,
 ) -> Option<usize> {
     let node = nodes.first()?;
-    let pairs = encryption_pairs(state, node.tags())?;
-    let (skip, nodes) = if let El::Heading(nt, _) = node.el() {
-        let idx = if let Some(next_heading_idx) = nodes[1..]
+    let access = encryption_access(state, node.tags())?;
+    let end = if let El::Heading(nt, _) = node.el() {
+        nodes[1..]
             .iter()
             .position(|n| matches!(n.el(), El::Heading(t, _) if *nt == *t))
-        {
-            next_heading_idx
-        } else {
-            nodes.len() - 1
-        };
-        (idx, &nodes[..idx])
+            .map(|p| p + 1)
+            .unwrap_or(nodes.len())
     } else {
-        (0, &nodes[..1])
+        1
     };
+    let (skip, nodes) = (end - 1, &nodes[..end]);
 
     // If any of this nodes tags are password protected, render out an
     // encrypted node instead.
-    render(state, &encrypt_nodes(state, &pairs, nodes, at_root), false);
+    render(state, &encrypt_nodes(state, &access, nodes, at_root), false);
     Some(skip)
 }
 
-fn header(title: &str) -> Node {
-    Node::block(
-        "head",
-        vec![
-            Node::inline("title", vec![Node::text(title)]),
-            Node::block(
-                "style",
-                vec![Node::text(&indent(include_str!("res/style.css"), 3))],
-            ),
-            Node::block(
-                "script",
-                vec![Node::text(&indent(include_str!("res/decrypt.js"), 3))],
-            ),
-        ],
-    )
+/// Parameters needed to derive decryption keys from tag keys in the browser.
+fn kdf_params(config: &Config) -> Node {
+    Node::inline("meta", Vec::new())
+        .with_attr("name", KDF_META_NAME)
+        .with_attr("content", super::aes::KDF_NAME)
+        .with_attr("data-iterations", &config.kdf_iterations.to_string())
+        .with_attr("data-salt", &super::aes::b64_encode(&config.kdf_salt))
+}
+
+/// Title of the document, including the name of the site if set.
+fn document_title(config: &Config, title: &str) -> String {
+    match &config.site_title {
+        Some(site) if site != title => format!("{title} - {site}"),
+        _ => title.to_string(),
+    }
+}
+
+/// Encrypted document title for a protected page, which is applied once
+/// decrypted.
+fn secret_title(state: &RenderState, access: &Access) -> Node {
+    let title = document_title(state.config, state.page.title());
+    let node = with_id(Node::span(vec![Node::text(&title)]), PAGE_TITLE_ID).with_attr("hidden", "");
+    encrypt_nodes(state, access, &[node], false)
+}
+
+fn header(config: &Config, title: &str, search: bool) -> Node {
+    let mut children = vec![
+        Node::inline("title", vec![Node::text(&document_title(config, title))]),
+        Node::block(
+            "style",
+            vec![Node::text(&indent(include_str!("res/style.css"), 3))],
+        ),
+    ];
+    if !config.tag_keys.is_empty() {
+        children.push(kdf_params(config));
+    }
+    if let Some(theme) = &config.theme {
+        children.push(Node::block("style", vec![Node::text(&indent(theme, 3))]));
+    }
+    children.push(Node::block(
+        "script",
+        vec![Node::text(&indent(&decrypt_script(), 3))],
+    ));
+    children.push(Node::block(
+        "script",
+        vec![Node::text(&indent(include_str!("res/code.js"), 3))],
+    ));
+    if search {
+        let index = format!("{}/{SEARCH_INDEX_FILE}", config.base_url);
+        children.push(Node::block("script", Vec::new()).with_attr("src", &index));
+        children.push(Node::block(
+            "script",
+            vec![Node::text(&indent(include_str!("res/search.js"), 3))],
+        ));
+    }
+
+    Node::block("head", children)
 }
 
 fn render_root_range(state: &RenderState, range: &[Node], skip_encryption: bool) -> String {
     let mut html = Html::new();
-    html.indent_adjust = state.stack.len();
+    html.indent_adjust = state.indent_adjust + state.stack.len();
     let mut state = RenderState {
         tree: state.tree,
         page: state.page,
@@ -463,11 +809,11 @@ fn render_root_range(state: &RenderState, range: &[Node], skip_encryption: bool)
             continue;
         }
 
-        if !skip_encryption {
-            if let Some(n) = handle_encryption_section(&mut state, &range[i..], true) {
-                skip = n;
-                continue;
-            }
+        if !skip_encryption
+            && let Some(n) = handle_encryption_section(&mut state, &range[i..], true)
+        {
+            skip = n;
+            continue;
         }
 
         let mut paragraph_needed = false;
@@ -485,17 +831,22 @@ fn render_root_range(state: &RenderState, range: &[Node], skip_encryption: bool)
                     }
                 }
             }
-            El::Code(..) | El::Link(..) | El::Style(..) => {
+            El::Code(..) | El::Link(..) | El::Style(..) | El::WikiLink(..) => {
                 paragraph_needed = true;
             }
             El::Block(..)
+            | El::Blockquote(..)
             | El::Inline(..)
             | El::Codeblock(..)
             | El::Details(..)
             | El::Heading(..)
             | El::Image(..)
+            | El::WikiImage(..)
             | El::List(..)
-            | El::Table(..) => {
+            | El::OrderedList(..)
+            | El::Table(..)
+            | El::Html(..)
+            | El::Toc => {
                 if paragraph_open {
                     state.lclosel();
                 }
@@ -521,22 +872,47 @@ fn render_root_range(state: &RenderState, range: &[Node], skip_encryption: bool)
     html.content
 }
 
+/// Script decrypting content in the browser, with the values it shares with
+/// the renderer filled in.
+pub fn decrypt_script() -> String {
+    include_str!("res/decrypt.js").replace("\"KEY_TEST_VALUE\"", &format!("\"{KEY_TEST_VALUE}\""))
+}
+
 fn make_tag_key_menu(config: &Config) -> Node {
     const ID: &str = "tag-keys-menu";
     const TITLE_CLASS: &str = "title";
     const LABEL_CLASS: &str = "tag-keys-label";
-    const TEST_CLASS: &str = "tag-keys-test";
+
+    // A key is tested by decrypting a known value with it. The same value
+    // sealed with each previous key tells a changed key from a wrong one.
+    let key_test = |tag: &Tag, key: &str, class: &str| {
+        seal_text(
+            config,
+            &[vec![(tag.as_ref(), key)]],
+            &[tag.as_ref()],
+            KEY_TEST_VALUE,
+        )
+        .with_attr("class", class)
+    };
+
+    // Sorted, so that the menu is the same each build.
+    let mut tag_keys: Vec<(&Tag, &String)> = config.tag_keys.iter().collect();
+    tag_keys.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
 
     let mut tag_entrys = Vec::new();
-    for (tag, key) in config.tag_keys.iter() {
-        tag_entrys.push(Node::item(vec![
+    for (tag, key) in tag_keys {
+        let mut items = vec![
             with_class(Node::span(vec![Node::text(tag.as_ref())]), LABEL_CLASS),
             Node::inline("input", Vec::new())
                 .with_attr("type", "password")
                 .with_attr("autocomplete", "off"),
             Node::inline("button", vec![Node::text("Clear")]),
-            encrypt_text(&[(tag, key)], "correct").with_attr("class", TEST_CLASS),
-        ]));
+            key_test(tag, key, KEY_TEST_CLASS),
+        ];
+        for previous in config.previous_tag_keys.get(tag).into_iter().flatten() {
+            items.push(key_test(tag, previous, PREVIOUS_KEY_TEST_CLASS));
+        }
+        tag_entrys.push(Node::item(items));
     }
 
     let node = Node::div(vec![Node::details(
@@ -549,7 +925,65 @@ fn make_tag_key_menu(config: &Config) -> Node {
     with_id(floating_menu(node), ID)
 }
 
-pub fn render_document(config: &Config, tree: &WikiTree, page: &WikiPage) -> Result<String, ()> {
+/// Section listing the pages which link to this one. Links from encrypted
+/// content, or to a protected page, are listed in an encrypted block for each
+/// access they need, which includes the heading if there are no public links.
+fn add_backlinks(state: &mut RenderState) {
+    const CLASS: &str = "backlinks";
+    const TITLE: &str = "Linked from";
+
+    let backlinks = state.tree.backlinks(state.page.id());
+    if backlinks.is_empty() {
+        return;
+    }
+
+    let this_access = page_access(state.config, state.tree, state.page);
+    let mut groups: Vec<(Access, Vec<Node>)> = Vec::new();
+    for link in backlinks {
+        let Some(from) = state.tree.get(link.from) else {
+            continue;
+        };
+        let mut children = vec![Node::link(from.title(), from.url())];
+        if !link.context.is_empty() {
+            children.push(Node::text(&format!(": {}", link.context)));
+        }
+
+        let access = nested_access(&this_access, link.access.clone());
+        match groups.iter_mut().find(|(a, _)| *a == access) {
+            Some((_, items)) => items.push(Node::item(children)),
+            None => groups.push((access, vec![Node::item(children)])),
+        }
+    }
+
+    let heading = || Node::heading(2, vec![Node::text(TITLE)]);
+    let has_public = groups.iter().any(|(access, _)| access.is_empty());
+    let mut section = Vec::new();
+    if has_public {
+        section.push(heading());
+    }
+    for (access, items) in groups {
+        if access.is_empty() {
+            section.push(Node::list(items));
+        } else {
+            let mut nodes = Vec::new();
+            if !has_public {
+                nodes.push(heading());
+            }
+            nodes.push(Node::list(items));
+            section.push(encrypt_nodes(state, &access, &nodes, false));
+        }
+    }
+
+    render(state, &with_class(Node::div(section), CLASS), true);
+}
+
+/// Render a page of the tree, with the nav tree made for the build.
+pub fn render_document(
+    config: &Config,
+    tree: &WikiTree,
+    nav: &NavTree,
+    page: &WikiPage,
+) -> Result<String, ()> {
     let Some(doc) = page.document() else {
         log::error(format!(
             "Can't render page with no document: {}",
@@ -568,20 +1002,45 @@ pub fn render_document(config: &Config, tree: &WikiTree, page: &WikiPage) -> Res
 
     let empty = &HashMap::new();
 
+    // The title of a protected page is only shown once decrypted.
+    let secret_title =
+        encryption_access(&state, &page.tags()).map(|access| secret_title(&state, &access));
+    let title = match secret_title {
+        Some(_) => PROTECTED_TITLE,
+        None => page.title(),
+    };
+
     state.lopenl("html", empty);
-    render(&mut state, &header(page.title()), true);
+    // Single files built on their own have no index to search.
+    let search = config.search && tree.pages().len() > 1;
+    render(&mut state, &header(config, title, search), true);
 
-    state.lopenl("body", empty);
+    let mut body_attrs = HashMap::new();
+    if let Some(template) = doc.meta().template() {
+        add_class(&mut body_attrs, &format!("template-{template}"));
+    }
+    state.lopenl("body", &body_attrs);
+
+    if let Some(secret_title) = &secret_title {
+        render(&mut state, secret_title, true);
+    }
 
     if config.nav_tree {
-        let nav_tree = super::nav::make_nav_tree(&state);
+        let nav_tree = super::nav::make_nav_tree(&state, nav);
         render(&mut state, &nav_tree, false);
     }
 
+    if search {
+        render(&mut state, &make_search_menu(), true);
+    }
+
     if !config.tag_keys.is_empty() {
         render(&mut state, &make_tag_key_menu(config), true);
     }
 
+    let toc = super::toc::make_toc_menu(&state, doc);
+    render(&mut state, &toc, true);
+
     state.start("div");
     state.attr("id", "content");
     state.finish(empty);
@@ -595,8 +1054,20 @@ pub fn render_document(config: &Config, tree: &WikiTree, page: &WikiPage) -> Res
         add_page_path(&mut state);
     }
 
-    let content = render_root_range(&state, doc.nodes(), config.tag_keys.is_empty());
-    state.push_str(&content);
+    if let Some(date) = doc.meta().date() {
+        add_page_date(&mut state, date);
+    }
+
+    // Tags from front matter protect the entire page content.
+    if let Some(access) = encryption_access(&state, &doc.meta().tags()) {
+        let encrypted = encrypt_nodes(&state, &access, doc.nodes(), true);
+        render(&mut state, &encrypted, true);
+    } else {
+        let content = render_root_range(&state, doc.nodes(), config.tag_keys.is_empty());
+        state.push_str(&content);
+    }
+
+    add_backlinks(&mut state);
 
     html.lclose();
     html.lclose();
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::Config,
+    log,
+    model::{Backlink, El, Id, Node, WikiPage, WikiTree},
+    parse::IMAGE_EXTS,
+};
+
+use super::{INDEX_FILE, OUTPUT_EXT, html::find_section, page_access, walk_access};
+
+/// Longest context shown for a backlink, in characters.
+const MAX_CONTEXT: usize = 200;
+
+/// Page with the title or filename of a link target, ignoring any section.
+fn find_titled<'a>(tree: &'a WikiTree, from: &WikiPage, target: &str) -> Option<&'a WikiPage> {
+    let title = target.split('#').next()?.trim();
+    if title.is_empty() {
+        return None;
+    }
+    tree.find_link_target(title, OUTPUT_EXT, from)
+}
+
+/// Page at a URL of the site, absolute or relative to the linking page.
+pub(super) fn find_url<'a>(tree: &'a WikiTree, from: &WikiPage, url: &str) -> Option<&'a WikiPage> {
+    let path = url.split(['#', '?']).next()?;
+    if path.is_empty() || path.contains(':') || path.starts_with("//") {
+        return None;
+    }
+
+    let mut segments: Vec<&str> = Vec::new();
+    if !path.starts_with('/') {
+        segments.extend(from.url().split('/').filter(|s| !s.is_empty()));
+        segments.pop();
+    }
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if path.ends_with('/') {
+        segments.push(INDEX_FILE);
+    }
+    tree.find_url(&format!("/{}", segments.join("/")))
+}
+
+/// The page a link node leads to, if it is a page of the wiki.
+fn link_target<'a>(
+    config: &Config,
+    tree: &'a WikiTree,
+    from: &WikiPage,
+    node: &Node,
+) -> Option<&'a WikiPage> {
+    let target = match node.el() {
+        El::Link(text, url) if url.is_empty() => {
+            config.empty_links.then(|| find_titled(tree, from, text))?
+        }
+        El::Link(_, url) => find_url(tree, from, url),
+        El::WikiLink(target, _) => find_titled(tree, from, target),
+        _ => None,
+    }?;
+    (target.is_doc() || target.is_index()).then_some(target)
+}
+
+/// Section of the target a link node leads to, if any.
+fn link_section(node: &Node) -> Option<&str> {
+    let target = match node.el() {
+        El::Link(text, url) if url.is_empty() => text,
+        El::Link(_, url) => url,
+        El::WikiLink(target, _) => target,
+        _ => return None,
+    };
+    target.split_once('#').map(|(_, section)| section)
+}
+
+/// Where each link and image of a page leads, as the URL, section and tags of
+/// its target, so that pages can be rendered again when these change.
+pub fn link_resolutions(config: &Config, tree: &WikiTree, page: &WikiPage) -> Vec<String> {
+    let Some(doc) = page.document() else {
+        return Vec::new();
+    };
+
+    let describe = |target: Option<&WikiPage>, section: Option<&str>| {
+        let Some(target) = target else {
+            return String::from("-");
+        };
+        let tags = target.tags();
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        let anchor = section.and_then(|section| find_section(target, section));
+        format!(
+            "{}#{}|{}",
+            target.url(),
+            anchor.unwrap_or_default(),
+            tags.join(" ")
+        )
+    };
+    let image = |target: &str| {
+        IMAGE_EXTS
+            .iter()
+            .find_map(|ext| tree.find_link_target(target, ext, page))
+    };
+
+    let mut resolutions = Vec::new();
+    for node in doc.nodes() {
+        node.walk(&mut |node| {
+            let target = match node.el() {
+                El::Link(..) | El::WikiLink(..) => link_target(config, tree, page, node),
+                El::Image(alt, url) if url.is_empty() => {
+                    config.empty_links.then(|| image(alt)).flatten()
+                }
+                El::Image(_, url) => find_url(tree, page, url),
+                El::WikiImage(target, _) => image(target),
+                _ => return,
+            };
+            resolutions.push(describe(target, link_section(node)));
+        });
+    }
+    resolutions
+}
+
+fn is_inline(node: &Node) -> bool {
+    matches!(
+        node.el(),
+        El::Code(..)
+            | El::Inline(..)
+            | El::Link(..)
+            | El::Style(..)
+            | El::Text(..)
+            | El::WikiLink(..)
+    )
+}
+
+fn ends_sentence(text: &str, i: usize) -> bool {
+    text[i..].starts_with(['.', '!', '?'])
+        && text[(i + 1)..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+}
+
+/// Whether `nodes[j]` continues the paragraph of the node before it. Text
+/// following text starts a new paragraph when rendered.
+fn continues_paragraph(nodes: &[&Node], j: usize) -> bool {
+    j > 0
+        && is_inline(nodes[j])
+        && is_inline(nodes[j - 1])
+        && !matches!(
+            (nodes[j - 1].el(), nodes[j].el()),
+            (El::Text(..), El::Text(..))
+        )
+}
+
+/// Whether a node is separated from the one before by a space when rendered,
+/// which text starting with punctuation isn't.
+fn spaced(node: &Node, text: &str) -> bool {
+    !matches!(node.el(), El::Text(..))
+        || text.starts_with(|c: char| c.is_alphanumeric() || c == '/')
+}
+
+/// The sentence containing the link at `nodes[i]`, from the paragraph of
+/// inline nodes around it. Tagged nodes are left out, as they are encrypted
+/// separately from the link.
+fn link_context(config: &Config, nodes: &[&Node], i: usize) -> String {
+    let mut start = i;
+    while continues_paragraph(nodes, start) {
+        start -= 1;
+    }
+    let mut end = i + 1;
+    while end < nodes.len() && continues_paragraph(nodes, end) {
+        end += 1;
+    }
+
+    let mut text = String::new();
+    let mut link = 0..0;
+    for (j, node) in nodes.iter().enumerate().take(end).skip(start) {
+        if j != i && config.access(node.tags()).is_some() {
+            continue;
+        }
+        let node_text = node.plain_text();
+        if !text.is_empty() && spaced(node, &node_text) {
+            text.push(' ');
+        }
+        if j == i {
+            link.start = text.len();
+        }
+        text.push_str(&node_text);
+        if j == i {
+            link.end = text.len();
+        }
+    }
+
+    let from = text[..link.start]
+        .char_indices()
+        .rfind(|(k, _)| ends_sentence(&text, *k))
+        .map_or(0, |(k, _)| k + 1);
+    let to = text[link.end..]
+        .char_indices()
+        .find(|(k, _)| ends_sentence(&text, link.end + k))
+        .map_or(text.len(), |(k, _)| link.end + k + 1);
+
+    // A link on its own has no context beyond itself.
+    let sentence = text[from..to].trim();
+    if sentence == &text[link] {
+        return String::new();
+    }
+    if sentence.chars().count() <= MAX_CONTEXT {
+        return sentence.to_string();
+    }
+    let mut shortened: String = sentence.chars().take(MAX_CONTEXT).collect();
+    if let Some(space) = shortened.rfind(' ') {
+        shortened.truncate(space);
+    }
+    shortened.push('…');
+    shortened
+}
+
+/// Record which pages link to each page, with the sentence each link is in,
+/// for the backlinks section of each page. A page linking to another several
+/// times is only recorded once for each access its links need. Generated
+/// indexes link to every page, so aren't counted.
+pub fn link_pages(config: &Config, tree: &mut WikiTree) {
+    let mut backlinks: HashMap<Id, Vec<Backlink>> = HashMap::new();
+    let pages = tree.pages().iter().filter(|p| p.is_doc() || p.is_index());
+    for page in pages.filter(|p| !p.is_generated()) {
+        let Some(doc) = page.document() else {
+            continue;
+        };
+
+        let nodes: Vec<&Node> = doc.nodes().iter().collect();
+        let access = page_access(config, tree, page);
+        walk_access(config, &nodes, &access, &mut |nodes, i, access| {
+            let Some(target) = link_target(config, tree, page, nodes[i]) else {
+                return;
+            };
+            if target.id() == page.id() {
+                return;
+            }
+
+            let links = backlinks.entry(target.id()).or_default();
+            if !links
+                .iter()
+                .any(|link| link.from == page.id() && link.access == *access)
+            {
+                links.push(Backlink {
+                    from: page.id(),
+                    context: link_context(config, nodes, i),
+                    access: access.clone(),
+                });
+            }
+        });
+    }
+
+    let count: usize = backlinks.values().map(Vec::len).sum();
+    log::debug(format!("Found {count} links between pages."));
+    tree.set_backlinks(backlinks);
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::{
+    config::Config,
+    log,
+    model::{El, Id, Node, Tag, WikiPage, WikiTree},
+};
+
+use super::{RenderState, html::Html, html::image_url, links::find_url, reader_keys};
+
+/// Tags from the given list which content is encrypted for, in order.
+pub fn protected_tags(config: &Config, tags: &[Tag]) -> Vec<Tag> {
+    tags.iter()
+        .filter(|tag| config.is_protected(tag))
+        .cloned()
+        .collect()
+}
+
+/// Record the encryption context of each image referenced by nodes. Once
+/// inside encrypted content, nested tags have no further effect, as when
+/// rendering.
+fn collect_references(
+    state: &RenderState,
+    nodes: &[&Node],
+    context: &[Tag],
+    references: &mut HashMap<Id, Vec<Vec<Tag>>>,
+) {
+    let mut i = 0;
+    while i < nodes.len() {
+        let node = nodes[i];
+        let tags = if context.is_empty() {
+            protected_tags(state.config, node.tags())
+        } else {
+            Vec::new()
+        };
+
+        // A tagged heading encrypts its whole section.
+        let end = match node.el() {
+            El::Heading(level, _) if !tags.is_empty() => nodes[(i + 1)..]
+                .iter()
+                .position(|n| matches!(n.el(), El::Heading(l, _) if l == level))
+                .map(|p| i + 1 + p)
+                .unwrap_or(nodes.len()),
+            _ => i + 1,
+        };
+
+        let context = if tags.is_empty() { context } else { &tags };
+        for node in &nodes[i..end] {
+            if let Some(media) =
+                image_url(state, node).and_then(|url| find_url(state.tree, state.page, &url))
+                && media.is_media()
+            {
+                references
+                    .entry(media.id())
+                    .or_default()
+                    .push(context.to_vec());
+            }
+            collect_references(state, &node.children(), context, references);
+        }
+        i = end;
+    }
+}
+
+/// Protected tags of the index pages of directories containing the page.
+fn directory_tags(config: &Config, tree: &WikiTree, page: &WikiPage) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut current = tree.get_parent(page);
+    while let Some(dir) = current {
+        for index in tree.children(dir.id()).into_iter().filter(|p| p.is_index()) {
+            for tag in protected_tags(config, &index.tags()) {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+        current = tree.get_parent(dir);
+    }
+    tags
+}
+
+/// Groups of tags to protect media with, given the encryption context of each
+/// reference to it, see `WikiPage::tag_groups`. Media is only protected if
+/// every reference is encrypted, with the tags common to all references. If
+/// there are none in common, readers of any one reference can read it.
+fn reference_groups(references: &[Vec<Tag>]) -> Vec<Vec<Tag>> {
+    if references.is_empty() || references.iter().any(Vec::is_empty) {
+        return Vec::new();
+    }
+
+    let mut common = references[0].clone();
+    common.retain(|tag| references.iter().all(|r| r.contains(tag)));
+    if !common.is_empty() {
+        return common.into_iter().map(|tag| vec![tag]).collect();
+    }
+
+    let mut any: Vec<Tag> = Vec::new();
+    for tag in references.iter().flatten() {
+        if !any.contains(tag) {
+            any.push(tag.clone());
+        }
+    }
+    vec![any]
+}
+
+/// Mark media which is only referenced from encrypted content, or which is in
+/// a directory whose index is tagged, to be encrypted with those tags. Other
+/// media is left unprotected, so this can be repeated after pages change.
+pub fn protect_media(config: &Config, tree: &mut WikiTree) {
+    if config.tag_keys.is_empty() {
+        return;
+    }
+
+    let mut references: HashMap<Id, Vec<Vec<Tag>>> = HashMap::new();
+    for page in tree.pages() {
+        let Some(doc) = page.document() else {
+            continue;
+        };
+
+        let mut html = Html::new();
+        let state = RenderState {
+            tree,
+            page,
+            config,
+            html: &mut html,
+        };
+        let nodes: Vec<&Node> = doc.nodes().iter().collect();
+        let context = protected_tags(config, &doc.meta().tags());
+        collect_references(&state, &nodes, &context, &mut references);
+    }
+
+    let mut protected = Vec::new();
+    for media in tree.pages().iter().filter(|page| page.is_media()) {
+        let tags = directory_tags(config, tree, media);
+        let groups = if tags.is_empty() {
+            let refs = references.get(&media.id()).map(Vec::as_slice);
+            reference_groups(refs.unwrap_or_default())
+        } else {
+            tags.into_iter().map(|tag| vec![tag]).collect()
+        };
+
+        if !groups.is_empty() {
+            log::debug(format!("Protecting {} with encryption.", media.url()));
+        }
+        protected.push((media.id(), groups));
+    }
+
+    for (id, groups) in protected {
+        tree.protect_media(id, groups);
+    }
+}
+
+/// Contents to output for a media file. Protected media is sealed for the
+/// readers of its tags, with the wrapped keys at the start of the file.
+pub fn media_contents(config: &Config, media: &WikiPage) -> Result<Vec<u8>, String> {
+    let Some(file) = media.file() else {
+        return Err(format!("No file for media {}", media.url()));
+    };
+    let data = std::fs::read(file)
+        .map_err(|e| format!("Failed to read file ({}): {e}", file.display()))?;
+
+    let Some(access) = config.group_access(media.tag_groups()) else {
+        return Ok(data);
+    };
+    let groups = reader_keys(config, &access);
+    super::aes::seal(&data, &groups, &config.kdf_salt, config.kdf_iterations)
+        .map(|sealed| sealed.to_bytes())
+        .map_err(|_| format!("Failed to encrypt {}", media.url()))
+}
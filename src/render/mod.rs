@@ -1,16 +1,32 @@
 mod aes;
+mod check;
 mod css;
+mod highlight;
 mod html;
+mod links;
+mod media;
 mod nav;
+mod pages;
+mod search;
+mod toc;
 
 #[cfg(test)]
 mod test;
 
-use crate::config::Config;
-use crate::model::{Tag, WikiPage, WikiTree};
+use crate::config::{Access, Config};
+use crate::model::{El, Node, Tag, WikiPage, WikiTree};
 
-pub use self::html::render_document;
-pub use self::nav::create_index;
+pub use self::aes::{SALT_LEN, Sealed, b64_decode, b64_encode, derive_keys, random_salt};
+pub use self::check::check_tree;
+pub use self::html::{
+    KDF_META_NAME, KEY_TEST_CLASS, PAGE_TITLE_ID, PREVIOUS_KEY_TEST_CLASS, SECRET_CLASS,
+    SECRET_MEDIA_CLASS, render_document,
+};
+pub use self::links::{link_pages, link_resolutions};
+pub use self::media::{media_contents, protect_media};
+pub use self::nav::{NavTree, create_index};
+pub use self::pages::protect_pages;
+pub use self::search::{SEARCH_INDEX_FILE, search_index};
 
 pub const INDEX_FILE: &str = "index.html";
 pub const OUTPUT_EXT: &str = "html";
@@ -48,35 +64,124 @@ fn capitalise_word(word: &str) -> String {
     }
 }
 
-fn encryption_pairs<'a>(
-    state: &'a RenderState,
-    tags: &[Tag],
-) -> Option<Vec<(&'a Tag, &'a String)>> {
-    if tags.is_empty() {
-        None
-    } else {
-        let pairs: Vec<(&Tag, &String)> = state
-            .config
-            .tag_keys
-            .iter()
-            .filter(|(k, _)| tags.contains(k))
-            .collect();
-        if pairs.is_empty() {
-            None
-        } else {
-            Some(pairs)
+/// Who can read content with the given tags, if it is to be encrypted.
+fn encryption_access(state: &RenderState, tags: &[Tag]) -> Option<Access> {
+    state.config.access(tags)
+}
+
+/// Access needing the keys of both `outer` and `inner`, as for content
+/// encrypted within other encrypted content.
+fn nested_access(outer: &Access, inner: Access) -> Access {
+    let mut access = outer.clone();
+    for group in inner {
+        if !access.contains(&group) {
+            access.push(group);
+        }
+    }
+    access
+}
+
+/// Who can read a page: readers of its own tags and those of every directory
+/// it is in, as protected directories hide the pages within them. Empty if
+/// the page is public.
+fn page_access(config: &Config, tree: &WikiTree, page: &WikiPage) -> Access {
+    let mut access = config.access(&page.tags()).unwrap_or_default();
+    let mut parent = tree.get_parent(page);
+    while let Some(dir) = parent.filter(|dir| !dir.is_root()) {
+        if let Some(dir_access) = config.access(&tree.page_tags(dir)) {
+            access = nested_access(&access, dir_access);
+        }
+        parent = tree.get_parent(dir);
+    }
+    access
+}
+
+/// Call f with each node, in document order, along with the nodes it is
+/// among and the access needed to read it. Tagged nodes and the sections of
+/// tagged headings need the keys for their tags as well as `access`, as when
+/// rendered.
+fn walk_access<'a>(
+    config: &Config,
+    nodes: &[&'a Node],
+    access: &Access,
+    f: &mut impl FnMut(&[&'a Node], usize, &Access),
+) {
+    let mut i = 0;
+    while i < nodes.len() {
+        let node = nodes[i];
+        let Some(inner) = config.access(node.tags()) else {
+            f(nodes, i, access);
+            walk_access(config, &node.children(), access, f);
+            i += 1;
+            continue;
+        };
+
+        let end = match node.el() {
+            El::Heading(level, _) => nodes[(i + 1)..]
+                .iter()
+                .position(|n| matches!(n.el(), El::Heading(l, _) if l == level))
+                .map_or(nodes.len(), |p| i + 1 + p),
+            _ => i + 1,
+        };
+        let access = nested_access(access, inner);
+        f(nodes, i, &access);
+        walk_access(config, &node.children(), &access, f);
+        walk_access(config, &nodes[(i + 1)..end], &access, f);
+        i = end;
+    }
+}
+
+/// Every tag able to read content, in order.
+fn access_tags(access: &Access) -> Vec<&str> {
+    let mut tags: Vec<&str> = Vec::new();
+    for tag in access.iter().flatten() {
+        if !tags.contains(&tag.as_ref()) {
+            tags.push(tag.as_ref());
         }
     }
+    tags
+}
+
+/// Tag and key of each reader, grouped as in access.
+fn reader_keys<'a>(config: &'a Config, access: &'a Access) -> Vec<Vec<(&'a str, &'a str)>> {
+    access
+        .iter()
+        .map(|readers| {
+            readers
+                .iter()
+                .filter_map(|tag| {
+                    config
+                        .tag_keys
+                        .get(tag)
+                        .map(|key| (tag.as_ref(), key.as_str()))
+                })
+                .collect()
+        })
+        .collect()
 }
 
 pub fn capitalise(title: &str) -> String {
     title
-        .split(|c| c == ' ' || c == '-' || c == '_')
+        .split([' ', '-', '_'])
         .map(capitalise_word)
         .collect::<Vec<String>>()
         .join(" ")
 }
 
+/// Convert text to a `kebab-case` slug suitable for use as a URL fragment,
+/// dropping any punctuation.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
 #[cfg(test)]
 fn render_node(node: &crate::model::Node) -> String {
     let mut tree = WikiTree::new();
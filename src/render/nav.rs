@@ -1,23 +1,94 @@
+use std::collections::HashMap;
+
 use crate::{
-    model::{Doc, Node, WikiPage, WikiTree},
+    config::Config,
+    model::{Doc, El, Id, Node, WikiPage, WikiTree},
     render::css::{floating_menu, title, with_class},
 };
 
-use super::{RenderState, css::with_id, encryption_pairs, html::encrypt_nodes};
+use super::{
+    RenderState,
+    css::with_id,
+    encryption_access,
+    html::{Html, encrypt_nodes, render_nodes_only},
+};
 
 fn make_page_link(page: &WikiPage) -> Node {
     Node::link(page.title(), page.url())
 }
 
 fn page_encryption<'a>(state: &'a RenderState, page: &'a WikiPage, node: Node) -> Node {
-    if let Some(pairs) = encryption_pairs(state, page.tags()) {
-        encrypt_nodes(state, &pairs, &[node], false)
+    if let Some(access) = encryption_access(state, &state.tree.page_tags(page)) {
+        encrypt_nodes(state, &access, &[node], false)
     } else {
         node
     }
 }
 
-fn make_nav_subtree<'a>(state: &'a RenderState, mut current: &'a WikiPage) -> Node {
+/// Children of a page in the order they should be listed.
+fn ordered_children(tree: &WikiTree, id: Id) -> Vec<&WikiPage> {
+    let mut children = tree.children(id);
+    children.sort_by_key(|child| child.order());
+    children
+}
+
+/// Entries of the nav tree for a build, made once rather than for every page.
+/// The subtree of a page is the same on every page outside it, where nothing
+/// in it is selected or open, so is kept rendered as a list item and only
+/// those around the current page are made again when rendering it.
+#[derive(Default)]
+pub struct NavTree {
+    subtrees: HashMap<Id, Node>,
+}
+
+impl NavTree {
+    pub fn new(config: &Config, tree: &WikiTree) -> Self {
+        let mut nav = Self::default();
+        if !config.nav_tree {
+            return nav;
+        }
+
+        let mut html = Html::new();
+        let state = RenderState {
+            tree,
+            page: tree.get(WikiTree::ROOT).expect("tree has a root"),
+            config,
+            html: &mut html,
+        };
+        for child in tree.children(WikiTree::ROOT) {
+            nav.add_subtree(&state, child);
+        }
+        nav
+    }
+
+    /// Add the subtree of a page, after those of its children.
+    fn add_subtree(&mut self, state: &RenderState, page: &WikiPage) {
+        for child in state.tree.children(page.id()) {
+            self.add_subtree(state, child);
+        }
+        let subtree = make_nav_subtree(state, self, None, page);
+        let item = match subtree.el() {
+            _ if subtree.is_empty() => subtree,
+            El::Item(_) => render_item(state, subtree),
+            _ => render_item(state, Node::item(vec![subtree])),
+        };
+        self.subtrees.insert(page.id(), item);
+    }
+}
+
+fn render_item(state: &RenderState, item: Node) -> Node {
+    let html = render_nodes_only(state.config, state.tree, state.page, &[item], false);
+    Node::html(html)
+}
+
+/// Entry of the nav tree for current and the pages within it, with `page`
+/// selected and the directories leading to it open.
+fn make_nav_subtree<'a>(
+    state: &'a RenderState,
+    nav: &NavTree,
+    page: Option<&WikiPage>,
+    mut current: &'a WikiPage,
+) -> Node {
     const THIS_PAGE_CLASS: &str = "nav-tree-selected";
     const CLASS_BULLET: &str = "nav-tree-bullet";
 
@@ -25,12 +96,18 @@ fn make_nav_subtree<'a>(state: &'a RenderState, mut current: &'a WikiPage) -> No
         return Node::empty();
     }
 
+    let is_within =
+        page.is_some_and(|page| page.id() == current.id() || page.is_descendent_of(current.id()));
+    if !is_within && let Some(subtree) = nav.subtrees.get(&current.id()) {
+        return subtree.clone();
+    }
+
     let mut children = Vec::new();
-    for child in state.tree.children(current.id()) {
+    for child in ordered_children(state.tree, current.id()) {
         if child.is_index() {
             current = child;
         } else {
-            let subtree = make_nav_subtree(state, child);
+            let subtree = make_nav_subtree(state, nav, page, child);
             if !subtree.is_empty() {
                 children.push(subtree);
             }
@@ -38,20 +115,21 @@ fn make_nav_subtree<'a>(state: &'a RenderState, mut current: &'a WikiPage) -> No
     }
 
     let mut link = make_page_link(current);
-    if current.id() == state.page.id() {
+    if page.is_some_and(|page| current.id() == page.id()) {
         link = with_class(link, THIS_PAGE_CLASS);
     }
 
     let node = if !children.is_empty() {
         let mut node = Node::details(vec![link], vec![Node::list(children)]);
 
-        if state.page.is_descendent_of(current.id())
-            || (current.is_index()
-                && current
-                    .parent()
-                    .map(|p| state.page.is_descendent_of(p))
-                    .unwrap_or(false))
-        {
+        if page.is_some_and(|page| {
+            page.is_descendent_of(current.id())
+                || (current.is_index()
+                    && current
+                        .parent()
+                        .map(|p| page.is_descendent_of(p))
+                        .unwrap_or(false))
+        }) {
             node.attr("open", "");
         }
         node
@@ -64,12 +142,12 @@ fn make_nav_subtree<'a>(state: &'a RenderState, mut current: &'a WikiPage) -> No
     page_encryption(state, current, node)
 }
 
-pub fn make_nav_tree(state: &RenderState) -> Node {
+pub fn make_nav_tree(state: &RenderState, nav: &NavTree) -> Node {
     const NAV_TREE_ID: &str = "nav-tree";
 
     let mut items = Vec::new();
-    for child in state.tree.children(WikiTree::ROOT) {
-        let subtree = make_nav_subtree(state, child);
+    for child in ordered_children(state.tree, WikiTree::ROOT) {
+        let subtree = make_nav_subtree(state, nav, Some(state.page), child);
         if !subtree.is_empty() {
             items.push(subtree);
         }
@@ -115,13 +193,18 @@ pub fn make_nav_breadcrumb(state: &RenderState) -> Node {
     }
 }
 
-pub fn create_index(page: &WikiPage, children: &[&WikiPage]) -> Doc {
+/// Index listing the children of a directory. Entries for protected pages are
+/// tagged, so are encrypted when rendered.
+pub fn create_index(tree: &WikiTree, page: &WikiPage, children: &[&WikiPage]) -> Doc {
+    let mut children = children.to_vec();
+    children.sort_by_key(|child| child.order());
+
     Doc::from(vec![
         Node::heading(1, vec![Node::text(page.title())]),
         Node::list(
             children
                 .iter()
-                .map(|n| Node::link(n.title(), n.url()))
+                .map(|n| Node::link(n.title(), n.url()).with_tags(tree.page_tags(n)))
                 .collect(),
         ),
     ])
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use crate::{
+    config::Config,
+    log,
+    model::{Id, WikiTree},
+};
+
+use super::{aes::opaque_name, reader_keys};
+
+/// Give pages protected by keyed tags opaque file names, so that their URLs
+/// don't reveal what they're about. A tagged index protects its directory,
+/// which is renamed instead. Names are derived from the tag keys and the salt
+/// of the site, so stay the same between builds.
+pub fn protect_pages(config: &Config, tree: &mut WikiTree) {
+    if config.tag_keys.is_empty() {
+        return;
+    }
+
+    let mut renames: Vec<(Id, String)> = Vec::new();
+    for page in tree.pages().iter().filter(|p| p.is_doc() || p.is_index()) {
+        let Some(access) = config.access(&page.tags()) else {
+            continue;
+        };
+
+        let target = if page.is_index() {
+            tree.get_parent(page)
+        } else {
+            Some(page)
+        };
+        let Some(target) = target.filter(|target| !target.is_root()) else {
+            continue;
+        };
+
+        let keys: Vec<&str> = reader_keys(config, &access)
+            .into_iter()
+            .flatten()
+            .map(|(_, key)| key)
+            .collect();
+        let mut name = opaque_name(&keys, target.url(), &config.kdf_salt, config.kdf_iterations);
+        if let Some(ext) = Path::new(target.filename()).extension() {
+            name.push('.');
+            name.push_str(&ext.to_string_lossy());
+        }
+
+        log::debug(format!("Renaming protected {} to {name}.", target.url()));
+        renames.push((target.id(), name));
+    }
+
+    for (id, name) in renames {
+        tree.rename(id, &name);
+    }
+}
@@ -0,0 +1,16 @@
+/**
+ * This script adds copying to the buttons of code blocks. Listening on the
+ * document handles code blocks which are only added once decrypted.
+ */
+document.addEventListener("click", event => {
+    const button = event.target.closest(".copy-code");
+    if (!button) {
+        return;
+    }
+
+    const code = button.parentNode.querySelector("code");
+    navigator.clipboard.writeText(code.textContent).then(() => {
+        button.innerText = "Copied";
+        setTimeout(() => button.innerText = "Copy", 2000);
+    });
+});
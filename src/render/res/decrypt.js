@@ -8,12 +8,33 @@
  */
 
 /**
- * Separator for nonce and tag attributes. For example an encrypted element
- * might have an attribute `tags="dm;paladin;phymme"`; the separator used is
- * this constant.
+ * Separator for the tags attribute. For example an encrypted element might
+ * have an attribute `tags="dm;paladin;phymme"`; the separator used is this
+ * constant.
  */
 const SEP = ";";
 
+/**
+ * Separators within the keys attribute of encrypted elements, and the header
+ * of encrypted media. Shares of the content key are separated by SHARE_SEP,
+ * the readers able to unwrap each share by READER_SEP, and the tag, nonce and
+ * wrapped share of each reader by FIELD_SEP.
+ */
+const SHARE_SEP = ";";
+const READER_SEP = ",";
+const FIELD_SEP = ":";
+
+/**
+ * Length in bytes of content keys, and so of each share of one.
+ */
+const CONTENT_KEY_LENGTH = 32;
+
+/**
+ * Value encrypted by the tests in the tag keys menu, filled in when rendered
+ * from KEY_TEST_VALUE in html.rs.
+ */
+const TEST_VALUE = "KEY_TEST_VALUE";
+
 /**
  * Key in local storage where added keys are stored.
  */
@@ -68,13 +89,43 @@ function base64toBytes(base64) {
     return stringToBytes(atob(base64));
 }
 
+/**
+ * Name of the meta element holding key derivation parameters for the page.
+ */
+const KDF_META_NAME = "mdwiki-kdf";
+
+/**
+ * Keys already derived from passwords. Derivation is deliberately slow, so
+ * each password is only derived once per page load.
+ */
+const derivedKeys = new Map();
+
+/**
+ * Read the parameters used to derive keys from passwords from the page.
+ * @returns {object | null} Salt and iterations, or null if the page doesn't
+ * specify any.
+ */
+function kdfParams() {
+    const meta = document.querySelector(`meta[name="${KDF_META_NAME}"]`);
+    if (!meta) {
+        return null;
+    }
+
+    return {
+        name: meta.getAttribute("content"),
+        salt: base64toBytes(meta.dataset.salt),
+        iterations: parseInt(meta.dataset.iterations),
+    };
+}
+
 /**
  * Given a string, return a 32-byte CryptoKey created by truncating the
- * provided key to 32 characters and then zero padding the end.
- * @param {string} key Password to create key from. 
+ * provided key to 32 characters and then zero padding the end. Used by pages
+ * rendered without key derivation parameters.
+ * @param {string} key Password to create key from.
  * @returns {Promise<CryptoKey>} Key created from the provided string.
  */
-async function createKey(key) {
+async function createLegacyKey(key) {
     const KEY_LENGTH = 32;
 
     let key_bytes = new Uint8Array(KEY_LENGTH);
@@ -91,6 +142,49 @@ async function createKey(key) {
     );
 }
 
+/**
+ * Derive a 256-bit AES-GCM key from a password using PBKDF2-SHA256.
+ * @param {string} key Password to derive key from.
+ * @param {object} params Salt and iterations to use.
+ * @returns {Promise<CryptoKey>} Key derived from the password.
+ */
+async function deriveKey(key, params) {
+    const material = await crypto.subtle.importKey(
+        "raw", new TextEncoder().encode(key), params.name, false, ["deriveKey"]
+    );
+
+    return await crypto.subtle.deriveKey(
+        {
+            name: params.name,
+            hash: "SHA-256",
+            salt: params.salt,
+            iterations: params.iterations,
+        },
+        material,
+        { name: "AES-GCM", length: 256 },
+        false,
+        ["decrypt"]
+    );
+}
+
+/**
+ * Given a password, return the CryptoKey to decrypt content with, using the
+ * key derivation parameters embedded in the page.
+ * @param {string} key Password to create key from.
+ * @returns {Promise<CryptoKey>} Key created from the provided string.
+ */
+async function createKey(key) {
+    const params = kdfParams();
+    if (!params) {
+        return await createLegacyKey(key);
+    }
+
+    if (!derivedKeys.has(key)) {
+        derivedKeys.set(key, deriveKey(key, params));
+    }
+    return await derivedKeys.get(key);
+}
+
 /**
  * Decrypt the provided data using a nonce and password.
  * @param {string} nonce Base64 encoded initialisation vector.
@@ -105,32 +199,73 @@ async function decrypt(nonce, data, keyText) {
 }
 
 /**
- * Given a possibly multiply encrypted block of raw data, attempt to
- * consecutively decrypt it using the provided nonces and passwords found in
- * keys for each tag.
- * @param {ArrayBuffer} data AES encrypted data to decrypt
- * @param {string[]} tags Tags to find keys for. 
- * @param {string[]} nonces Base64 encoded nonces associated with tags. 
- * @param {object} keys Map from tag to associated password. 
- * @returns {Promise<ArrayBuffer | null>} Decoded data on success, or null.
+ * Parse the wrapped shares of a content key.
+ * @param {string} keysAttr Shares as found in the keys attribute.
+ * @returns {object[][]} For each share, the tag, nonce and wrapped share for
+ * each of its readers.
  */
-async function decryptChained(data, tags, nonces, keys) {
-    if (tags.length == 0) {
-        // Once all tags have been decrypted, this should be plain text.
-        return new TextDecoder().decode(data);;
+function parseShares(keysAttr) {
+    return keysAttr.split(SHARE_SEP).map(share =>
+        share.split(READER_SEP).map(reader => {
+            const [tag, nonce, wrapped] = reader.split(FIELD_SEP);
+            return { tag, nonce, wrapped };
+        })
+    );
+}
+
+/**
+ * Check whether there is a key to unwrap every share of a content key.
+ * @param {object[][]} shares Shares from parseShares.
+ * @param {object} keys Map from tag to tag key.
+ * @returns {boolean} Whether all shares have a reader with a key.
+ */
+function canUnlock(shares, keys) {
+    return shares.every(readers => readers.some(reader => reader.tag in keys));
+}
+
+/**
+ * Unwrap a share of a content key using the key of any of its readers.
+ * @param {object[]} readers Readers able to unwrap the share.
+ * @param {object} keys Map from tag to tag key.
+ * @returns {Promise<Uint8Array>} The share.
+ */
+async function unwrapShare(readers, keys) {
+    for (const reader of readers) {
+        if (!(reader.tag in keys)) {
+            continue;
+        }
+
+        try {
+            const wrapped = base64toBytes(reader.wrapped);
+            return new Uint8Array(await decrypt(reader.nonce, wrapped, keys[reader.tag]));
+        } catch {
+            // The key may be wrong; another reader's might not be.
+        }
     }
+    throw new Error("No key to unwrap share");
+}
 
-    let key = keys[tags[0]];
-    if (!key) {
-        // No password available for this key. Fail.
-        return null;
+/**
+ * Decrypt data sealed with a content key, which is the XOR of its shares.
+ * @param {Uint8Array} nonce Nonce the data was encrypted with.
+ * @param {Uint8Array} data Encrypted data.
+ * @param {object[][]} shares Shares of the content key from parseShares.
+ * @param {object} keys Map from tag to tag key.
+ * @returns {Promise<ArrayBuffer>} Decrypted data.
+ */
+async function openSealed(nonce, data, shares, keys) {
+    const keyBytes = new Uint8Array(CONTENT_KEY_LENGTH);
+    for (const readers of shares) {
+        const share = await unwrapShare(readers, keys);
+        share.forEach((byte, i) => keyBytes[i] ^= byte);
     }
 
-    const nextData = await decrypt(nonces[0], data, key);
-    return await decryptChained(nextData, tags.slice(1), nonces.slice(1), keys);
+    const key = await crypto.subtle.importKey(
+        "raw", keyBytes, "AES-GCM", false, ["decrypt"]
+    );
+    return await crypto.subtle.decrypt({ name: "AES-GCM", iv: nonce }, key, data);
 }
 
-
 /**
  * Given an element, return a list of tags for which a key is required to
  * decrypt the content of the given element.
@@ -140,12 +275,17 @@ async function decryptChained(data, tags, nonces, keys) {
 function elTags(el) {
     const tagList = el.getAttribute("tags");
     if (tagList) {
-        return tagList.split(SEP).reverse();
+        return tagList.split(SEP);
     } else {
         return [];
     }
 }
 
+/**
+ * ID of the element holding the title of a protected page, once decrypted.
+ */
+const PAGE_TITLE_ID = "page-title";
+
 /**
  * Render HTML to elements for insertion.
  * @param {string} html HTML to load as elements.
@@ -164,19 +304,27 @@ function htmlToElements(html) {
  * @return {Promise<boolean>} Whether the element was successfully updated.
  */
 async function decryptEl(el, keys, replace = true) {
-    const cipherText = stringToBytes(atob(el.innerText));
-    const tags = elTags(el);
-    const nonces = el.getAttribute("nonces").split(SEP).reverse();
+    const cipherText = base64toBytes(el.innerText);
+    const nonce = base64toBytes(el.getAttribute("nonce"));
+    const shares = parseShares(el.getAttribute("keys"));
 
     let plainText;
     try {
-        plainText = await decryptChained(cipherText, tags, nonces, keys);
+        const data = await openSealed(nonce, cipherText, shares, keys);
+        plainText = new TextDecoder().decode(data);
     } catch {
         plainText = null;
     }
     if (plainText) {
         if (replace) {
-            return el.parentNode.replaceChild(htmlToElements(plainText), el);
+            const elements = htmlToElements(plainText);
+            elements.querySelectorAll(".secret-media")
+                .forEach(img => decryptMedia(img, keys));
+            const title = elements.getElementById(PAGE_TITLE_ID);
+            if (title) {
+                document.title = title.textContent;
+            }
+            return el.parentNode.replaceChild(elements, el);
         } else {
             return plainText;
         }
@@ -185,6 +333,45 @@ async function decryptEl(el, keys, replace = true) {
     }
 }
 
+/**
+ * Length in bytes of the nonce in encrypted media.
+ */
+const MEDIA_NONCE_LENGTH = 12;
+
+/**
+ * Fetch an encrypted media file for an image, decrypt it using the keys for
+ * the image's tags and display it from a blob URL. The file starts with the
+ * length of the wrapped shares of its content key as a 32-bit big-endian
+ * integer, then the shares, the nonce and the ciphertext.
+ * @param {HTMLImageElement} img Image with the URL of the media in data-src.
+ * @param {object} keys Map from tag to decryption key.
+ * @return {Promise<boolean>} Whether the image was successfully decrypted.
+ */
+async function decryptMedia(img, keys) {
+    if (img.src || !elTags(img).some(tag => tag in keys)) {
+        return false;
+    }
+
+    try {
+        const response = await fetch(img.dataset.src);
+        const file = new Uint8Array(await response.arrayBuffer());
+        const keysLength = new DataView(file.buffer).getUint32(0);
+        const keysEnd = 4 + keysLength;
+        const shares = parseShares(new TextDecoder().decode(file.slice(4, keysEnd)));
+        if (!canUnlock(shares, keys)) {
+            return false;
+        }
+
+        const nonce = file.slice(keysEnd, keysEnd + MEDIA_NONCE_LENGTH);
+        const ciphertext = file.slice(keysEnd + MEDIA_NONCE_LENGTH);
+        const data = await openSealed(nonce, ciphertext, shares, keys);
+        img.src = URL.createObjectURL(new Blob([data]));
+        return true;
+    } catch {
+        return false;
+    }
+}
+
 /**
  * Test an entry from the tag keys menu to check that the key is correct,
  * updating the input to reflect the result.
@@ -193,8 +380,6 @@ async function decryptEl(el, keys, replace = true) {
  * @param {object} keys Map from tag to tag key.
  */
 async function testKey(entry, keys) {
-    const TEST_VALUE = "correct";
-
     const tag = entry.querySelector(".tag-keys-label").innerText;
     const key = entry.querySelector("input");
     const test = entry.querySelector(".tag-keys-test");
@@ -205,6 +390,8 @@ async function testKey(entry, keys) {
             key.disabled = true;
             key.style.backgroundColor = "";
             key.title = "Unlocked";
+        } else if (await isPreviousKey(entry, keys)) {
+            promptChangedKey(entry, tag);
         } else {
             key.disabled = false;
             key.style.backgroundColor = "var(--bg3)";
@@ -213,6 +400,47 @@ async function testKey(entry, keys) {
     }
 }
 
+/**
+ * Check whether the stored key for an entry from the tag keys menu is one the
+ * tag had before its key was changed.
+ *
+ * @param {HTMLElement} entry Entry to test key for.
+ * @param {object} keys Map from tag to tag key.
+ * @returns {Promise<boolean>} Whether the key is a previous one.
+ */
+async function isPreviousKey(entry, keys) {
+    for (const test of entry.querySelectorAll(".tag-keys-previous")) {
+        if (await decryptEl(test, keys, false) === TEST_VALUE) {
+            return true;
+        }
+    }
+    return false;
+}
+
+/**
+ * Forget the stored key for a tag whose key has been changed, and open the tag
+ * keys menu to ask for the new one.
+ *
+ * @param {HTMLElement} entry Entry of the tag in the tag keys menu.
+ * @param {string} tag Tag whose key has changed.
+ */
+function promptChangedKey(entry, tag) {
+    removeKey(tag);
+
+    const key = entry.querySelector("input");
+    key.value = "";
+    key.disabled = false;
+    key.style.backgroundColor = "var(--bg3)";
+    key.title = "Key changed";
+    key.placeholder = "Key changed, enter the new key";
+
+    const menu = entry.closest("details");
+    if (menu) {
+        menu.open = true;
+    }
+    key.focus();
+}
+
 /**
  * Loads the tag key map from local storage.
  * @returns {object} Map from tag to tag key from local storage.
@@ -247,21 +475,6 @@ function removeKey(tag) {
     return keys;
 }
 
-/**
- * Check if each tag in tags has an associated key present in the given map.
- * @param {string[]} tags Tags to check for associated keys.
- * @param {string[]} keys Key map to check in.
- * @return {boolean} Whether all keys are available.
- */
-function allKeysAvailable(tags, keys) {
-    for (const tag of tags) {
-        if (!(tag in keys)) {
-            return false;
-        }
-    }
-    return true;
-}
-
 /**
  * Decrypt all elements which have all keys available. Also updates the tag key
  * menu to reflect available and correct keys.
@@ -274,8 +487,11 @@ function decryptAvailable(keys) {
         .forEach(entry => testKey(entry, keys));
 
     document.querySelectorAll(".secret").forEach(el => {
-        if (allKeysAvailable(elTags(el), keys)) {
+        if (canUnlock(parseShares(el.getAttribute("keys")), keys)) {
             decryptEl(el, keys);
         }
     });
+
+    document.querySelectorAll(".secret-media")
+        .forEach(img => decryptMedia(img, keys));
 }
@@ -0,0 +1,229 @@
+/**
+ * This script searches the index of the wiki written when it was built, which
+ * defines SEARCH_INDEX. Encrypted content is indexed in sealed parts, which are
+ * opened with the tag keys in local storage in the same way as the content.
+ * Each entry of the index has the URL (u), title (t), tags (g), headings as
+ * anchor and text (h) and distinct words (w) of a part of a page.
+ */
+
+/**
+ * Maximum number of results listed.
+ */
+const SEARCH_RESULTS_LIMIT = 20;
+
+/**
+ * Pages of the index opened with the keys they were opened with, so that
+ * sealed parts are only opened again when the keys change.
+ */
+let searchPages = null;
+let searchKeys = null;
+
+window.addEventListener("load", () => {
+    const menu = document.getElementById("search-menu");
+    const input = menu.querySelector("input");
+    input.oninput = () => search(input.value);
+    input.onkeydown = event => {
+        const first = menu.querySelector("#search-results a");
+        if (event.key === "Enter" && first) {
+            window.location.href = first.href;
+        } else if (event.key === "Escape") {
+            input.value = "";
+            search("");
+        }
+    };
+});
+
+/**
+ * Combine entries of the index for the same page, as encrypted parts of a
+ * page are indexed separately from the rest.
+ * @param {object[]} entries Entries of the index.
+ * @returns {Map<string, object>} Map from URL to page.
+ */
+function mergeEntries(entries) {
+    const pages = new Map();
+    for (const entry of entries) {
+        const page = pages.get(entry.u)
+            || { url: entry.u, title: entry.t, tags: [], headings: [], words: [] };
+        page.tags.push(...entry.g);
+        page.headings.push(...entry.h);
+        page.words.push(...entry.w.split(" ").filter(word => word));
+        pages.set(entry.u, page);
+    }
+    return pages;
+}
+
+/**
+ * Open the parts of the index which the keys in local storage can read.
+ * @returns {Promise<Map<string, object>>} Map from URL to searchable page.
+ */
+async function searchablePages() {
+    const keys = loadKeys();
+    const keysJson = JSON.stringify(keys);
+    if (searchPages && searchKeys === keysJson) {
+        return searchPages;
+    }
+
+    const entries = [...SEARCH_INDEX.pages];
+    for (const sealed of SEARCH_INDEX.sealed) {
+        const shares = parseShares(sealed.keys);
+        if (!canUnlock(shares, keys)) {
+            continue;
+        }
+        try {
+            const data = await openSealed(
+                base64toBytes(sealed.nonce), base64toBytes(sealed.data), shares, keys);
+            entries.push(...JSON.parse(new TextDecoder().decode(data)));
+        } catch {
+            // Keys which don't open the part are reported by the keys menu.
+        }
+    }
+
+    searchPages = mergeEntries(entries);
+    searchKeys = keysJson;
+    return searchPages;
+}
+
+/**
+ * Edit distance between two words, or max + 1 if it is more than max.
+ * @param {string} a First word.
+ * @param {string} b Second word.
+ * @param {number} max Largest distance of interest.
+ * @returns {number} Number of insertions, deletions and substitutions.
+ */
+function editDistance(a, b, max) {
+    if (Math.abs(a.length - b.length) > max) {
+        return max + 1;
+    }
+    let previous = Array.from({ length: b.length + 1 }, (_, i) => i);
+    for (let i = 1; i <= a.length; i++) {
+        const current = [i];
+        for (let j = 1; j <= b.length; j++) {
+            const substitution = previous[j - 1] + (a[i - 1] === b[j - 1] ? 0 : 1);
+            current.push(Math.min(previous[j] + 1, current[j - 1] + 1, substitution));
+        }
+        if (Math.min(...current) > max) {
+            return max + 1;
+        }
+        previous = current;
+    }
+    return previous[b.length];
+}
+
+/**
+ * Score how well a search term matches a word: exactly, as a prefix, or
+ * within a small edit distance for longer terms.
+ * @param {string} term Search term, in lower case.
+ * @param {string} word Word of the page, in lower case.
+ * @returns {number} Score, 0 if it doesn't match.
+ */
+function matchWord(term, word) {
+    if (word === term) {
+        return 3;
+    } else if (word.startsWith(term)) {
+        return 2;
+    }
+    const max = term.length >= 7 ? 2 : term.length >= 4 ? 1 : 0;
+    return max > 0 && editDistance(term, word, max) <= max ? 1 : 0;
+}
+
+/**
+ * Best score of a term against some words.
+ * @param {string} term Search term, in lower case.
+ * @param {string[]} words Words to match against.
+ * @returns {number} Highest score of any word.
+ */
+function matchWords(term, words) {
+    let best = 0;
+    for (const word of words) {
+        best = Math.max(best, matchWord(term, word));
+        if (best === 3) {
+            break;
+        }
+    }
+    return best;
+}
+
+/**
+ * Split text into lower case words.
+ * @param {string} text Text to split.
+ * @returns {string[]} Words of the text.
+ */
+function searchWords(text) {
+    return text.toLowerCase().split(/[^\p{L}\p{N}]+/u).filter(word => word);
+}
+
+/**
+ * Score a page against the search terms. Every term must match somewhere,
+ * with matches in the title, tags and headings counting for more than those
+ * in the text.
+ * @param {object} page Page from the index.
+ * @param {string[]} terms Search terms.
+ * @returns {object|null} Score and best matching heading, or null.
+ */
+function scorePage(page, terms) {
+    const title = searchWords(page.title);
+    const tags = page.tags.map(tag => tag.toLowerCase());
+    let score = 0;
+    for (const term of terms) {
+        const termScore = 4 * matchWords(term, title)
+            + 2 * matchWords(term, tags)
+            + matchWords(term, page.words);
+        if (termScore === 0) {
+            return null;
+        }
+        score += termScore;
+    }
+
+    let heading = null;
+    let headingScore = 0;
+    for (const [id, text] of page.headings) {
+        const words = searchWords(text);
+        const matched = terms.reduce((sum, term) => sum + matchWords(term, words), 0);
+        if (matched > headingScore) {
+            heading = [id, text];
+            headingScore = matched;
+        }
+    }
+    return { score: score + 2 * headingScore, heading };
+}
+
+/**
+ * Search the index and list the best results below the search box.
+ * @param {string} query Text entered in the search box.
+ */
+async function search(query) {
+    const list = document.getElementById("search-results");
+    const terms = searchWords(query);
+    if (terms.length === 0 || typeof SEARCH_INDEX === "undefined") {
+        list.replaceChildren();
+        return;
+    }
+
+    const results = [];
+    for (const page of (await searchablePages()).values()) {
+        const result = scorePage(page, terms);
+        if (result) {
+            results.push({ page, ...result });
+        }
+    }
+    results.sort((a, b) => b.score - a.score || a.page.title.localeCompare(b.page.title));
+
+    const items = results.slice(0, SEARCH_RESULTS_LIMIT).map(({ page, heading }) => {
+        const item = document.createElement("li");
+        const link = document.createElement("a");
+        link.href = heading ? `${page.url}#${heading[0]}` : page.url;
+        link.textContent = heading ? `${page.title} › ${heading[1]}` : page.title;
+        item.appendChild(link);
+        return item;
+    });
+    if (items.length === 0) {
+        const item = document.createElement("li");
+        item.textContent = "No results";
+        items.push(item);
+    }
+
+    // Only show the results of the latest search, which may finish first.
+    if (document.getElementById("search-menu").querySelector("input").value === query) {
+        list.replaceChildren(...items);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{
+    config::{Access, Config},
+    log,
+    model::{El, Node, Tag, WikiPage, WikiTree},
+    render::css::{floating_menu, with_id},
+};
+
+use super::{
+    access_tags,
+    aes::{b64_encode, seal},
+    page_access, reader_keys, walk_access,
+};
+
+/// File at the root of the site holding the search index, as a script
+/// defining `SEARCH_INDEX`.
+pub const SEARCH_INDEX_FILE: &str = "search-index.js";
+
+/// The part of a page readable with some access, or by anyone.
+#[derive(Default)]
+struct Entry {
+    url: String,
+    title: String,
+    tags: Vec<String>,
+    /// Anchor and text of each heading.
+    headings: Vec<(String, String)>,
+    /// Distinct words of the text, in lower case.
+    words: Vec<String>,
+}
+
+impl Entry {
+    fn add_words(&mut self, text: &str) {
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            let word = word.to_lowercase();
+            if !word.is_empty() && !self.words.contains(&word) {
+                self.words.push(word);
+            }
+        }
+    }
+
+    fn add_tags(&mut self, tags: &[Tag]) {
+        for tag in tags {
+            if !self.tags.iter().any(|t| t == tag.as_ref()) {
+                self.tags.push(tag.as_ref().to_string());
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let tags: Vec<String> = self.tags.iter().map(|tag| json_string(tag)).collect();
+        let headings: Vec<String> = self
+            .headings
+            .iter()
+            .map(|(id, text)| format!("[{},{}]", json_string(id), json_string(text)))
+            .collect();
+        format!(
+            "{{\"u\":{},\"t\":{},\"g\":[{}],\"h\":[{}],\"w\":{}}}",
+            json_string(&self.url),
+            json_string(&self.title),
+            tags.join(","),
+            headings.join(","),
+            json_string(&self.words.join(" ")),
+        )
+    }
+}
+
+/// Entries of a page, one for each access its content needs. Public content
+/// has no access.
+struct PageEntries<'a> {
+    url: String,
+    title: &'a str,
+    parts: Vec<(Access, Entry)>,
+}
+
+impl PageEntries<'_> {
+    fn entry(&mut self, access: &Access) -> &mut Entry {
+        let i = match self.parts.iter().position(|(a, _)| a == access) {
+            Some(i) => i,
+            None => {
+                let entry = Entry {
+                    url: self.url.clone(),
+                    title: self.title.to_string(),
+                    ..Entry::default()
+                };
+                self.parts.push((access.clone(), entry));
+                self.parts.len() - 1
+            }
+        };
+        &mut self.parts[i].1
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '<' => json.push_str("\\u003c"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn index_page<'a>(config: &Config, tree: &WikiTree, page: &'a WikiPage) -> PageEntries<'a> {
+    let mut entries = PageEntries {
+        url: format!("{}{}", config.base_url, page.url()),
+        title: page.title(),
+        parts: Vec::new(),
+    };
+
+    let access = page_access(config, tree, page);
+    let entry = entries.entry(&access);
+    entry.add_words(page.title());
+    entry.add_tags(&page.tags());
+
+    let Some(doc) = page.document() else {
+        return entries;
+    };
+    let nodes: Vec<&Node> = doc.nodes().iter().collect();
+    walk_access(config, &nodes, &access, &mut |nodes, i, access| {
+        let node = nodes[i];
+        let entry = entries.entry(access);
+        entry.add_tags(node.tags());
+        if let Some(text) = node.el_text() {
+            entry.add_words(text);
+        }
+        if let El::Heading(..) = node.el()
+            && let Some(id) = node.attrs().get("id")
+        {
+            entry.headings.push((id.clone(), node.plain_text()));
+        }
+    });
+    entries
+}
+
+/// Entries readable with access, sealed so that they can only be searched
+/// once the keys are available.
+fn seal_entries(config: &Config, access: &Access, entries: &[Entry]) -> Option<String> {
+    let json: Vec<String> = entries.iter().map(Entry::to_json).collect();
+    let plaintext = format!("[{}]", json.join(","));
+    let groups = reader_keys(config, access);
+    let Ok(sealed) = seal(
+        plaintext.as_bytes(),
+        &groups,
+        &config.kdf_salt,
+        config.kdf_iterations,
+    ) else {
+        log::error("Failed to encrypt search index.");
+        return None;
+    };
+
+    Some(format!(
+        "{{\"tags\":{},\"keys\":{},\"nonce\":{},\"data\":{}}}",
+        json_string(&access_tags(access).join(";")),
+        json_string(&sealed.keys),
+        json_string(&b64_encode(&sealed.nonce)),
+        json_string(&b64_encode(&sealed.ciphertext)),
+    ))
+}
+
+/// Script defining the search index of every page in the tree. Public content
+/// is indexed in plain text, while encrypted content is indexed in a sealed
+/// part for each access, which the browser opens along with the content.
+pub fn search_index(config: &Config, tree: &WikiTree) -> String {
+    let mut public: Vec<Entry> = Vec::new();
+    let mut sealed: Vec<(Access, Vec<Entry>)> = Vec::new();
+    for page in tree.pages().iter().filter(|p| p.is_doc() || p.is_index()) {
+        for (access, entry) in index_page(config, tree, page).parts {
+            if access.is_empty() {
+                public.push(entry);
+            } else if let Some((_, entries)) = sealed.iter_mut().find(|(a, _)| *a == access) {
+                entries.push(entry);
+            } else {
+                sealed.push((access, vec![entry]));
+            }
+        }
+    }
+
+    let public: Vec<String> = public.iter().map(Entry::to_json).collect();
+    let sealed: Vec<String> = sealed
+        .iter()
+        .filter_map(|(access, entries)| seal_entries(config, access, entries))
+        .collect();
+    format!(
+        "const SEARCH_INDEX = {{\"pages\":[{}],\"sealed\":[{}]}};\n",
+        public.join(",\n"),
+        sealed.join(",\n"),
+    )
+}
+
+/// Floating search box, listing results below it.
+pub fn make_search_menu() -> Node {
+    const ID: &str = "search-menu";
+    const RESULTS_ID: &str = "search-results";
+
+    let input = Node::inline("input", Vec::new())
+        .with_attr("type", "search")
+        .with_attr("placeholder", "Search")
+        .with_attr("autocomplete", "off");
+    let results = with_id(Node::list(Vec::new()), RESULTS_ID);
+    with_id(floating_menu(Node::div(vec![input, results])), ID)
+}
@@ -1,8 +1,10 @@
+use std::collections::HashMap;
+
 use test::css::{with_class, with_id};
 
 use crate::{
-    config::Config,
-    model::{Doc, Id, Node, Style, WikiTree},
+    config::{Config, TagMatch},
+    model::{Align, Doc, Id, Meta, Node, Style, Tag, Value, WikiTree},
 };
 
 use super::html::*;
@@ -37,7 +39,10 @@ fn style() -> String {
     style.push_str(&indent(include_str!("res/style.css"), 3));
     style.push_str("\n    </style>\n");
     style.push_str("    <script>\n      ");
-    style.push_str(&indent(include_str!("res/decrypt.js"), 3));
+    style.push_str(&indent(&decrypt_script(), 3));
+    style.push_str("\n    </script>\n");
+    style.push_str("    <script>\n      ");
+    style.push_str(&indent(include_str!("res/code.js"), 3));
     style.push_str("\n    </script>");
     style
 }
@@ -76,11 +81,48 @@ fn wrap_main(title: &str, main: &[&str]) -> String {
     concat(&lines.iter().map(|s| s.as_str()).collect::<Vec<&str>>())
 }
 
+/// Config which encrypts content tagged `dm`.
+fn dm_config() -> Config {
+    Config {
+        tag_keys: HashMap::from([("dm".into(), String::from("password"))]),
+        ..Config::none()
+    }
+}
+
+/// Decrypt the first encrypted element in html with the given tag keys.
+fn decrypt_secret(config: &Config, html: &str, keys: &[(&str, &str)]) -> Option<String> {
+    use base64::Engine;
+    let b64 = |data: &str| base64::engine::general_purpose::STANDARD.decode(data).ok();
+
+    let class = html.find("class=\"secret\"")?;
+    let span = &html[html[..class].rfind("<span")?..];
+    let (open, rest) = span.split_once('>')?;
+    let attr = |name: &str| {
+        let (_, value) = open.split_once(&format!(" {name}=\""))?;
+        value.split_once('"').map(|(value, _)| value)
+    };
+
+    let sealed = aes::Sealed {
+        nonce: b64(attr("nonce")?)?.try_into().ok()?,
+        ciphertext: b64(rest.split_once('<')?.0.trim())?,
+        keys: attr("keys")?.to_string(),
+    };
+    let keys = keys.iter().copied().collect();
+    let plaintext = sealed.open(&keys, &config.kdf_salt, config.kdf_iterations)?;
+    String::from_utf8(plaintext).ok()
+}
+
 fn test_render_document(doc: impl Into<Doc>, main: &[&str]) {
     let title = "Page Title";
     let (tree, page) = make_file(doc.into(), title);
     assert_eq_lines(
-        render_document(&Config::none(), &tree, tree.get(page).unwrap()).unwrap(),
+        render_document(
+            &Config::none(),
+            &tree,
+            &NavTree::new(&Config::none(), &tree),
+            tree.get(page).unwrap(),
+        )
+        .unwrap(),
         wrap_main(title, main),
     );
 }
@@ -93,6 +135,13 @@ fn test_capitalise() {
     assert_eq!(capitalise("magic is a resource"), "Magic is a Resource");
 }
 
+#[test]
+fn test_slugify() {
+    assert_eq!(slugify("Market Square"), "market-square");
+    assert_eq!(slugify("  What's  up -- doc? "), "whats-up-doc");
+    assert_eq!(slugify("Ünïcode_Heading"), "ünïcode-heading");
+}
+
 const MD: &str = r#"
 # Test Markdown File
 
@@ -110,7 +159,9 @@ This is a test markdown file. It should
 fn test_render_heading() {
     test_render_document(
         vec![Node::heading(1, vec![Node::text("Hello World")])],
-        &["<h1>Hello World</h1>"],
+        &[
+            "<h1 id=\"hello-world\">Hello World <a class=\"permalink\" href=\"#hello-world\">¶</a></h1>",
+        ],
     );
 }
 
@@ -140,7 +191,7 @@ fn test_render_style() {
             Style::Italic,
             vec![Node::style(Style::Bold, vec![Node::text("italic bold")])]
         )),
-        concat!("<i><b>italic bold</b></i>")
+        "<i><b>italic bold</b></i>"
     )
 }
 
@@ -157,7 +208,7 @@ fn test_full_document() {
     test_render_document(
         crate::parse::parse_document(MD.trim()),
         &[
-            "<h1>Test Markdown File</h1>",
+            "<h1 id=\"test-markdown-file\">Test Markdown File <a class=\"permalink\" href=\"#test-markdown-file\">¶</a></h1>",
             "<p>",
             "  This is a test markdown file. It should",
             "</p>",
@@ -170,7 +221,7 @@ fn test_full_document() {
             "    </ul>",
             "  </li>",
             "</ul>",
-            "<h2>Handle Subheadings</h2>",
+            "<h2 id=\"handle-subheadings\">Handle Subheadings <a class=\"permalink\" href=\"#handle-subheadings\">¶</a></h2>",
         ],
     );
 }
@@ -187,8 +238,104 @@ fn test_code() {
 fn test_code_block() {
     assert_eq!(
         render_node(&Node::codeblock(Some("py"), "print(\"Hello World\")")),
-        "<pre>\n  print(&quot;Hello World&quot;)\n</pre>"
+        concat(&[
+            "<div class=\"codeblock\">",
+            "  <button class=\"copy-code\">Copy</button>",
+            concat!(
+                "  <pre class=\"language-py\"><code><span class=\"hl-function\">print</span>",
+                "(<span class=\"hl-string\">&quot;Hello World&quot;</span>)</code></pre>",
+            ),
+            "</div>",
+        ])
+    );
+    assert_eq!(
+        render_node(&Node::codeblock(None, "if a < b {\n    a\n}")),
+        concat(&[
+            "<div class=\"codeblock\">",
+            "  <button class=\"copy-code\">Copy</button>",
+            "  <pre><code>if a &lt; b {\n    a\n}</code></pre>",
+            "</div>",
+        ])
+    );
+}
+
+#[test]
+fn test_highlight() {
+    use highlight::highlight;
+
+    let span = |class: &str, text: &str| format!("<span class=\"hl-{class}\">{text}</span>");
+    assert_eq!(
+        highlight(
+            Some("rust"),
+            "fn f(c: &'a str) -> Vec<u8> { '\\n' } // done"
+        ),
+        format!(
+            "{} {}(c: &amp;'a str) -&gt; {}&lt;u8&gt; {{ {} }} {}",
+            span("keyword", "fn"),
+            span("function", "f"),
+            span("type", "Vec"),
+            span("string", "'\\n'"),
+            span("comment", "// done"),
+        )
+    );
+    assert_eq!(
+        highlight(Some("py"), "x = \"\"\"a\nb\"\"\" # 1.5\nprint(None, 1.5)"),
+        format!(
+            "x = {} {}\n{}({}, {})",
+            span("string", "&quot;&quot;&quot;a\nb&quot;&quot;&quot;"),
+            span("comment", "# 1.5"),
+            span("function", "print"),
+            span("literal", "None"),
+            span("number", "1.5"),
+        )
+    );
+    assert_eq!(
+        highlight(Some("sh"), "echo \"$HOME\" ${#x} $1 # home"),
+        format!(
+            "echo {} {} {} {}",
+            span("string", "&quot;$HOME&quot;"),
+            span("variable", "${#x}"),
+            span("variable", "$1"),
+            span("comment", "# home"),
+        )
+    );
+    assert_eq!(
+        highlight(Some("json"), "{\"a\":[1,true]}"),
+        format!(
+            "{{{}:[{},{}]}}",
+            span("key", "&quot;a&quot;"),
+            span("number", "1"),
+            span("literal", "true"),
+        )
     );
+    assert_eq!(
+        highlight(Some("toml"), "[tags.dm]\nencrypted = true"),
+        format!(
+            "{}\n{} = {}",
+            span("key", "[tags.dm]"),
+            span("key", "encrypted"),
+            span("literal", "true"),
+        )
+    );
+    assert_eq!(
+        highlight(Some("yaml"), "url: http://x\nok: yes"),
+        format!(
+            "{}: http://x\n{}: {}",
+            span("key", "url"),
+            span("key", "ok"),
+            span("literal", "yes"),
+        )
+    );
+    assert_eq!(
+        highlight(Some("SQL"), "SELECT name FROM t -- all"),
+        format!(
+            "{} name {} t {}",
+            span("keyword", "SELECT"),
+            span("keyword", "FROM"),
+            span("comment", "-- all"),
+        )
+    );
+    assert_eq!(highlight(Some("lisp"), "(< a b)"), "(&lt; a b)");
 }
 
 #[test]
@@ -241,7 +388,7 @@ fn test_link_included_in_paragraph() {
 
 #[test]
 fn test_encrypted_nodes() {
-    let config = &Config::default();
+    let config = &dm_config();
     let mut tree = WikiTree::new();
     let page = tree.add_doc(WikiTree::ROOT, "myfile.html", "My File", Doc::empty());
     let page = tree.get(page).unwrap();
@@ -257,7 +404,8 @@ fn test_encrypted_nodes() {
     assert!(html.contains("<h1>efgh</h1>"));
     assert!(html.starts_with("<span"));
     assert!(html.contains("class=\"secret\""));
-    assert!(html.contains("nonces=\""));
+    assert!(html.contains("nonce=\""));
+    assert!(html.contains("keys=\"dm:"));
     assert!(html.contains("tags=\"dm\""));
 }
 
@@ -275,6 +423,11 @@ fn make_state<'a>(
     }
 }
 
+/// Nav tree of the page being rendered, made just for it.
+fn nav_tree(state: &RenderState) -> Node {
+    make_nav_tree(state, &NavTree::default())
+}
+
 #[test]
 fn test_nav_tree() {
     let mut tree = WikiTree::new();
@@ -284,7 +437,7 @@ fn test_nav_tree() {
     tree.add_doc(country, "cityb", "Cityb", Doc::empty());
 
     assert_eq!(
-        make_nav_tree(&make_state(
+        nav_tree(&make_state(
             &tree,
             country,
             &mut Html::new(),
@@ -336,7 +489,7 @@ fn test_nav_tree_render() {
     tree.add_doc(page, "child", "Child", Doc::empty());
 
     assert_eq_lines(
-        render_node(&make_nav_tree(&make_state(
+        render_node(&nav_tree(&make_state(
             &tree,
             page,
             &mut Html::new(),
@@ -372,7 +525,7 @@ fn test_empty_dir_excluded() {
     let mut tree = WikiTree::new();
     let dir = tree.add_dir(WikiTree::ROOT, "dir");
     assert!(
-        !render_node(&make_nav_tree(&make_state(
+        !render_node(&nav_tree(&make_state(
             &tree,
             dir,
             &mut Html::new(),
@@ -383,12 +536,44 @@ fn test_empty_dir_excluded() {
     );
 }
 
+#[test]
+fn test_nav_tree_shared() {
+    let config = Config {
+        nav_tree: true,
+        ..Config::none()
+    };
+    let mut tree = WikiTree::new();
+    let places = tree.add_dir(WikiTree::ROOT, "places");
+    tree.add_index(places, "index.html", "Places", Doc::empty());
+    let country = tree.add_doc(places, "country.html", "Country", Doc::empty());
+    tree.add_doc(country, "city.html", "City", Doc::empty());
+    let people = tree.add_dir(WikiTree::ROOT, "people");
+    tree.add_doc(people, "king.html", "King", Doc::empty());
+
+    // Made once for every page, it's the same as made for each.
+    let nav = NavTree::new(&config, &tree);
+    for page in 0..tree.pages().len() {
+        let mut html = Html::new();
+        let state = make_state(&tree, page, &mut html, &config);
+        assert_eq_lines(
+            render_node(&make_nav_tree(&state, &nav)),
+            render_node(&nav_tree(&state)),
+        );
+
+        let page = tree.get(page).unwrap();
+        assert_eq!(
+            render_document(&config, &tree, &nav, page),
+            render_document(&config, &tree, &NavTree::default(), page)
+        );
+    }
+}
+
 #[test]
 fn test_index_replaces_dir() {
     let mut tree = WikiTree::new();
     let dir = tree.add_dir(WikiTree::ROOT, "dir");
     let idx = tree.add_index(dir, "index.html", "Index", Doc::empty());
-    let html = render_node(&make_nav_tree(&make_state(
+    let html = render_node(&nav_tree(&make_state(
         &tree,
         idx,
         &mut Html::new(),
@@ -413,7 +598,7 @@ fn test_media_excluded_from_nav_tree() {
         std::path::PathBuf::from("./image.png"),
     );
 
-    let html = render_node(&make_nav_tree(&make_state(
+    let html = render_node(&nav_tree(&make_state(
         &tree,
         page,
         &mut Html::new(),
@@ -442,3 +627,882 @@ fn test_breadcrumbs() {
         )
     );
 }
+
+#[test]
+fn test_render_table() {
+    assert_eq_lines(
+        render_node(&Node::table_with_header(
+            vec![Align::Left, Align::None, Align::Right],
+            vec![
+                vec![Node::text("Name")],
+                vec![Node::text("Class")],
+                vec![Node::text("Gold")],
+            ],
+            vec![
+                vec![
+                    vec![Node::text("Aria")],
+                    vec![Node::style(Style::Bold, vec![Node::text("Paladin")])],
+                    vec![Node::text("40")],
+                ],
+                vec![vec![Node::text("Borin")]],
+            ],
+        )),
+        concat(&[
+            "<table>",
+            "  <thead>",
+            "    <tr>",
+            "      <th style=\"text-align: left\">Name</th>",
+            "      <th>Class</th>",
+            "      <th style=\"text-align: right\">Gold</th>",
+            "    </tr>",
+            "  </thead>",
+            "  <tbody>",
+            "    <tr>",
+            "      <td style=\"text-align: left\">Aria</td>",
+            "      <td><b>Paladin</b></td>",
+            "      <td style=\"text-align: right\">40</td>",
+            "    </tr>",
+            "    <tr>",
+            "      <td style=\"text-align: left\">Borin</td>",
+            "      <td></td>",
+            "      <td style=\"text-align: right\"></td>",
+            "    </tr>",
+            "  </tbody>",
+            "</table>",
+        ]),
+    );
+}
+
+#[test]
+fn test_render_table_document() {
+    test_render_document(
+        crate::parse::parse_document("Party:\n\n| a | b |\n|---|:-:|\n| c | d |"),
+        &[
+            "<p>",
+            "  Party:",
+            "</p>",
+            "<table>",
+            "  <thead>",
+            "    <tr>",
+            "      <th>a</th>",
+            "      <th style=\"text-align: center\">b</th>",
+            "    </tr>",
+            "  </thead>",
+            "  <tbody>",
+            "    <tr>",
+            "      <td>c</td>",
+            "      <td style=\"text-align: center\">d</td>",
+            "    </tr>",
+            "  </tbody>",
+            "</table>",
+        ],
+    );
+}
+
+#[test]
+fn test_render_ordered_list() {
+    assert_eq_lines(
+        render_node(&Node::ordered_list(
+            4,
+            vec![
+                Node::item(vec![
+                    Node::text("Fourth"),
+                    Node::list(vec![Node::text("Bullet")]),
+                ]),
+                Node::text("Fifth"),
+            ],
+        )),
+        concat(&[
+            "<ol start=\"4\">",
+            "  <li>Fourth",
+            "    <ul>",
+            "      <li>Bullet</li>",
+            "    </ul>",
+            "  </li>",
+            "  <li>Fifth</li>",
+            "</ol>",
+        ]),
+    );
+    assert_eq!(
+        render_node(&Node::ordered_list(1, vec![Node::text("First")])),
+        "<ol>\n  <li>First</li>\n</ol>"
+    );
+}
+
+#[test]
+fn test_render_blockquote() {
+    test_render_document(
+        crate::parse::parse_document("> Quoted\n>\n> * item"),
+        &[
+            "<blockquote>",
+            "  <p>",
+            "    Quoted",
+            "  </p>",
+            "  <ul>",
+            "    <li>item</li>",
+            "  </ul>",
+            "</blockquote>",
+        ],
+    );
+}
+
+#[test]
+fn test_render_callout() {
+    test_render_document(
+        crate::parse::parse_document("> [!TIP]\n> Bring rope."),
+        &[
+            "<blockquote class=\"callout callout-tip\">",
+            "  <p class=\"callout-title\">Tip</p>",
+            "  <p>",
+            "    Bring rope.",
+            "  </p>",
+            "</blockquote>",
+        ],
+    );
+}
+
+fn meta(entries: &[(&str, Value)]) -> Meta {
+    let mut meta = Meta::default();
+    for (key, value) in entries {
+        meta.insert(key, value.clone());
+    }
+    meta
+}
+
+#[test]
+fn test_front_matter_rendering() {
+    let doc = Doc::from(vec![Node::text("Body")]).with_meta(meta(&[
+        ("template", Value::String("npc".into())),
+        ("date", Value::String("2024-05-01".into())),
+    ]));
+    let (tree, page) = make_file(doc, "Page Title");
+    let html = render_document(
+        &Config::none(),
+        &tree,
+        &NavTree::new(&Config::none(), &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap();
+    assert!(html.contains("<body class=\"template-npc\">"));
+    assert!(html.contains("<time datetime=\"2024-05-01\">2024-05-01</time>"));
+}
+
+#[test]
+fn test_front_matter_tags_encrypt_page() {
+    let doc = Doc::from(vec![
+        Node::heading(1, vec![Node::text("Secret Heading")]),
+        Node::text("Secret body"),
+    ])
+    .with_meta(meta(&[(
+        "tags",
+        Value::List(vec![Value::String("dm".into())]),
+    )]));
+    let (tree, page) = make_file(doc, "Page Title");
+    let html = render_document(
+        &dm_config(),
+        &tree,
+        &NavTree::new(&dm_config(), &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap();
+    assert!(!html.contains("Secret Heading"));
+    assert!(!html.contains("Secret body"));
+    assert!(html.contains("class=\"secret\""));
+}
+
+#[test]
+fn test_nav_tree_order() {
+    let mut tree = WikiTree::new();
+    tree.add_doc(WikiTree::ROOT, "b.html", "Bravo", Doc::empty());
+    tree.add_doc(
+        WikiTree::ROOT,
+        "c.html",
+        "Charlie",
+        Doc::empty().with_meta(meta(&[("order", Value::Integer(2))])),
+    );
+    let a = tree.add_doc(
+        WikiTree::ROOT,
+        "a.html",
+        "Alpha",
+        Doc::empty().with_meta(meta(&[("order", Value::Integer(1))])),
+    );
+
+    let html = render_node(&nav_tree(&make_state(
+        &tree,
+        a,
+        &mut Html::new(),
+        &Config::none(),
+    )));
+    let alpha = html.find("Alpha").unwrap();
+    let bravo = html.find("Bravo").unwrap();
+    let charlie = html.find("Charlie").unwrap();
+    assert!(alpha < charlie && charlie < bravo);
+}
+
+#[test]
+fn test_render_wiki_links() {
+    let mut tree = WikiTree::new();
+    let places = tree.add_dir(WikiTree::ROOT, "places");
+    tree.add_doc(places, "the-capital.html", "The Capital", Doc::empty());
+    tree.add_media(places, "world-map.png", "World Map", "world-map.png");
+    let page = tree.add_doc(WikiTree::ROOT, "page.html", "Page", Doc::empty());
+    let html = render_nodes_only(
+        &Config::none(),
+        &tree,
+        tree.get(page).unwrap(),
+        &[
+            Node::wiki_link("the capital#Market Square", "market"),
+            Node::wiki_link("#History", "History"),
+            Node::wiki_image("world-map.png", "Map"),
+            Node::wiki_image("World Map", "Map"),
+        ],
+        true,
+    );
+    assert_eq!(
+        html,
+        concat!(
+            "<a href=\"/places/the-capital.html#market-square\">market</a>",
+            " <a href=\"#history\">History</a>",
+            " <img src=\"/places/world-map.png\" alt=\"Map\">",
+            "<img src=\"/places/world-map.png\" alt=\"Map\">"
+        )
+    );
+}
+
+#[test]
+fn test_heading_ids_deduplicated() {
+    let mut tree = WikiTree::new();
+    let page = tree.add_doc(
+        WikiTree::ROOT,
+        "page.html",
+        "Page",
+        crate::parse::parse_document("# Notes\n## Notes\n> ## Notes!\n## Other `code`"),
+    );
+    assert_eq!(
+        tree.get(page).unwrap().document().unwrap().anchors(),
+        vec!["notes", "notes-1", "notes-2", "other-code"]
+    );
+}
+
+#[test]
+fn test_tagged_heading_ids() {
+    let mut tree = WikiTree::new();
+    let page = tree.add_doc(
+        WikiTree::ROOT,
+        "page.html",
+        "Page",
+        crate::parse::parse_document(
+            "# Inn\n\n#dm\n## Notes\n\n### Notes\n\nSecret\n\n## Notes\n\nPublic",
+        ),
+    );
+    // Public ids are the same as without the tagged section.
+    assert_eq!(
+        tree.get(page).unwrap().document().unwrap().anchors(),
+        vec!["inn", "notes-1", "notes-2", "notes"]
+    );
+}
+
+#[test]
+fn test_section_links() {
+    let mut tree = WikiTree::new();
+    tree.add_doc(
+        WikiTree::ROOT,
+        "city.html",
+        "City",
+        crate::parse::parse_document("# City\n## Market Square"),
+    );
+    let page = tree.add_doc(
+        WikiTree::ROOT,
+        "page.html",
+        "Page",
+        crate::parse::parse_document("## Local History"),
+    );
+    let config = Config {
+        empty_links: true,
+        ..Config::none()
+    };
+    let html = render_nodes_only(
+        &config,
+        &tree,
+        tree.get(page).unwrap(),
+        &[
+            Node::link("City#Market Square", ""),
+            Node::link("History", "#local-history"),
+            Node::link("History", "#Local History"),
+        ],
+        true,
+    );
+    assert_eq!(
+        html,
+        concat!(
+            "<a href=\"/city.html#market-square\">City#Market Square</a>",
+            " <a href=\"#local-history\">History</a>",
+            " <a href=\"#local-history\">History</a>",
+        )
+    );
+}
+
+fn toc_document() -> Doc {
+    crate::parse::parse_document(concat!(
+        "# Town\n",
+        "[TOC]\n",
+        "## History\n",
+        "### Founding\n",
+        "## Places\n",
+        "#dm\n",
+        "## Secret Lair\n",
+        "### Treasure\n",
+        "## People\n",
+    ))
+}
+
+#[test]
+fn test_toc_marker() {
+    let mut tree = WikiTree::new();
+    let page = tree.add_doc(WikiTree::ROOT, "town.html", "Town", toc_document());
+    let config = dm_config();
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap();
+    assert!(html.contains(&concat(&[
+        "        <nav class=\"toc\">",
+        "          <span class=\"title\">Contents</span>",
+        "          <ul>",
+        "            <li><a href=\"#town\">Town</a>",
+        "              <ul>",
+        "                <li><a href=\"#history\">History</a>",
+        "                  <ul>",
+        "                    <li><a href=\"#founding\">Founding</a></li>",
+        "                  </ul>",
+        "                </li>",
+        "                <li><a href=\"#places\">Places</a></li>",
+        "                <li><a href=\"#people\">People</a></li>",
+        "              </ul>",
+        "            </li>",
+        "          </ul>",
+        "        </nav>",
+    ])));
+    assert!(!html.contains("id=\"toc\""));
+    assert!(!html.contains("Secret Lair"));
+    assert!(!html.contains("Treasure"));
+}
+
+#[test]
+fn test_toc_menu_threshold() {
+    let doc = crate::parse::parse_document(
+        "# Town
+## History
+## Places",
+    );
+    let mut tree = WikiTree::new();
+    let page = tree.add_doc(WikiTree::ROOT, "town.html", "Town", doc);
+    let page = tree.get(page).unwrap();
+
+    let config = Config {
+        toc_min_headings: 3,
+        ..Config::none()
+    };
+    let html = render_document(&config, &tree, &NavTree::new(&config, &tree), page).unwrap();
+    assert!(html.contains("<div class=\"floating-menu\" id=\"toc\">"));
+    assert!(html.contains("<li><a href=\"#places\">Places</a></li>"));
+
+    let config = Config {
+        toc_min_headings: 4,
+        ..Config::none()
+    };
+    let html = render_document(&config, &tree, &NavTree::new(&config, &tree), page).unwrap();
+    assert!(!html.contains("id=\"toc\""));
+}
+
+#[test]
+fn test_site_settings() {
+    let mut tree = WikiTree::new();
+    tree.add_doc(WikiTree::ROOT, "city.html", "City", Doc::empty());
+    let page = tree.add_doc(
+        WikiTree::ROOT,
+        "page.html",
+        "Page",
+        Doc::from(vec![
+            Node::link("City", ""),
+            Node::link("Elsewhere", "https://example.com/"),
+        ]),
+    );
+    let config = Config {
+        empty_links: true,
+        site_title: Some(String::from("The Realm")),
+        base_url: String::from("/realm"),
+        ..Config::none()
+    };
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap();
+    assert!(html.contains("<title>Page - The Realm</title>"));
+    assert!(html.contains("<a href=\"/realm/city.html\">City</a>"));
+    assert!(html.contains("<a href=\"https://example.com/\">Elsewhere</a>"));
+}
+
+#[test]
+fn test_protect_media() {
+    let mut tree = WikiTree::new();
+    let secret = tree.add_media(WikiTree::ROOT, "lair.png", "Lair", "lair.png");
+    let public = tree.add_media(WikiTree::ROOT, "town.png", "Town", "town.png");
+    let dir = tree.add_dir(WikiTree::ROOT, "dm");
+    tree.add_index(
+        dir,
+        "index.html",
+        "DM",
+        Doc::from(vec![Node::text("Notes").with_tags(vec!["dm".into()])]),
+    );
+    let notes = tree.add_media(dir, "notes.png", "Notes", "notes.png");
+    let page = tree.add_doc(
+        WikiTree::ROOT,
+        "page.html",
+        "Page",
+        crate::parse::parse_document(concat!(
+            "![Town](/town.png)\n\n",
+            "#dm\n",
+            "## Lair\n",
+            "![Lair]()\n",
+            "![Town]()\n",
+            "## Town\n",
+            "![[notes.png]]\n",
+        )),
+    );
+
+    let config = Config {
+        empty_links: true,
+        ..dm_config()
+    };
+    protect_media(&config, &mut tree);
+    let dm: Vec<Tag> = vec!["dm".into()];
+    assert_eq!(tree.get(secret).unwrap().tags(), dm);
+    assert_eq!(tree.get(public).unwrap().tags(), Vec::<Tag>::new());
+    assert_eq!(tree.get(notes).unwrap().tags(), dm);
+
+    let html = render_nodes_only(
+        &config,
+        &tree,
+        tree.get(page).unwrap(),
+        &[Node::image("Lair", ""), Node::image("Town", "")],
+        true,
+    );
+    assert_eq!(
+        html,
+        concat!(
+            "<img data-src=\"/lair.png\" alt=\"Lair\" class=\"secret-media\" tags=\"dm\">",
+            "<img src=\"/town.png\" alt=\"Town\">",
+        )
+    );
+}
+
+#[test]
+fn test_protect_media_with_different_tags() {
+    let mut tree = WikiTree::new();
+    let map = tree.add_media(WikiTree::ROOT, "map.png", "Map", "map.png");
+    let page = tree.add_doc(
+        WikiTree::ROOT,
+        "page.html",
+        "Page",
+        crate::parse::parse_document(concat!(
+            "#dm\n",
+            "## Lair\n",
+            "![Map](/map.png)\n",
+            "#paladin\n",
+            "## Temple\n",
+            "![Map](/map.png)\n",
+        )),
+    );
+
+    let mut config = dm_config();
+    config
+        .tag_keys
+        .insert("paladin".into(), String::from("oath"));
+    protect_media(&config, &mut tree);
+
+    // Readers of either section can see it, rather than only those with both
+    // keys.
+    let media = tree.get(map).unwrap();
+    let either = vec![vec![Tag::from("dm"), Tag::from("paladin")]];
+    assert_eq!(media.tag_groups(), either);
+    assert_eq!(config.group_access(media.tag_groups()), Some(either));
+
+    let html = render_nodes_only(
+        &config,
+        &tree,
+        tree.get(page).unwrap(),
+        &[Node::image("Map", "/map.png")],
+        true,
+    );
+    assert_eq!(
+        html,
+        "<img data-src=\"/map.png\" alt=\"Map\" class=\"secret-media\" tags=\"dm;paladin\">"
+    );
+}
+
+#[test]
+fn test_protect_relative_media() {
+    let mut tree = WikiTree::new();
+    let maps = tree.add_dir(WikiTree::ROOT, "maps");
+    let lair = tree.add_media(maps, "lair.png", "Lair", "lair.png");
+    let places = tree.add_dir(WikiTree::ROOT, "places");
+    let page = tree.add_doc(
+        places,
+        "vault.html",
+        "Vault",
+        crate::parse::parse_document("#dm\n## Lair\n![Lair](../maps/lair.png)\n"),
+    );
+
+    let config = dm_config();
+    protect_media(&config, &mut tree);
+    assert_eq!(tree.get(lair).unwrap().tags(), vec![Tag::from("dm")]);
+
+    let html = render_nodes_only(
+        &config,
+        &tree,
+        tree.get(page).unwrap(),
+        &[Node::image("Lair", "../maps/lair.png")],
+        true,
+    );
+    assert_eq!(
+        html,
+        "<img data-src=\"../maps/lair.png\" alt=\"Lair\" class=\"secret-media\" tags=\"dm\">"
+    );
+}
+
+#[test]
+fn test_protect_pages() {
+    let dm: Vec<Tag> = vec!["dm".into()];
+    let mut tree = WikiTree::new();
+    let lair = tree.add_doc(
+        WikiTree::ROOT,
+        "lair.html",
+        "Secret Lair",
+        Doc::from(vec![Node::text("Traps").with_tags(dm.clone())]),
+    );
+    let town = tree.add_doc(WikiTree::ROOT, "town.html", "Town", Doc::empty());
+    let vault = tree.add_dir(WikiTree::ROOT, "vault");
+    tree.add_index(
+        vault,
+        "index.html",
+        "Vault",
+        Doc::from(vec![Node::text("Gold").with_tags(dm.clone())]),
+    );
+    let ledger = tree.add_doc(vault, "ledger.html", "Ledger", Doc::empty());
+
+    let config = Config {
+        add_breadcrumbs: true,
+        page_heading: true,
+        ..dm_config()
+    };
+    protect_pages(&config, &mut tree);
+
+    let lair = tree.get(lair).unwrap();
+    assert!(!lair.url().contains("lair"));
+    assert!(lair.url().ends_with(".html"));
+    assert_eq!(tree.get(town).unwrap().url(), "/town.html");
+    let vault = tree.get(vault).unwrap();
+    assert!(!vault.url().contains("vault"));
+    assert_eq!(
+        tree.get(ledger).unwrap().url(),
+        format!("{}/ledger.html", vault.url())
+    );
+
+    let html = render_document(&config, &tree, &NavTree::new(&config, &tree), lair).unwrap();
+    assert!(html.contains("<title>Protected page</title>"));
+    assert!(!html.contains("Secret Lair"));
+
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(ledger).unwrap(),
+    )
+    .unwrap();
+    assert!(!html.contains("Vault"));
+
+    let root = tree.get(WikiTree::ROOT).unwrap();
+    let index = create_index(&tree, root, &tree.children(WikiTree::ROOT));
+    let html = render_nodes_only(&config, &tree, root, index.nodes(), false);
+    assert!(html.contains("Town"));
+    assert!(!html.contains("Secret Lair"));
+    assert!(!html.contains("Vault"));
+}
+
+#[test]
+fn test_encrypted_section() {
+    let config = dm_config();
+    let (tree, page) = make_file(Doc::empty(), "Page");
+    let nodes = vec![
+        Node::heading(2, vec![Node::text("Lair")]).with_tags(vec!["dm".into()]),
+        Node::text("Traps"),
+        Node::text("Treasure"),
+        Node::heading(2, vec![Node::text("Town")]),
+    ];
+    let html = render_nodes_only(&config, &tree, tree.get(page).unwrap(), &nodes, false);
+    assert!(html.contains("Town"));
+
+    let plaintext = decrypt_secret(&config, &html, &[("dm", "password")]).unwrap();
+    assert!(plaintext.contains("Lair"));
+    assert!(plaintext.contains("Traps"));
+    assert!(plaintext.contains("Treasure"));
+    assert!(!plaintext.contains("Town"));
+}
+
+#[test]
+fn test_tag_policies() {
+    let keys = [("dm", "k1"), ("paladin", "k2"), ("rogue", "k3")];
+    let mut config = Config {
+        tag_keys: keys
+            .iter()
+            .map(|(tag, key)| ((*tag).into(), key.to_string()))
+            .collect(),
+        tag_unlocks: vec![("dm".into(), vec!["paladin".into(), "rogue".into()])],
+        ..Config::none()
+    };
+    let (tree, page) = make_file(Doc::empty(), "Page");
+    let render = |config: &Config, tags: &[&str]| {
+        let node = Node::text("Secret").with_tags(tags.iter().map(|t| (*t).into()).collect());
+        render_nodes_only(config, &tree, tree.get(page).unwrap(), &[node], false)
+    };
+    let readable = |config: &Config, html: &str, tags: &[&str]| {
+        let keys: Vec<(&str, &str)> = keys.into_iter().filter(|(t, _)| tags.contains(t)).collect();
+        decrypt_secret(config, html, &keys).is_some_and(|text| text.contains("Secret"))
+    };
+
+    // The dm key unlocks paladin content.
+    let html = render(&config, &["paladin"]);
+    assert!(readable(&config, &html, &["paladin"]));
+    assert!(readable(&config, &html, &["dm"]));
+    assert!(!readable(&config, &html, &["rogue"]));
+
+    // By default, a key is needed for each tag.
+    let html = render(&config, &["paladin", "rogue"]);
+    assert!(readable(&config, &html, &["paladin", "rogue"]));
+    assert!(readable(&config, &html, &["dm"]));
+    assert!(!readable(&config, &html, &["paladin"]));
+
+    config.tag_match = TagMatch::Any;
+    let html = render(&config, &["paladin", "rogue"]);
+    assert!(readable(&config, &html, &["paladin"]));
+    assert!(readable(&config, &html, &["rogue"]));
+    assert!(!readable(&config, &html, &[]));
+}
+
+#[test]
+fn test_search_index() {
+    let config = Config {
+        search: true,
+        ..dm_config()
+    };
+    let mut tree = WikiTree::new();
+    let town = crate::parse::parse_document(
+        "# Town\n\n## Market Square\n\nApples\n\n#dm\n## Cellar\n\nSmugglers\n\n## Docks\n\nShips",
+    );
+    let town = tree.add_doc(WikiTree::ROOT, "town.html", "Town", town);
+    let lair = Doc::from(vec![Node::text("Dragon").with_tags(vec!["dm".into()])]);
+    tree.add_doc(WikiTree::ROOT, "lair.html", "Secret Lair", lair);
+    protect_pages(&config, &mut tree);
+
+    let index = search_index(&config, &tree);
+    let (public, sealed) = index.split_once("\"sealed\"").unwrap();
+    assert!(public.contains("\"u\":\"/town.html\",\"t\":\"Town\""));
+    assert!(public.contains("[\"market-square\",\"Market Square\"]"));
+    assert!(public.contains("apples"));
+    assert!(public.contains("ships"));
+    assert!(!index.contains("smugglers"));
+    assert!(!index.contains("Cellar"));
+    assert!(!index.contains("Lair"));
+    assert!(!index.contains("dragon"));
+
+    // Both encrypted parts need only the dm key, so are sealed together.
+    assert_eq!(sealed.matches("\"keys\"").count(), 1);
+    let field = |name: &str| {
+        let (_, rest) = sealed.split_once(&format!("\"{name}\":\"")).unwrap();
+        rest.split_once('"').unwrap().0
+    };
+    let sealed = aes::Sealed {
+        nonce: b64_decode(field("nonce")).unwrap().try_into().unwrap(),
+        ciphertext: b64_decode(field("data")).unwrap(),
+        keys: field("keys").to_string(),
+    };
+    let keys = HashMap::from([("dm", "password")]);
+    let plaintext = sealed
+        .open(&keys, &config.kdf_salt, config.kdf_iterations)
+        .map(|bytes| String::from_utf8(bytes).unwrap())
+        .unwrap();
+    assert!(plaintext.contains("\"u\":\"/town.html\""));
+    assert!(plaintext.contains("[\"cellar\",\"Cellar\"]"));
+    assert!(plaintext.contains("smugglers"));
+    assert!(plaintext.contains("\"t\":\"Secret Lair\""));
+    assert!(plaintext.contains("dragon"));
+    assert!(!plaintext.contains("ships"));
+
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(town).unwrap(),
+    )
+    .unwrap();
+    assert!(html.contains("<script src=\"/search-index.js\">"));
+    assert!(html.contains("id=\"search-menu\""));
+}
+
+#[test]
+fn test_backlinks() {
+    let config = dm_config();
+    let mut tree = WikiTree::new();
+    let town = crate::parse::parse_document("# Town\n\nA walled town.");
+    let town = tree.add_doc(WikiTree::ROOT, "town.html", "Town", town);
+    let tavern = crate::parse::parse_document(
+        "# Tavern\n\nAle is cheap. The [[Town]] is nearby! Rooms are free.\n\n* [[Town]]\n\nBack to [Town](town.html).",
+    );
+    let tavern = tree.add_doc(WikiTree::ROOT, "tavern.html", "Tavern", tavern);
+    let inn = crate::parse::parse_document(
+        "# Inn\n\nCosy.\n\n#dm\n## Secrets\n\nThe [[Town]] guards are bribed.",
+    );
+    let inn = tree.add_doc(WikiTree::ROOT, "inn.html", "Inn", inn);
+    protect_pages(&config, &mut tree);
+    link_pages(&config, &mut tree);
+
+    // Several public links from a page are only listed once.
+    let backlinks = tree.backlinks(town);
+    assert_eq!(backlinks.len(), 2);
+    assert_eq!(backlinks[0].from, tavern);
+    assert_eq!(backlinks[0].context, "The Town is nearby!");
+    assert!(backlinks[0].access.is_empty());
+    assert_eq!(backlinks[1].from, inn);
+    assert_eq!(backlinks[1].context, "The Town guards are bribed.");
+    assert_eq!(backlinks[1].access, vec![vec![Tag::from("dm")]]);
+    assert!(tree.backlinks(tavern).is_empty());
+
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(town).unwrap(),
+    )
+    .unwrap();
+    assert!(html.contains("<h2>Linked from</h2>"));
+    assert!(html.contains("<a href=\"/tavern.html\">Tavern</a>: The Town is nearby!"));
+    assert!(!html.contains("Inn"));
+    assert!(!html.contains("bribed"));
+
+    let secret = decrypt_secret(&config, &html, &[("dm", "password")]).unwrap();
+    assert!(secret.contains("<a href=\"/inn.html\">Inn</a>: The Town guards are bribed."));
+    assert!(!secret.contains("Linked from"));
+}
+
+#[test]
+fn test_backlink_context() {
+    let config = Config {
+        empty_links: true,
+        ..dm_config()
+    };
+    let mut tree = WikiTree::new();
+    let castle = tree.add_doc(WikiTree::ROOT, "castle.html", "Castle", Doc::empty());
+    let king = crate::parse::parse_document(
+        "# King\n\nThe king lives in [Castle]()\n#dm and is secretly a vampire.",
+    );
+    tree.add_doc(WikiTree::ROOT, "king.html", "King", king);
+    let queen = crate::parse::parse_document(
+        "# Queen\n\nShe visits the [[Castle]], often. Rarely *alone*.\n\nOr the [[King]].",
+    );
+    tree.add_doc(WikiTree::ROOT, "queen.html", "Queen", queen);
+    link_pages(&config, &mut tree);
+
+    // Tagged text next to a public link is encrypted, so isn't part of it.
+    let backlinks = tree.backlinks(castle);
+    assert_eq!(backlinks[0].context, "The king lives in Castle");
+    assert_eq!(backlinks[1].context, "She visits the Castle, often.");
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(castle).unwrap(),
+    )
+    .unwrap();
+    assert!(!html.contains("vampire"));
+}
+
+#[test]
+fn test_check_tree() {
+    let config = Config::none();
+    let mut tree = WikiTree::new();
+    let index = crate::parse::parse_document(
+        "# Home\n\n[[Town#Market]] [[Town#Docks]] [[Nowhere]]\n\n[Town](town.html#market) \
+        [Away](https://example.com) [Lost](/lost.html) [Top](#home)\n\n![Map](map.png) ![Gone]()",
+    );
+    tree.add_index(WikiTree::ROOT, INDEX_FILE, "Home", index);
+    let town = crate::parse::parse_document("# Town\n\n## Market\n\n[Home](./)");
+    tree.add_doc(WikiTree::ROOT, "town.html", "Town", town);
+    let places = tree.add_dir(WikiTree::ROOT, "places");
+    tree.add_doc(places, "town.html", "Town", Doc::empty());
+    tree.add_doc(places, "cave.html", "Cave", Doc::empty());
+    tree.add_media(places, "map.png", "Map", "map.png");
+    // Generated indexes are titled after their directories, so aren't
+    // duplicates of pages.
+    let cave = tree.add_dir(places, "cave");
+    tree.add_generated_index(cave, INDEX_FILE, "Cave", Doc::empty());
+    link_pages(&config, &mut tree);
+
+    let report = check_tree(&config, &tree);
+    assert_eq!(
+        report.broken,
+        vec![
+            "/index.html: no section \"Docks\" of /town.html for link \"Town > Docks\"",
+            "/index.html: no page found for link \"Nowhere\"",
+            "/index.html: no page at /lost.html for link \"Lost\"",
+            "/index.html: no page at map.png for image \"Map\"",
+            "/index.html: image \"Gone\" has no URL",
+        ]
+    );
+    assert_eq!(
+        report.orphans,
+        vec![
+            "/places/town.html: no other page links to it",
+            "/places/cave.html: no other page links to it",
+        ]
+    );
+    assert_eq!(
+        report.duplicates,
+        vec!["\"Town\" is the title of /town.html, /places/town.html"]
+    );
+}
+
+#[test]
+fn test_tag_key_menu() {
+    let config = Config {
+        tag_keys: HashMap::from([
+            ("rogue".into(), String::from("k1")),
+            ("dm".into(), String::from("k2")),
+            ("paladin".into(), String::from("k3")),
+        ]),
+        ..Config::none()
+    };
+    let (tree, page) = make_file(Doc::empty(), "Page");
+    let html = render_document(
+        &config,
+        &tree,
+        &NavTree::new(&config, &tree),
+        tree.get(page).unwrap(),
+    )
+    .unwrap();
+
+    // Tags are listed in the same order every build.
+    let label = |tag: &str| html.find(&format!("class=\"tag-keys-label\">{tag}<"));
+    assert!(label("dm").is_some());
+    assert!(label("dm") < label("paladin"));
+    assert!(label("paladin") < label("rogue"));
+
+    // The script checks keys against the value the tests are sealed with.
+    assert!(html.contains(&format!("const TEST_VALUE = \"{KEY_TEST_VALUE}\";")));
+}
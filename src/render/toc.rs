@@ -0,0 +1,129 @@
+use crate::{
+    model::{Doc, El, Node},
+    render::css::{floating_menu, title, with_class, with_id},
+};
+
+use super::{RenderState, encryption_access};
+
+const TOC_TITLE: &str = "Contents";
+
+struct Entry {
+    level: u8,
+    text: String,
+    id: String,
+}
+
+/// Collect headings with anchors from nodes, in document order. Encrypted
+/// headings are omitted along with the rest of their section, so that their
+/// text isn't leaked into the table of contents.
+fn collect_entries(state: &RenderState, nodes: &[&Node], entries: &mut Vec<Entry>) {
+    let mut i = 0;
+    while i < nodes.len() {
+        let node = nodes[i];
+        i += 1;
+
+        if encryption_access(state, node.tags()).is_some() {
+            if let El::Heading(level, _) = node.el() {
+                i += nodes[i..]
+                    .iter()
+                    .position(|n| matches!(n.el(), El::Heading(l, _) if l == level))
+                    .unwrap_or(nodes.len() - i);
+            }
+            continue;
+        }
+
+        if let El::Heading(level, _) = node.el()
+            && let Some(id) = node.attrs().get("id")
+        {
+            entries.push(Entry {
+                level: *level,
+                text: node.plain_text(),
+                id: id.clone(),
+            });
+        } else {
+            collect_entries(state, &node.children(), entries);
+        }
+    }
+}
+
+fn toc_entries(state: &RenderState, doc: &Doc) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    if encryption_access(state, &doc.meta().tags()).is_none() {
+        let nodes: Vec<&Node> = doc.nodes().iter().collect();
+        collect_entries(state, &nodes, &mut entries);
+    }
+    entries
+}
+
+/// Nest entries under the nearest preceding entry of a lower level.
+fn make_items(entries: &[Entry]) -> Vec<Node> {
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+        let entry = &entries[i];
+        let end = entries[(i + 1)..]
+            .iter()
+            .position(|e| e.level <= entry.level)
+            .map(|p| i + 1 + p)
+            .unwrap_or(entries.len());
+
+        let mut children = vec![Node::link(&entry.text, &format!("#{}", entry.id))];
+        let subitems = make_items(&entries[(i + 1)..end]);
+        if !subitems.is_empty() {
+            children.push(Node::list(subitems));
+        }
+        items.push(Node::item(children));
+        i = end;
+    }
+    items
+}
+
+fn toc_content(entries: &[Entry]) -> Vec<Node> {
+    vec![
+        title(Node::span(vec![Node::text(TOC_TITLE)])),
+        Node::list(make_items(entries)),
+    ]
+}
+
+fn has_toc_marker(doc: &Doc) -> bool {
+    let mut found = false;
+    for node in doc.nodes() {
+        node.walk(&mut |n| found |= matches!(n.el(), El::Toc));
+    }
+    found
+}
+
+/// Table of contents for the current page, to be placed where a `[TOC]`
+/// marker appears in the document.
+pub fn make_toc(state: &RenderState) -> Node {
+    const TOC_CLASS: &str = "toc";
+
+    let Some(doc) = state.page.document() else {
+        return Node::empty();
+    };
+
+    let entries = toc_entries(state, doc);
+    if entries.is_empty() {
+        Node::empty()
+    } else {
+        with_class(Node::block("nav", toc_content(&entries)), TOC_CLASS)
+    }
+}
+
+/// Floating table of contents, added to pages without a `[TOC]` marker which
+/// have at least as many headings as the configured threshold.
+pub fn make_toc_menu(state: &RenderState, doc: &Doc) -> Node {
+    const TOC_ID: &str = "toc";
+
+    let min_headings = state.config.toc_min_headings;
+    if min_headings == 0 || has_toc_marker(doc) {
+        return Node::empty();
+    }
+
+    let entries = toc_entries(state, doc);
+    if entries.len() < min_headings {
+        Node::empty()
+    } else {
+        with_id(floating_menu(Node::div(toc_content(&entries))), TOC_ID)
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config::{CONFIG_FILE, Config},
+    log,
+    model::{WikiPage, WikiTree},
+    parallel,
+    render::{self, INDEX_FILE, SEARCH_INDEX_FILE},
+    watch::Watcher,
+};
+
+#[cfg(test)]
+mod test;
+
+/// Script added to each page to reload it when the site is rebuilt.
+const RELOAD_SCRIPT: &str = "/.mdwiki/reload.js";
+
+/// Stream of events sent to the reload script, each the ID of the latest
+/// build. Hidden files aren't part of the wiki, so these can't clash with it.
+const RELOAD_EVENTS: &str = "/.mdwiki/events";
+
+/// Interval at which comments are sent on the event stream, to find out when
+/// the browser has gone away.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: status.as_bytes().to_vec(),
+        }
+    }
+}
+
+fn content_type(url: &str) -> &'static str {
+    let ext = url.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+    match ext.to_lowercase().as_str() {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Decode `%XX` escapes in a URL path.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| path.get((i + 1)..(i + 3)))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escape {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Add the reload script to the head of a rendered page.
+fn add_reload_script(config: &Config, html: String) -> String {
+    let script = format!(
+        "<script src=\"{}{RELOAD_SCRIPT}\"></script>\n</head>",
+        config.base_url
+    );
+    html.replacen("</head>", &script, 1)
+}
+
+/// Every file of the rendered site by URL, along with an ID for the build
+/// which changes each time it's rebuilt.
+struct Site {
+    build: String,
+    files: HashMap<String, (&'static str, Vec<u8>)>,
+}
+
+impl Site {
+    fn render(config: &Config, tree: &WikiTree) -> Self {
+        let pages: Vec<&WikiPage> = tree
+            .pages()
+            .iter()
+            .filter(|page| page.is_doc() || page.is_index() || page.is_media())
+            .collect();
+        let nav = render::NavTree::new(config, tree);
+        let bodies = parallel::map(&pages, |page| {
+            if page.is_media() {
+                return render::media_contents(config, page)
+                    .map_err(log::error)
+                    .ok();
+            }
+            match render::render_document(config, tree, &nav, page) {
+                Ok(html) => Some(add_reload_script(config, html).into_bytes()),
+                Err(()) => {
+                    log::error(format!("Failed to render {}", page.url()));
+                    None
+                }
+            }
+        });
+
+        let mut files = HashMap::new();
+        for (page, body) in pages.iter().zip(bodies) {
+            if let Some(body) = body {
+                files.insert(page.url().to_string(), (content_type(page.url()), body));
+            }
+        }
+
+        if config.search {
+            let url = format!("/{SEARCH_INDEX_FILE}");
+            let index = render::search_index(config, tree).into_bytes();
+            files.insert(url, (content_type(SEARCH_INDEX_FILE), index));
+        }
+        let script = include_str!("reload.js").as_bytes().to_vec();
+        files.insert(
+            RELOAD_SCRIPT.to_string(),
+            (content_type(RELOAD_SCRIPT), script),
+        );
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            build: format!("{:x}", time.as_nanos()),
+            files,
+        }
+    }
+
+    fn respond_to(&self, config: &Config, path: &str) -> Response {
+        let path = percent_decode(path.split(['?', '#']).next().unwrap_or(""));
+        let Some(path) = path.strip_prefix(&config.base_url) else {
+            return Response::error("404 Not Found");
+        };
+        let url = if path.ends_with('/') {
+            format!("{path}{INDEX_FILE}")
+        } else {
+            path.to_string()
+        };
+
+        match self.files.get(&url) {
+            Some((content_type, body)) => Response::ok(content_type, body.clone()),
+            None => Response::error("404 Not Found"),
+        }
+    }
+}
+
+/// The latest build of the site, shared between the connections and the
+/// thread rebuilding it.
+struct Server<'a> {
+    config: &'a Config,
+    site: Mutex<Arc<Site>>,
+    rebuilt: Condvar,
+}
+
+impl Server<'_> {
+    fn site(&self) -> Arc<Site> {
+        Arc::clone(&self.site.lock().unwrap())
+    }
+
+    fn update(&self, site: Site) {
+        *self.site.lock().unwrap() = Arc::new(site);
+        self.rebuilt.notify_all();
+    }
+
+    /// Send the ID of each build to the reload script as server-sent events,
+    /// until the browser closes the connection.
+    fn stream_reloads(&self, mut stream: TcpStream) {
+        let header = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+        let mut build = self.site().build.clone();
+        let mut result = stream.write_all(format!("{header}data: {build}\n\n").as_bytes());
+        while result.is_ok() {
+            let site = self.site.lock().unwrap();
+            let (site, _) = self
+                .rebuilt
+                .wait_timeout_while(site, KEEPALIVE_INTERVAL, |site| site.build == build)
+                .unwrap();
+            let event = if site.build == build {
+                String::from(": keepalive\n\n")
+            } else {
+                build = site.build.clone();
+                format!("data: {build}\n\n")
+            };
+            drop(site);
+            result = stream.write_all(event.as_bytes());
+        }
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) {
+        let mut request_line = String::new();
+        if BufReader::new(&stream)
+            .read_line(&mut request_line)
+            .is_err()
+        {
+            return;
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or("/"));
+        let events = format!("{}{RELOAD_EVENTS}", self.config.base_url);
+        if method == "GET" && path == events {
+            self.stream_reloads(stream);
+            return;
+        }
+
+        let response = match method {
+            "GET" | "HEAD" => self.site().respond_to(self.config, path),
+            _ => Response::error("405 Method Not Allowed"),
+        };
+        log::debug(format!("{method} {path} {}", response.status));
+
+        let header = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        );
+        let mut result = stream.write_all(header.as_bytes());
+        if method != "HEAD" {
+            result = result.and_then(|_| stream.write_all(&response.body));
+        }
+        if let Err(e) = result {
+            log::debug(format!("Failed to send response: {e}"));
+        }
+    }
+
+    /// Rebuild the site whenever files in the wiki change, keeping the last
+    /// build if loading the wiki fails.
+    fn watch(&self, src: &Path, load: &impl Fn() -> Result<WikiTree, String>) {
+        let mut watcher = Watcher::new(src);
+        loop {
+            let changed = watcher.wait();
+            match changed.as_slice() {
+                [path] => log::info(format!("{} changed, rebuilding.", path.display())),
+                paths => {
+                    for path in paths {
+                        log::debug(format!("Changed: {}", path.display()));
+                    }
+                    log::info(format!("{} files changed, rebuilding.", paths.len()));
+                }
+            }
+            let is_config = |path: &&PathBuf| path.file_name().is_some_and(|n| n == CONFIG_FILE);
+            if let Some(path) = changed.iter().find(is_config) {
+                log::warning(format!(
+                    "Changes to {} take effect when restarted.",
+                    path.display()
+                ));
+            }
+
+            match load() {
+                Ok(tree) => {
+                    self.update(Site::render(self.config, &tree));
+                    log::info("Rebuilt the wiki.");
+                }
+                Err(e) => log::error(e),
+            }
+        }
+    }
+}
+
+/// Serve the wiki in `src` over HTTP on localhost from memory, rendering it
+/// from `tree`. The site is rebuilt with the tree from `load` whenever files
+/// in `src` change, and pages open in the browser reload. Only returns if the
+/// server can't be started.
+pub fn serve(
+    config: &Config,
+    src: &Path,
+    port: u16,
+    tree: WikiTree,
+    load: impl Fn() -> Result<WikiTree, String> + Sync,
+) -> Result<(), String> {
+    let server = Server {
+        config,
+        site: Mutex::new(Arc::new(Site::render(config, &tree))),
+        rebuilt: Condvar::new(),
+    };
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+    log::info(format!(
+        "Serving on http://127.0.0.1:{port}{}/",
+        config.base_url
+    ));
+
+    let server = &server;
+    std::thread::scope(|scope| {
+        scope.spawn(|| server.watch(src, &load));
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    scope.spawn(move || server.handle_connection(stream));
+                }
+                Err(e) => log::warning(format!("Failed to accept connection: {e}")),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,17 @@
+/**
+ * This script is added to pages by `mdwiki serve`, and reloads the page when
+ * the wiki is rebuilt. The server sends the ID of the latest build when the
+ * connection opens and again after each rebuild, including after the server
+ * restarts.
+ */
+(() => {
+    const url = document.currentScript.src.replace(/reload\.js$/, "events");
+    const events = new EventSource(url);
+    let build = null;
+    events.onmessage = event => {
+        if (build !== null && event.data !== build) {
+            window.location.reload();
+        }
+        build = event.data;
+    };
+})();
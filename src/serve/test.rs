@@ -0,0 +1,54 @@
+use crate::{
+    config::Config,
+    model::{Doc, Node, WikiTree},
+    render::INDEX_FILE,
+};
+
+use super::{RELOAD_SCRIPT, Site};
+
+fn site(config: &Config) -> Site {
+    let mut tree = WikiTree::new();
+    let index = Doc::from(vec![Node::text("Home")]);
+    tree.add_index(WikiTree::ROOT, INDEX_FILE, "Home", index);
+    let places = tree.add_dir(WikiTree::ROOT, "places");
+    let town = Doc::from(vec![Node::text("Town")]);
+    tree.add_doc(places, "town.html", "Town", town);
+    Site::render(config, &tree)
+}
+
+fn body(site: &Site, config: &Config, path: &str) -> Option<String> {
+    let response = site.respond_to(config, path);
+    (response.status == "200 OK").then(|| String::from_utf8(response.body).unwrap())
+}
+
+#[test]
+fn test_serve_from_memory() {
+    let config = Config {
+        search: true,
+        ..Config::none()
+    };
+    let site = site(&config);
+
+    let index = body(&site, &config, "/").unwrap();
+    assert!(index.contains("<script src=\"/.mdwiki/reload.js\"></script>\n</head>"));
+    assert_eq!(body(&site, &config, "/index.html"), Some(index));
+    assert!(body(&site, &config, "/places/town.html?q=1").is_some());
+    assert!(body(&site, &config, "/places/").is_none());
+    assert!(body(&site, &config, "/search-index.js").is_some());
+    assert!(body(&site, &config, RELOAD_SCRIPT).is_some());
+}
+
+#[test]
+fn test_serve_base_url() {
+    let config = Config {
+        base_url: String::from("/wiki"),
+        ..Config::none()
+    };
+    let site = site(&config);
+
+    let town = body(&site, &config, "/wiki/places/town.html").unwrap();
+    assert!(town.contains("<script src=\"/wiki/.mdwiki/reload.js\"></script>"));
+    assert!(body(&site, &config, "/wiki/.mdwiki/reload.js").is_some());
+    assert!(body(&site, &config, "/places/town.html").is_none());
+    assert!(body(&site, &config, "/search-index.js").is_none());
+}
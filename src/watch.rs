@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::log;
+
+/// How often the files being watched are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long files must go unchanged before changes are reported, so that
+/// several files saved together are reported at once.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Modification time and size of each file.
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+/// Watches the files in a directory for changes by polling them, skipping
+/// hidden files and directories as the wiki does.
+pub struct Watcher {
+    root: PathBuf,
+    exclude: Vec<PathBuf>,
+    files: Snapshot,
+}
+
+impl Watcher {
+    pub fn new(root: &Path) -> Self {
+        let mut watcher = Self {
+            root: root.to_path_buf(),
+            exclude: Vec::new(),
+            files: Snapshot::new(),
+        };
+        watcher.files = watcher.scan();
+        watcher
+    }
+
+    /// Ignore changes within a directory, such as the output of a build
+    /// inside the wiki being watched.
+    pub fn exclude(mut self, dir: &Path) -> Self {
+        let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        self.exclude.push(dir);
+        self.files = self.scan();
+        self
+    }
+
+    fn is_excluded(&self, dir: &Path) -> bool {
+        !self.exclude.is_empty()
+            && std::fs::canonicalize(dir).is_ok_and(|dir| self.exclude.contains(&dir))
+    }
+
+    fn scan_dir(&self, dir: &Path, files: &mut Snapshot) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            log::debug(format!("Couldn't read directory: {}", dir.display()));
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                if !self.is_excluded(&path) {
+                    self.scan_dir(&path, files);
+                }
+            } else {
+                files.insert(path, (metadata.modified().ok(), metadata.len()));
+            }
+        }
+    }
+
+    fn scan(&self) -> Snapshot {
+        let mut files = Snapshot::new();
+        self.scan_dir(&self.root, &mut files);
+        files
+    }
+
+    /// Files added, changed or removed between two snapshots, sorted.
+    fn changes(old: &Snapshot, new: &Snapshot) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = new
+            .iter()
+            .filter(|(path, stat)| old.get(*path) != Some(stat))
+            .map(|(path, _)| path.clone())
+            .chain(old.keys().filter(|path| !new.contains_key(*path)).cloned())
+            .collect();
+        changed.sort();
+        changed
+    }
+
+    /// Block until files are added, changed or removed, returning their paths
+    /// once they have stopped changing.
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let mut files = self.scan();
+            if Self::changes(&self.files, &files).is_empty() {
+                continue;
+            }
+
+            loop {
+                std::thread::sleep(SETTLE_TIME);
+                let settled = self.scan();
+                if Self::changes(&files, &settled).is_empty() {
+                    break;
+                }
+                files = settled;
+            }
+
+            let changed = Self::changes(&self.files, &files);
+            self.files = files;
+            if !changed.is_empty() {
+                return changed;
+            }
+        }
+    }
+}